@@ -0,0 +1,8 @@
+CREATE TABLE user_game_achievement (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER NOT NULL,
+    api_name TEXT NOT NULL,
+    name TEXT NOT NULL,
+    unlock_date INTEGER,
+    UNIQUE (user_game_id, api_name)
+);
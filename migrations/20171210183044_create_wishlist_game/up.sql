@@ -0,0 +1,8 @@
+CREATE TABLE wishlist_game (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    game_id INTEGER NOT NULL,
+    added_date INTEGER NOT NULL,
+    user_game_id INTEGER,
+    UNIQUE (user_id, game_id)
+);
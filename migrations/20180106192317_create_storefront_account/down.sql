@@ -0,0 +1,15 @@
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    steam_id TEXT
+);
+INSERT INTO user_new (id, username, email, steam_id)
+SELECT user.id, user.username, user.email, (
+    SELECT account_id FROM storefront_account
+    WHERE storefront_account.user_id = user.id AND storefront_account.provider = 'steam'
+    ORDER BY storefront_account.id LIMIT 1
+) FROM user;
+DROP TABLE user;
+ALTER TABLE user_new RENAME to user;
+DROP TABLE storefront_account;
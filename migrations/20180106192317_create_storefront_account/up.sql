@@ -0,0 +1,16 @@
+CREATE TABLE storefront_account (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    UNIQUE (user_id, provider, account_id)
+);
+INSERT INTO storefront_account (user_id, provider, account_id) SELECT id, 'steam', steam_id FROM user WHERE steam_id IS NOT NULL AND steam_id != '';
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL
+);
+INSERT INTO user_new (id, username, email) SELECT id, username, email FROM user;
+DROP TABLE user;
+ALTER TABLE user_new RENAME to user;
@@ -0,0 +1 @@
+DROP TABLE user_game_history;
@@ -0,0 +1,8 @@
+CREATE TABLE user_game_history (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER NOT NULL,
+    play_state TEXT NOT NULL,
+    date INTEGER NOT NULL
+);
+INSERT INTO user_game_history (user_game_id, play_state, date)
+    SELECT id, play_state, acquisition_date FROM user_game;
@@ -0,0 +1,10 @@
+DROP TABLE user_game_note;
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL
+);
+INSERT INTO user_new (id, username, email)
+SELECT id, username, email FROM user;
+DROP TABLE user;
+ALTER TABLE user_new RENAME to user;
@@ -0,0 +1,8 @@
+CREATE TABLE user_game_note (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER UNIQUE NOT NULL,
+    notes TEXT NOT NULL,
+    review TEXT,
+    rating INTEGER
+);
+ALTER TABLE user ADD COLUMN rating_scale INTEGER NOT NULL DEFAULT 10;
@@ -0,0 +1,2 @@
+DROP TABLE game_list_entry;
+DROP TABLE game_list;
@@ -0,0 +1,14 @@
+CREATE TABLE game_list (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    UNIQUE (user_id, slug)
+);
+CREATE TABLE game_list_entry (
+    id INTEGER PRIMARY KEY,
+    game_list_id INTEGER NOT NULL,
+    user_game_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    UNIQUE (game_list_id, user_game_id)
+);
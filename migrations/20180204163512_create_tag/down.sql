@@ -0,0 +1,12 @@
+DROP TABLE user_game_tag;
+DROP TABLE tag;
+CREATE TABLE game_list_new (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    UNIQUE (user_id, slug)
+);
+INSERT INTO game_list_new SELECT id, user_id, name, slug FROM game_list;
+DROP TABLE game_list;
+ALTER TABLE game_list_new RENAME TO game_list;
@@ -0,0 +1,14 @@
+ALTER TABLE game_list ADD COLUMN ranked INTEGER NOT NULL DEFAULT 1;
+CREATE TABLE tag (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    UNIQUE (user_id, slug)
+);
+CREATE TABLE user_game_tag (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    UNIQUE (user_game_id, tag_id)
+);
@@ -0,0 +1,11 @@
+DROP TABLE up_next_entry;
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    rating_scale INTEGER NOT NULL DEFAULT 10
+);
+INSERT INTO user_new (id, username, email, rating_scale)
+SELECT id, username, email, rating_scale FROM user;
+DROP TABLE user;
+ALTER TABLE user_new RENAME to user;
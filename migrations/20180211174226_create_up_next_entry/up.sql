@@ -0,0 +1,10 @@
+CREATE TABLE up_next_entry (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    user_game_id INTEGER UNIQUE NOT NULL,
+    position INTEGER NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 2,
+    estimated_hours INTEGER,
+    now_playing INTEGER NOT NULL DEFAULT 0
+);
+ALTER TABLE user ADD COLUMN now_playing_limit INTEGER NOT NULL DEFAULT 2;
@@ -0,0 +1,12 @@
+DROP TABLE user_token;
+CREATE TABLE user_new (
+    id INTEGER PRIMARY KEY,
+    username TEXT UNIQUE NOT NULL,
+    email TEXT UNIQUE NOT NULL,
+    rating_scale INTEGER NOT NULL DEFAULT 10,
+    now_playing_limit INTEGER NOT NULL DEFAULT 2
+);
+INSERT INTO user_new (id, username, email, rating_scale, now_playing_limit)
+SELECT id, username, email, rating_scale, now_playing_limit FROM user;
+DROP TABLE user;
+ALTER TABLE user_new RENAME to user;
@@ -0,0 +1,10 @@
+CREATE TABLE user_token (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    purpose TEXT NOT NULL,
+    token_hash TEXT UNIQUE NOT NULL,
+    new_email TEXT,
+    expires_date INTEGER NOT NULL,
+    used_date INTEGER
+);
+ALTER TABLE user ADD COLUMN email_verified INTEGER NOT NULL DEFAULT 0;
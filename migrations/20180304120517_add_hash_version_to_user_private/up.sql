@@ -0,0 +1 @@
+ALTER TABLE user_private ADD COLUMN hash_version INTEGER NOT NULL DEFAULT 1;
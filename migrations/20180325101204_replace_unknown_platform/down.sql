@@ -0,0 +1 @@
+UPDATE user_game SET platform = 'unknown' WHERE platform = 'other';
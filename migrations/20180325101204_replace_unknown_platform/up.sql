@@ -0,0 +1 @@
+UPDATE user_game SET platform = 'other' WHERE platform = 'unknown';
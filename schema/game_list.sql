@@ -0,0 +1,8 @@
+CREATE TABLE game_list (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    ranked INTEGER NOT NULL DEFAULT 1,
+    UNIQUE (user_id, slug)
+);
@@ -0,0 +1,7 @@
+CREATE TABLE game_list_entry (
+    id INTEGER PRIMARY KEY,
+    game_list_id INTEGER NOT NULL,
+    user_game_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    UNIQUE (game_list_id, user_game_id)
+);
@@ -0,0 +1,10 @@
+CREATE TABLE goal (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    target INTEGER,
+    platform TEXT,
+    start_date INTEGER,
+    end_date INTEGER,
+    met_date INTEGER
+);
@@ -0,0 +1,8 @@
+CREATE TABLE login_attempt (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER,
+    username_or_email TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    date INTEGER NOT NULL,
+    succeeded INTEGER NOT NULL
+);
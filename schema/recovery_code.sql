@@ -0,0 +1,6 @@
+CREATE TABLE recovery_code (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_date INTEGER
+);
@@ -0,0 +1,7 @@
+CREATE TABLE storefront_account (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    provider TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    UNIQUE (user_id, provider, account_id)
+);
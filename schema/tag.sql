@@ -0,0 +1,7 @@
+CREATE TABLE tag (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    slug TEXT NOT NULL,
+    UNIQUE (user_id, slug)
+);
@@ -2,5 +2,7 @@ CREATE TABLE user (
     id INTEGER PRIMARY KEY,
     username TEXT UNIQUE NOT NULL,
     email TEXT UNIQUE NOT NULL,
-    steam_id TEXT
+    rating_scale INTEGER NOT NULL DEFAULT 10,
+    now_playing_limit INTEGER NOT NULL DEFAULT 2,
+    email_verified INTEGER NOT NULL DEFAULT 0
 );
@@ -0,0 +1,6 @@
+CREATE TABLE user_game_history (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER NOT NULL,
+    play_state TEXT NOT NULL,
+    date INTEGER NOT NULL
+);
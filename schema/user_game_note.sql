@@ -0,0 +1,7 @@
+CREATE TABLE user_game_note (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER UNIQUE NOT NULL,
+    notes TEXT NOT NULL,
+    review TEXT,
+    rating INTEGER
+);
@@ -0,0 +1,7 @@
+CREATE TABLE user_game_playtime (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER UNIQUE NOT NULL,
+    playtime_forever INTEGER NOT NULL,
+    playtime_2weeks INTEGER NOT NULL,
+    last_played_date INTEGER
+);
@@ -0,0 +1,6 @@
+CREATE TABLE user_game_tag (
+    id INTEGER PRIMARY KEY,
+    user_game_id INTEGER NOT NULL,
+    tag_id INTEGER NOT NULL,
+    UNIQUE (user_game_id, tag_id)
+);
@@ -2,5 +2,6 @@ CREATE TABLE user_private (
     id INTEGER PRIMARY KEY,
     user_id INTEGER UNIQUE,
     password_hash TEXT NOT NULL,
-    salt TEXT NOT NULL
+    salt TEXT NOT NULL,
+    hash_version INTEGER NOT NULL DEFAULT 1
 );
@@ -0,0 +1,271 @@
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::fs::File;
+use std::fs;
+use std::io;
+use std::os::raw::c_int;
+use std::path::Path;
+use std::path::PathBuf;
+use std::ptr;
+
+use diesel::connection::Connection;
+use diesel::connection::SimpleConnection;
+use diesel::expression::sql;
+use diesel::prelude::LoadDsl;
+use diesel::sqlite::SqliteConnection;
+use diesel::types::Text;
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use libsqlite3_sys as ffi;
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use model::DATABASE_PATH;
+
+// the tables this build of gamelog expects, which a restored database has to have
+pub(crate) const SCHEMA: &'static [&'static str] = &[
+    include_str!("../schema/game.sql"),
+    include_str!("../schema/game_list.sql"),
+    include_str!("../schema/game_list_entry.sql"),
+    include_str!("../schema/goal.sql"),
+    include_str!("../schema/login_attempt.sql"),
+    include_str!("../schema/recovery_code.sql"),
+    include_str!("../schema/storefront_account.sql"),
+    include_str!("../schema/tag.sql"),
+    include_str!("../schema/up_next_entry.sql"),
+    include_str!("../schema/user.sql"),
+    include_str!("../schema/user_game.sql"),
+    include_str!("../schema/user_game_achievement.sql"),
+    include_str!("../schema/user_game_history.sql"),
+    include_str!("../schema/user_game_note.sql"),
+    include_str!("../schema/user_game_playtime.sql"),
+    include_str!("../schema/user_game_tag.sql"),
+    include_str!("../schema/user_private.sql"),
+    include_str!("../schema/user_token.sql"),
+    include_str!("../schema/user_two_factor.sql"),
+    include_str!("../schema/wishlist_game.sql"),
+];
+
+const TABLE_COLUMNS_QUERY: &'static str = "
+    SELECT sqlite_master.name || '.' || columns.name
+    FROM sqlite_master, pragma_table_info(sqlite_master.name) AS columns
+    WHERE sqlite_master.type = 'table'
+";
+
+// copying a few pages at a time lets the webapp keep writing in between steps
+const PAGES_PER_STEP: c_int = 64;
+const BUSY_SLEEP_MILLISECONDS: c_int = 100;
+
+const BACKUP_PREFIX: &'static str = "gamelog-";
+
+/// A bare SQLite handle, since diesel doesn't expose the backup API.
+struct RawConnection {
+    handle: *mut ffi::sqlite3,
+}
+
+impl RawConnection {
+    fn open(path: &Path, flags: c_int) -> Result<RawConnection, Error> {
+        let path_string = CString::new(path.to_string_lossy().into_owned()).chain_err(|| "invalid database path")?;
+        let mut handle = ptr::null_mut();
+        let result = unsafe { ffi::sqlite3_open_v2(path_string.as_ptr(), &mut handle, flags, ptr::null()) };
+        // sqlite hands back a handle even when opening fails, so it still needs closing
+        let connection = RawConnection{handle: handle};
+        if result != ffi::SQLITE_OK {
+            return Err(format!("unable to open {}: {}", path.display(), connection.error_message()).into());
+        }
+        Ok(connection)
+    }
+
+    fn error_message(&self) -> String {
+        if self.handle.is_null() {
+            return "out of memory".to_string();
+        }
+        unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(self.handle)) }.to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for RawConnection {
+    fn drop(&mut self) {
+        unsafe { ffi::sqlite3_close(self.handle) };
+    }
+}
+
+/// Copies a live database with SQLite's online backup API, so the copy is consistent even if
+/// something writes to the source in the meantime.
+fn copy_database(source_path: &Path, destination_path: &Path) -> Result<(), Error> {
+    let source = RawConnection::open(source_path, ffi::SQLITE_OPEN_READONLY)?;
+    let destination = RawConnection::open(destination_path, ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE)?;
+
+    let main = CString::new("main").unwrap();
+    let backup = unsafe { ffi::sqlite3_backup_init(destination.handle, main.as_ptr(), source.handle, main.as_ptr()) };
+    if backup.is_null() {
+        return Err(format!("unable to start backup: {}", destination.error_message()).into());
+    }
+
+    loop {
+        match unsafe { ffi::sqlite3_backup_step(backup, PAGES_PER_STEP) } {
+            ffi::SQLITE_OK => continue,
+            ffi::SQLITE_DONE => break,
+            ffi::SQLITE_BUSY | ffi::SQLITE_LOCKED => unsafe { ffi::sqlite3_sleep(BUSY_SLEEP_MILLISECONDS); },
+            // finishing reports the error below
+            _ => break,
+        }
+    }
+
+    if unsafe { ffi::sqlite3_backup_finish(backup) } != ffi::SQLITE_OK {
+        return Err(format!("backup failed: {}", destination.error_message()).into());
+    }
+    Ok(())
+}
+
+fn is_gzipped(path: &Path) -> bool {
+    path.extension().map_or(false, |extension| extension == "gz")
+}
+
+fn gzip(source_path: &Path, destination_path: &Path) -> Result<(), Error> {
+    let mut source = File::open(source_path).chain_err(|| "unable to open uncompressed backup")?;
+    let destination = File::create(destination_path).chain_err(|| format!("unable to create {}", destination_path.display()))?;
+    let mut encoder = GzEncoder::new(destination, Compression::default());
+    io::copy(&mut source, &mut encoder).chain_err(|| "unable to compress backup")?;
+    encoder.finish().chain_err(|| "unable to compress backup")?;
+    Ok(())
+}
+
+fn gunzip(source_path: &Path, destination_path: &Path) -> Result<(), Error> {
+    let source = File::open(source_path).chain_err(|| format!("unable to open {}", source_path.display()))?;
+    let mut destination = File::create(destination_path).chain_err(|| "unable to create uncompressed copy")?;
+    io::copy(&mut GzDecoder::new(source), &mut destination).chain_err(|| "unable to decompress backup")?;
+    Ok(())
+}
+
+/// Where a backup to `destination` goes: into a timestamped file if it's a directory.
+fn get_backup_path(destination: &Path, compress: bool) -> PathBuf {
+    if !destination.is_dir() {
+        return destination.to_path_buf();
+    }
+    let timestamp = time::now_utc().strftime("%Y%m%dT%H%M%SZ").map(|timestamp| timestamp.to_string()).unwrap_or_default();
+    let extension = if compress { "db.gz" } else { "db" };
+    destination.join(format!("{}{}.{}", BACKUP_PREFIX, timestamp, extension))
+}
+
+/// Removes all but the newest `keep` timestamped backups in a directory.
+fn rotate_backups(directory: &Path, keep: usize) -> Result<(), Error> {
+    let entries = fs::read_dir(directory).chain_err(|| format!("unable to list {}", directory.display()))?;
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry.chain_err(|| "unable to list backups")?.path();
+        let is_backup = path.file_name().and_then(|name| name.to_str()).map_or(false, |name| {
+            name.starts_with(BACKUP_PREFIX) && (name.ends_with(".db") || name.ends_with(".db.gz"))
+        });
+        if is_backup {
+            backups.push(path);
+        }
+    }
+
+    // the timestamps sort the same way as the times they stand for
+    backups.sort();
+    let remove_count = backups.len().saturating_sub(keep);
+    for path in backups.into_iter().take(remove_count) {
+        fs::remove_file(&path).chain_err(|| format!("unable to remove old backup {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Backs up the database to a file, or to a timestamped file if `destination` is a directory,
+/// and keeps only the newest `keep` backups in that directory if given.
+///
+/// Compresses with gzip if asked to or if the destination file ends in `.gz`.
+pub fn backup(destination: &Path, compress: bool, keep: Option<usize>) -> Result<PathBuf, Error> {
+    let compress = compress || is_gzipped(destination);
+    let backup_path = get_backup_path(destination, compress);
+    if backup_path.exists() {
+        return Err(format!("{} already exists", backup_path.display()).into());
+    }
+
+    if compress {
+        let uncompressed_path = backup_path.with_extension("tmp");
+        let result = copy_database(Path::new(DATABASE_PATH), &uncompressed_path).and_then(
+            |_| check_database(&uncompressed_path)
+        ).and_then(
+            |_| gzip(&uncompressed_path, &backup_path)
+        );
+        let _ = fs::remove_file(&uncompressed_path);
+        result?;
+    } else {
+        copy_database(Path::new(DATABASE_PATH), &backup_path)?;
+        check_database(&backup_path)?;
+    }
+
+    if let Some(keep) = keep {
+        if destination.is_dir() {
+            rotate_backups(destination, keep)?;
+        }
+    }
+    Ok(backup_path)
+}
+
+fn get_table_columns(conn: &SqliteConnection) -> Result<BTreeSet<String>, Error> {
+    let columns: Vec<String> = sql::<Text>(TABLE_COLUMNS_QUERY).load(conn).chain_err(|| "unable to read schema")?;
+    Ok(columns.into_iter().collect())
+}
+
+/// Makes sure a database passes SQLite's integrity check and has every table and column this
+/// version of gamelog uses.
+fn check_database(path: &Path) -> Result<(), Error> {
+    let conn = SqliteConnection::establish(&path.to_string_lossy()).chain_err(|| "unable to open database")?;
+
+    let problems: Vec<String> = sql::<Text>("PRAGMA integrity_check").load(&conn).chain_err(
+        || "unable to check database integrity"
+    )?;
+    if problems != vec!["ok".to_string()] {
+        return Err(format!("database is corrupt: {}", problems.join("; ")).into());
+    }
+
+    let expected_conn = SqliteConnection::establish(":memory:").chain_err(|| "unable to open in-memory database")?;
+    for table in SCHEMA {
+        expected_conn.batch_execute(table).chain_err(|| "unable to create expected schema")?;
+    }
+    let columns = get_table_columns(&conn)?;
+    let missing: Vec<String> = get_table_columns(&expected_conn)?.difference(&columns).cloned().collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "database doesn't match this version of gamelog, it's missing {}",
+            missing.join(", "),
+        ).into());
+    }
+    Ok(())
+}
+
+/// Replaces the database with a backup, after checking the backup is sound.
+///
+/// The database being replaced is kept next to it with a `.before-restore` suffix.
+pub fn restore(source: &Path) -> Result<(), Error> {
+    if !source.is_file() {
+        return Err(format!("{} doesn't exist", source.display()).into());
+    }
+
+    // stage the backup next to the database so the final rename can't cross filesystems
+    let staged_path = PathBuf::from(format!("{}.restore", DATABASE_PATH));
+    let staged = if is_gzipped(source) {
+        gunzip(source, &staged_path)
+    } else {
+        fs::copy(source, &staged_path).map(|_| ()).chain_err(|| format!("unable to copy {}", source.display()))
+    };
+    if let Err(error) = staged.and_then(|_| check_database(&staged_path)) {
+        let _ = fs::remove_file(&staged_path);
+        return Err(error);
+    }
+
+    let database_path = Path::new(DATABASE_PATH);
+    if database_path.exists() {
+        fs::rename(
+            database_path,
+            format!("{}.before-restore", DATABASE_PATH),
+        ).chain_err(|| "unable to move the current database aside")?;
+    }
+    fs::rename(&staged_path, database_path).chain_err(|| "unable to move the restored database into place")?;
+    Ok(())
+}
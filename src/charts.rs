@@ -0,0 +1,216 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use time;
+
+// colors for successive series, from Bootstrap's palette
+const COLORS: &'static [&'static str] = &[
+    "#337ab7", "#5cb85c", "#5bc0de", "#f0ad4e", "#d9534f", "#9b59b6", "#777777", "#333333",
+];
+
+const HEATMAP_COLORS: &'static [&'static str] = &["#ebedf0", "#c6e48b", "#7bc96f", "#239a3b", "#196127"];
+
+const DAY_SECONDS: i64 = 86400;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn color(index: usize) -> &'static str {
+    COLORS[index % COLORS.len()]
+}
+
+/// One bar of a stacked bar chart, with a value for each of the chart's series.
+pub struct Bar {
+    pub label: String,
+    pub values: Vec<i64>,
+}
+
+/// Horizontal bars, each split into one segment per series, with a legend underneath.
+pub fn stacked_bar_chart(series: &[String], bars: &[Bar]) -> String {
+    let label_width = 140;
+    let bar_width = 400;
+    let row_height = 22;
+    let legend_height = 20 * ((series.len() + 3) / 4) as i64;
+    let height = row_height * bars.len() as i64 + legend_height + 10;
+    let largest = bars.iter().map(|bar| bar.values.iter().sum::<i64>()).max().unwrap_or(0).max(1);
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#,
+        label_width + bar_width + 50,
+        height,
+    ).unwrap();
+    for (row, bar) in bars.iter().enumerate() {
+        let y = row as i64 * row_height;
+        write!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="end">{}</text>"#,
+            label_width - 6,
+            y + 15,
+            escape(&bar.label),
+        ).unwrap();
+
+        let mut x = label_width;
+        for (index, &value) in bar.values.iter().enumerate() {
+            let width = value * bar_width / largest;
+            if width > 0 {
+                write!(
+                    svg,
+                    r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"><title>{}: {}</title></rect>"#,
+                    x,
+                    y + 2,
+                    width,
+                    row_height - 4,
+                    color(index),
+                    escape(series.get(index).map(|name| name.as_str()).unwrap_or("")),
+                    value,
+                ).unwrap();
+            }
+            x += width;
+        }
+        write!(svg, r#"<text x="{}" y="{}">{}</text>"#, x + 4, y + 15, bar.values.iter().sum::<i64>()).unwrap();
+    }
+
+    let legend_y = row_height * bars.len() as i64 + 10;
+    for (index, name) in series.iter().enumerate() {
+        let x = (index % 4) as i64 * 150;
+        let y = legend_y + (index / 4) as i64 * 20;
+        write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="12" height="12" fill="{}"/><text x="{}" y="{}">{}</text>"#,
+            x,
+            y,
+            color(index),
+            x + 16,
+            y + 11,
+            escape(name),
+        ).unwrap();
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A single line through the points in order, labelled along the bottom with every few points'
+/// labels so they don't overlap.
+pub fn line_chart(points: &[(String, i64)]) -> String {
+    let width = 600;
+    let height = 200;
+    let left = 40;
+    let bottom = 20;
+    let plot_width = width - left - 10;
+    let plot_height = height - bottom - 10;
+    let largest = points.iter().map(|&(_, value)| value).max().unwrap_or(0).max(1);
+    let smallest = points.iter().map(|&(_, value)| value).min().unwrap_or(0).min(0);
+    let range = largest - smallest;
+    let step = if points.len() > 1 { plot_width / (points.len() as i64 - 1) } else { 0 };
+    let label_every = (points.len() / 8).max(1);
+
+    let x_of = |index: usize| left + index as i64 * step;
+    let y_of = |value: i64| 10 + (largest - value) * plot_height / range;
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="11">"#,
+        width,
+        height,
+    ).unwrap();
+    write!(
+        svg,
+        r##"<line x1="{0}" y1="10" x2="{0}" y2="{1}" stroke="#ccc"/><line x1="{0}" y1="{1}" x2="{2}" y2="{1}" stroke="#ccc"/>"##,
+        left,
+        10 + plot_height,
+        width - 10,
+    ).unwrap();
+    write!(svg, r#"<text x="{}" y="14" text-anchor="end">{}</text>"#, left - 4, largest).unwrap();
+    write!(svg, r#"<text x="{}" y="{}" text-anchor="end">{}</text>"#, left - 4, 10 + plot_height, smallest).unwrap();
+
+    let path: Vec<String> = points.iter().enumerate().map(
+        |(index, &(_, value))| format!("{},{}", x_of(index), y_of(value))
+    ).collect();
+    write!(svg, r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#, path.join(" "), color(0)).unwrap();
+
+    for (index, &(ref label, value)) in points.iter().enumerate() {
+        write!(
+            svg,
+            r#"<circle cx="{}" cy="{}" r="3" fill="{}"><title>{}: {}</title></circle>"#,
+            x_of(index),
+            y_of(value),
+            color(0),
+            escape(label),
+            value,
+        ).unwrap();
+        if index % label_every == 0 {
+            write!(
+                svg,
+                r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+                x_of(index),
+                height - 4,
+                escape(label),
+            ).unwrap();
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A year of days ending at `end`, one square per day in columns of weeks, shaded by how many
+/// things happened that day. `counts` is keyed by `YYYY-MM-DD`.
+pub fn calendar_heatmap(counts: &BTreeMap<String, i64>, end: time::Tm) -> String {
+    let cell = 12;
+    let weeks = 53;
+    let top = 16;
+    let largest = counts.values().cloned().max().unwrap_or(0).max(1);
+
+    // start on the Sunday 52 weeks before the week `end` is in
+    let end_sec = end.to_timespec().sec;
+    let start_sec = end_sec - (end.tm_wday as i64 + (weeks - 1) * 7) * DAY_SECONDS;
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="10">"#,
+        weeks * cell + 10,
+        top + 7 * cell,
+    ).unwrap();
+
+    let mut day_sec = start_sec;
+    let mut last_month = -1;
+    while day_sec <= end_sec {
+        let day = time::at_utc(time::Timespec::new(day_sec, 0));
+        let week = (day_sec - start_sec) / DAY_SECONDS / 7;
+        let date = day.strftime("%Y-%m-%d").map(|date| date.to_string()).unwrap_or_default();
+        let count = counts.get(&date).cloned().unwrap_or(0);
+        let shade = if count == 0 {
+            0
+        } else {
+            1 + ((count - 1) * (HEATMAP_COLORS.len() as i64 - 1) / largest) as usize
+        };
+
+        if day.tm_mon != last_month && day.tm_mday <= 7 {
+            write!(
+                svg,
+                r#"<text x="{}" y="10">{}</text>"#,
+                week * cell,
+                day.strftime("%b").map(|month| month.to_string()).unwrap_or_default(),
+            ).unwrap();
+            last_month = day.tm_mon;
+        }
+        write!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"><title>{}: {}</title></rect>"#,
+            week * cell,
+            top + day.tm_wday as i64 * cell,
+            cell - 2,
+            cell - 2,
+            HEATMAP_COLORS[shade],
+            date,
+            count,
+        ).unwrap();
+        day_sec += DAY_SECONDS;
+    }
+    svg.push_str("</svg>");
+    svg
+}
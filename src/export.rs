@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use csv;
+use serde_json;
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use helpers::format_date;
+use import::ImportSummary;
+use model;
+
+/// Bumped whenever the document changes in a way older importers can't read.
+///
+/// Version 2 added tags, lists, the up next queue and goals. Version 1 exports don't have them,
+/// so they're read as empty.
+pub const EXPORT_VERSION: u32 = 2;
+
+/// Everything gamelog knows about one user, as written by `export_user`.
+#[derive(Serialize, Deserialize)]
+pub struct AccountExport {
+    pub version: u32,
+    pub exported_date: i64,
+    pub profile: ExportedProfile,
+    pub storefront_accounts: Vec<ExportedStorefrontAccount>,
+    pub collection: Vec<ExportedUserGame>,
+    pub wishlist: Vec<ExportedWishlistGame>,
+    #[serde(default)]
+    pub lists: Vec<ExportedGameList>,
+    /// In queue order.
+    #[serde(default)]
+    pub up_next: Vec<ExportedUpNextEntry>,
+    #[serde(default)]
+    pub goals: Vec<ExportedGoal>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedProfile {
+    pub username: String,
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedStorefrontAccount {
+    pub provider: String,
+    pub account_id: String,
+}
+
+/// Dates are unix timestamps, playtime is in minutes and the rating is out of 100.
+#[derive(Serialize, Deserialize)]
+pub struct ExportedUserGame {
+    pub name: String,
+    pub platform: String,
+    pub play_state: String,
+    pub acquisition_date: i64,
+    pub start_date: Option<i64>,
+    pub beat_date: Option<i64>,
+    #[serde(default)]
+    pub playtime: Option<i64>,
+    #[serde(default)]
+    pub history: Vec<ExportedPlayStateChange>,
+    /// Markdown.
+    #[serde(default)]
+    pub notes: String,
+    /// Markdown.
+    #[serde(default)]
+    pub review: Option<String>,
+    #[serde(default)]
+    pub rating: Option<i64>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedPlayStateChange {
+    pub play_state: String,
+    pub date: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedWishlistGame {
+    pub name: String,
+    pub added_date: i64,
+}
+
+/// Games are referred to by name, in list order.
+#[derive(Serialize, Deserialize)]
+pub struct ExportedGameList {
+    pub name: String,
+    pub slug: String,
+    pub ranked: bool,
+    pub games: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportedUpNextEntry {
+    pub name: String,
+    pub priority: i64,
+    pub estimated_hours: Option<i64>,
+    pub now_playing: bool,
+}
+
+/// Dates are unix timestamps, see `goals::GoalKind` for what each kind uses.
+#[derive(Serialize, Deserialize)]
+pub struct ExportedGoal {
+    pub kind: String,
+    pub target: Option<i64>,
+    pub platform: Option<String>,
+    pub start_date: Option<i64>,
+    pub end_date: Option<i64>,
+    pub met_date: Option<i64>,
+}
+
+pub fn export_user(user_id: i64) -> Result<AccountExport, Error> {
+    let user = model::get_user_by_id(user_id)?;
+    let playtimes = model::get_total_playtimes(user_id)?;
+    let mut notes = model::get_user_game_notes(user_id)?;
+    let mut tags = model::get_user_game_tags(user_id)?;
+
+    let mut history: HashMap<i64, Vec<ExportedPlayStateChange>> = HashMap::new();
+    for change in model::get_user_game_history(user_id)? {
+        history.entry(change.user_game_id).or_insert_with(Vec::new).push(ExportedPlayStateChange{
+            play_state: change.play_state,
+            date: change.date,
+        });
+    }
+
+    let mut collection: Vec<ExportedUserGame> = model::get_user_games_with_names(user_id)?.into_iter().map(|(name, user_game)| {
+        let (game_notes, review, rating) = match notes.remove(&user_game.id) {
+            Some(note) => (note.notes, note.review, note.rating),
+            None => ("".to_string(), None, None),
+        };
+        ExportedUserGame{
+            name: name,
+            platform: user_game.platform,
+            play_state: user_game.play_state,
+            acquisition_date: user_game.acquisition_date,
+            start_date: user_game.start_date,
+            beat_date: user_game.beat_date,
+            playtime: playtimes.get(&user_game.id).cloned(),
+            history: history.remove(&user_game.id).unwrap_or_default(),
+            notes: game_notes,
+            review: review,
+            rating: rating,
+            tags: tags.remove(&user_game.id).unwrap_or_default().into_iter().map(|tag| tag.name).collect(),
+        }
+    }).collect();
+    collection.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut lists = vec![];
+    for game_list in model::get_game_lists(user_id)? {
+        lists.push(ExportedGameList{
+            games: model::get_game_list_entries_with_names(game_list.id)?.into_iter().map(|(name, _)| name).collect(),
+            name: game_list.name,
+            slug: game_list.slug,
+            ranked: game_list.ranked,
+        });
+    }
+
+    Ok(AccountExport{
+        version: EXPORT_VERSION,
+        exported_date: time::get_time().sec,
+        profile: ExportedProfile{
+            username: user.username,
+            email: user.email,
+        },
+        storefront_accounts: model::get_storefront_accounts(user_id)?.into_iter().map(
+            |account| ExportedStorefrontAccount{
+                provider: account.provider,
+                account_id: account.account_id,
+            }
+        ).collect(),
+        collection: collection,
+        wishlist: model::get_wishlist_games_with_names(user_id)?.into_iter().map(
+            |(name, wishlist_game)| ExportedWishlistGame{
+                name: name,
+                added_date: wishlist_game.added_date,
+            }
+        ).collect(),
+        lists: lists,
+        up_next: model::get_up_next_with_names(user_id)?.into_iter().map(
+            |(name, entry)| ExportedUpNextEntry{
+                name: name,
+                priority: entry.priority,
+                estimated_hours: entry.estimated_hours,
+                now_playing: entry.now_playing,
+            }
+        ).collect(),
+        goals: model::get_goals(user_id)?.into_iter().map(
+            |goal| ExportedGoal{
+                kind: goal.kind,
+                target: goal.target,
+                platform: goal.platform,
+                start_date: goal.start_date,
+                end_date: goal.end_date,
+                met_date: goal.met_date,
+            }
+        ).collect(),
+    })
+}
+
+pub fn to_json(export: &AccountExport) -> Result<String, Error> {
+    serde_json::to_string_pretty(export).chain_err(|| "unable to serialize export")
+}
+
+fn format_optional_date(date: Option<i64>) -> String {
+    date.map(format_date).unwrap_or_default()
+}
+
+/// The collection alone, in the columns the CSV importer expects by default, plus the tags, which
+/// it skips.
+pub fn to_csv(export: &AccountExport) -> Result<String, Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(
+        &["name", "platform", "play_state", "acquisition_date", "start_date", "beat_date", "notes", "tags"]
+    ).chain_err(|| "unable to write CSV header")?;
+    for user_game in &export.collection {
+        writer.write_record(&[
+            user_game.name.clone(),
+            user_game.platform.clone(),
+            user_game.play_state.clone(),
+            format_date(user_game.acquisition_date),
+            format_optional_date(user_game.start_date),
+            format_optional_date(user_game.beat_date),
+            user_game.notes.clone(),
+            user_game.tags.join(", "),
+        ]).chain_err(|| "unable to write CSV row")?;
+    }
+    let bytes = writer.into_inner().chain_err(|| "unable to write CSV")?;
+    String::from_utf8(bytes).chain_err(|| "CSV isn't valid UTF-8")
+}
+
+pub fn from_json(json: &str) -> Result<AccountExport, Error> {
+    let export: AccountExport = serde_json::from_str(json).chain_err(|| "unable to parse export")?;
+    if export.version > EXPORT_VERSION {
+        return Err(format!(
+            "export is version {}, but only versions up to {} can be imported",
+            export.version,
+            EXPORT_VERSION,
+        ).into());
+    }
+    Ok(export)
+}
+
+/// Loads an export into a user's account, leaving their profile alone.
+///
+/// Games already in the collection are overwritten with the exported copy, and lists with the same
+/// URL get the exported games added to them. Goals the user already has aren't added twice.
+pub fn import_user(user_id: i64, export: AccountExport) -> Result<ImportSummary, Error> {
+    let mut summary = ImportSummary::new();
+
+    let imported: Vec<model::ImportedUserGame> = export.collection.iter().map(|user_game| model::ImportedUserGame{
+        name: user_game.name.clone(),
+        platform: Some(user_game.platform.clone()),
+        play_state: Some(user_game.play_state.clone()),
+        acquisition_date: Some(user_game.acquisition_date),
+        start_date: user_game.start_date,
+        beat_date: user_game.beat_date,
+        notes: Some(user_game.notes.clone()),
+        review: user_game.review.clone(),
+        rating: user_game.rating,
+        history: user_game.history.iter().map(|change| (change.play_state.clone(), change.date)).collect(),
+    }).collect();
+    model::import_user_games(user_id, &imported)?;
+    summary.imported = imported.len();
+
+    let user_game_ids: HashMap<String, i64> = model::get_user_games_with_names(user_id)?.into_iter().map(
+        |(name, user_game)| (name, user_game.id)
+    ).collect();
+    for user_game in &export.collection {
+        let user_game_id = match user_game_ids.get(&user_game.name) {
+            Some(user_game_id) => *user_game_id,
+            None => continue,
+        };
+        if let Some(playtime) = user_game.playtime {
+            model::update_total_playtime(user_game_id, playtime)?;
+        }
+        // version 1 exports have no tags, so they leave the existing ones alone
+        if !user_game.tags.is_empty() {
+            model::set_user_game_tags(user_id, user_game_id, &user_game.tags)?;
+        }
+    }
+
+    for list in export.lists {
+        let slug = list.slug;
+        let game_list = match model::get_optional_game_list_by_slug(user_id, &slug)? {
+            Some(game_list) => game_list,
+            None => {
+                model::add_game_list(model::NewGameList{
+                    user_id: user_id,
+                    name: list.name,
+                    slug: slug.clone(),
+                    ranked: list.ranked,
+                })?;
+                model::get_optional_game_list_by_slug(user_id, &slug)?.ok_or::<Error>("unable to find list".into())?
+            },
+        };
+        let list_user_game_ids: Vec<i64> = list.games.iter().filter_map(|name| user_game_ids.get(name).cloned()).collect();
+        for user_game_id in &list_user_game_ids {
+            model::add_game_list_entry(game_list.id, *user_game_id)?;
+        }
+        model::reorder_game_list(game_list.id, &list_user_game_ids)?;
+    }
+
+    let mut up_next_user_game_ids = vec![];
+    for entry in export.up_next {
+        if let Some(user_game_id) = user_game_ids.get(&entry.name).cloned() {
+            model::add_up_next_entry(user_id, user_game_id, entry.priority, entry.estimated_hours)?;
+            model::set_up_next_now_playing(user_id, user_game_id, entry.now_playing)?;
+            up_next_user_game_ids.push(user_game_id);
+        }
+    }
+    model::reorder_up_next(user_id, &up_next_user_game_ids)?;
+
+    let existing_goals = model::get_goals(user_id)?;
+    for goal in export.goals {
+        let already_added = existing_goals.iter().any(|existing| {
+            existing.kind == goal.kind &&
+                existing.target == goal.target &&
+                existing.platform == goal.platform &&
+                existing.start_date == goal.start_date &&
+                existing.end_date == goal.end_date
+        });
+        if !already_added {
+            model::add_goal(model::NewGoal{
+                user_id: user_id,
+                kind: goal.kind,
+                target: goal.target,
+                platform: goal.platform,
+                start_date: goal.start_date,
+                end_date: goal.end_date,
+                met_date: goal.met_date,
+            })?;
+        }
+    }
+
+    for wishlist_game in export.wishlist {
+        let game_id = model::upsert_game(wishlist_game.name)?;
+        model::upsert_wishlist_game(model::NewWishlistGame{
+            user_id: user_id,
+            game_id: game_id,
+            added_date: wishlist_game.added_date,
+            user_game_id: None,
+        })?;
+        summary.wishlisted += 1;
+    }
+
+    let linked = model::get_storefront_accounts(user_id)?;
+    for account in export.storefront_accounts {
+        let already_linked = linked.iter().any(
+            |linked| linked.provider == account.provider && linked.account_id == account.account_id
+        );
+        if !already_linked {
+            model::add_storefront_account(model::NewStorefrontAccount{
+                user_id: user_id,
+                provider: account.provider,
+                account_id: account.account_id,
+            })?;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Loads an export file written by `gamelog export` into a user's account.
+pub fn import_file(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    let user = model::get_user_by_name(username.to_string())?;
+    let mut json = String::new();
+    File::open(path).and_then(
+        |mut file| file.read_to_string(&mut json)
+    ).chain_err(|| format!("unable to read {}", path.display()))?;
+    import_user(user.id, from_json(&json)?)
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+
+use errors::Error;
+use helpers::FINISHED_PLAY_STATES;
+use helpers::format_date;
+use helpers::get_finish_dates;
+use helpers::get_platforms;
+use model;
+
+/// The kinds of goals there are, stored in `goal.kind` by slug.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GoalKind {
+    /// Beat `target` games, counting the ones beaten between the optional start and end dates.
+    BeatCount,
+    /// Get the number of unplayed and unfinished games below `target`, by the optional end date.
+    BacklogBelow,
+    /// Finish every game on `platform`, apart from multiplayer games and ones marked null.
+    FinishPlatform,
+}
+
+impl GoalKind {
+    pub fn all() -> Vec<GoalKind> {
+        vec![GoalKind::BeatCount, GoalKind::BacklogBelow, GoalKind::FinishPlatform]
+    }
+
+    pub fn from_slug(slug: &str) -> Option<GoalKind> {
+        GoalKind::all().into_iter().find(|kind| kind.slug() == slug)
+    }
+
+    pub fn slug(&self) -> &'static str {
+        match *self {
+            GoalKind::BeatCount => "beat_count",
+            GoalKind::BacklogBelow => "backlog_below",
+            GoalKind::FinishPlatform => "finish_platform",
+        }
+    }
+
+    pub fn display(&self) -> &'static str {
+        match *self {
+            GoalKind::BeatCount => "Beat a number of games",
+            GoalKind::BacklogBelow => "Get the backlog below a number of games",
+            GoalKind::FinishPlatform => "Finish every game on a platform",
+        }
+    }
+}
+
+/// How far along a goal is, as of now.
+pub struct GoalProgress {
+    pub goal_id: i64,
+    pub description: String,
+    pub current: i64,
+    pub target: i64,
+    pub percent: i64,
+    pub met: bool,
+    /// The goal's end date passed without it being met.
+    pub missed: bool,
+}
+
+fn describe_period(start_date: Option<i64>, end_date: Option<i64>) -> String {
+    match (start_date, end_date) {
+        (Some(start_date), Some(end_date)) => format!(" between {} and {}", format_date(start_date), format_date(end_date)),
+        (Some(start_date), None) => format!(" since {}", format_date(start_date)),
+        (None, Some(end_date)) => format!(" by {}", format_date(end_date)),
+        (None, None) => "".to_string(),
+    }
+}
+
+pub fn evaluate(
+    goal: &model::Goal,
+    user_games: &[model::UserGame],
+    finish_dates: &HashMap<i64, i64>,
+    platform_names: &HashMap<String, String>,
+    now: i64,
+) -> GoalProgress {
+    let target = goal.target.unwrap_or(0);
+    let kind = GoalKind::from_slug(&goal.kind);
+    let (description, current, target, met, percent) = match kind {
+        Some(GoalKind::BeatCount) => {
+            let current = finish_dates.values().filter(
+                |&&date| goal.start_date.map(|start_date| date >= start_date).unwrap_or(true) &&
+                    goal.end_date.map(|end_date| date <= end_date).unwrap_or(true)
+            ).count() as i64;
+            (
+                format!("Beat {} games{}", target, describe_period(goal.start_date, goal.end_date)),
+                current,
+                target,
+                current >= target,
+                if target > 0 { current * 100 / target } else { 100 },
+            )
+        },
+        Some(GoalKind::BacklogBelow) => {
+            let current = user_games.iter().filter(
+                |user_game| user_game.play_state == "unplayed" || user_game.play_state == "unfinished"
+            ).count() as i64;
+            (
+                format!("Get the backlog below {} games{}", target, describe_period(None, goal.end_date)),
+                current,
+                target,
+                current < target,
+                if current > 0 { target * 100 / current } else { 100 },
+            )
+        },
+        Some(GoalKind::FinishPlatform) => {
+            let platform = goal.platform.clone().unwrap_or_default();
+            let games: Vec<&model::UserGame> = user_games.iter().filter(
+                |user_game| user_game.platform == platform &&
+                    user_game.play_state != "multiplayer" && user_game.play_state != "null"
+            ).collect();
+            let current = games.iter().filter(
+                |user_game| FINISHED_PLAY_STATES.contains(&user_game.play_state.as_str())
+            ).count() as i64;
+            let total = games.len() as i64;
+            (
+                format!(
+                    "Finish every {} game{}",
+                    platform_names.get(&platform).cloned().unwrap_or(platform.clone()),
+                    describe_period(None, goal.end_date),
+                ),
+                current,
+                total,
+                total > 0 && current == total,
+                if total > 0 { current * 100 / total } else { 0 },
+            )
+        },
+        None => (format!("Unknown goal \"{}\"", goal.kind), 0, target, false, 0),
+    };
+
+    // beat counts only count games finished within the goal's dates, so they can be judged on
+    // the count alone. The other kinds look at the collection as it is now, which only counts
+    // up to the end date. Either way, a goal that was met stays met.
+    let in_time = kind == Some(GoalKind::BeatCount) || goal.end_date.map(|end_date| now <= end_date).unwrap_or(true);
+    let met = (met && in_time) || goal.met_date.is_some();
+    GoalProgress{
+        goal_id: goal.id,
+        description: description,
+        current: current,
+        target: target,
+        // only a met goal shows a full bar, e.g. a backlog right at its target is still unmet
+        percent: if met { 100 } else { percent.min(99) },
+        met: met,
+        missed: !met && goal.end_date.map(|end_date| now > end_date).unwrap_or(false),
+    }
+}
+
+/// Every one of a user's goals, with how far along it is.
+pub fn get_progress(user_id: i64, now: i64) -> Result<Vec<(model::Goal, GoalProgress)>, Error> {
+    let goals = model::get_goals(user_id)?;
+    if goals.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let user_games = model::get_user_games(user_id)?;
+    let finish_dates = get_finish_dates(&user_games, &model::get_user_game_history(user_id)?);
+    let platform_names: HashMap<String, String> = get_platforms()?.into_iter().map(
+        |platform| (platform.slug, platform.name)
+    ).collect();
+
+    Ok(goals.into_iter().map(|goal| {
+        let progress = evaluate(&goal, &user_games, &finish_dates, &platform_names, now);
+        (goal, progress)
+    }).collect())
+}
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::thread;
 
 use askama::Template;
 use iron::IronResult;
@@ -12,16 +13,44 @@ use params::Params;
 use router::Router;
 use time;
 
+use charts;
 use errors::Error;
 use errors::ResultExt;
 use errors;
+use export;
+use goals;
+use import::csv_file;
+use mail;
+use markdown;
+use helpers::FINISHED_PLAY_STATES;
+use helpers::Platform;
+use helpers::PlayState;
+use helpers::Priority;
+use helpers::RATING_SCALES;
+use helpers::check_email;
+use helpers::check_password;
+use helpers::check_username;
+use helpers::format_date;
+use helpers::get_ordered_user_game_ids_from_param_map;
+use helpers::get_param_file_contents_from_param_map;
 use helpers::get_param_string_from_param_map;
+use helpers::get_platforms;
+use helpers::get_play_states;
+use helpers::get_priorities;
 use helpers::get_user_from_session;
 use helpers::get_user_signup_info;
+use helpers::rating_from_scale;
+use helpers::rating_to_scale;
+use helpers::slugify;
 use model;
+use serde_json;
+use session::PendingLogin;
+use session::PendingLoginKey;
 use session::Session;
 use session::SessionKey;
-use serde_json;
+use storefront;
+use two_factor;
+use year_in_review;
 
 
 macro_rules! try_session {
@@ -61,13 +90,109 @@ struct BaseTemplate {
 struct UserLogTemplate {
     _parent: BaseTemplate,
     username: String,
+    user_id: i64,
+    is_owner: bool,
+    currently_playing: Vec<CurrentlyPlayingPresenter>,
     games: Vec<UserGamePresenter>,
+    tags: Vec<TagPresenter>,
+    filter: String,
+    goals: Vec<goals::GoalProgress>,
+}
+
+#[derive(Template)]
+#[template(path = "wishlist.html")]
+struct WishlistTemplate {
+    _parent: BaseTemplate,
+    username: String,
+    user_id: i64,
+    games: Vec<WishlistGamePresenter>,
+}
+
+#[derive(Template)]
+#[template(path = "game_lists.html")]
+struct GameListsTemplate {
+    _parent: BaseTemplate,
+    username: String,
+    user_id: i64,
+    is_owner: bool,
+    lists: Vec<GameListPresenter>,
+}
+
+#[derive(Template)]
+#[template(path = "game_list.html")]
+struct GameListTemplate {
+    _parent: BaseTemplate,
+    username: String,
+    user_id: i64,
+    is_owner: bool,
+    list_id: i64,
+    name: String,
+    ranked: bool,
+    entries: Vec<GameListEntryPresenter>,
+    addable_games: Vec<AddableGamePresenter>,
+}
+
+#[derive(Template)]
+#[template(path = "up_next.html")]
+struct UpNextTemplate<'a> {
+    _parent: BaseTemplate,
+    username: String,
+    user_id: i64,
+    is_owner: bool,
+    entries: Vec<UpNextEntryPresenter>,
+    now_playing_count: usize,
+    now_playing_limit: i64,
+    total_hours: i64,
+    priorities: Vec<Priority<'a>>,
+    addable_games: Vec<AddableGamePresenter>,
+}
+
+#[derive(Template)]
+#[template(path = "stats.html")]
+struct StatsTemplate {
+    _parent: BaseTemplate,
+    username: String,
+    user_id: i64,
+    total: i64,
+    completion_rate: String,
+    average_days_to_beat: String,
+    total_playtime: String,
+    play_states: Vec<StatPresenter>,
+    platforms: Vec<StatPresenter>,
+    beaten_by_year: Vec<StatPresenter>,
+    trend: Vec<TrendPresenter>,
+    backlog_change: i64,
+    play_states_by_platform_chart: String,
+    backlog_chart: String,
+    completions_chart: String,
+}
+
+#[derive(Template)]
+#[template(path = "year_in_review.html")]
+struct YearInReviewTemplate {
+    _parent: BaseTemplate,
+    review: year_in_review::YearInReview,
+}
+
+#[derive(Template)]
+#[template(path = "goals.html")]
+struct GoalsTemplate {
+    _parent: BaseTemplate,
+    user_id: i64,
+    goals: Vec<goals::GoalProgress>,
+    kinds: Vec<GoalKindPresenter>,
+    platforms: Vec<Platform>,
 }
 
 #[derive(Template)]
 #[template(path = "signup_form.html")]
 struct SignupFormTemplate {
     _parent: BaseTemplate,
+    username: String,
+    email: String,
+    username_error: String,
+    email_error: String,
+    password_error: String,
 }
 
 #[derive(Template)]
@@ -76,6 +201,19 @@ struct LoginFormTemplate {
     _parent: BaseTemplate,
 }
 
+#[derive(Template)]
+#[template(path = "forgot_password_form.html")]
+struct ForgotPasswordFormTemplate {
+    _parent: BaseTemplate,
+}
+
+#[derive(Template)]
+#[template(path = "reset_password_form.html")]
+struct ResetPasswordFormTemplate {
+    _parent: BaseTemplate,
+    token: String,
+}
+
 #[derive(Template)]
 #[template(path = "user_game_form.html")]
 struct UserGameFormTemplate<'a> {
@@ -88,6 +226,13 @@ struct UserGameFormTemplate<'a> {
     disabled_name: bool,
     set_user_game_state: String,
     set_platform: String,
+    wishlisted_since: String,
+    show_notes: bool,
+    notes: String,
+    review: String,
+    rating: String,
+    rating_scale: i64,
+    tags: String,
 }
 
 #[derive(Template)]
@@ -95,76 +240,166 @@ struct UserGameFormTemplate<'a> {
 struct UserSettingsFormTemplate {
     _parent: BaseTemplate,
     username: String,
-    steam_id: String,
+    email: String,
+    email_verified: bool,
+    accounts: Vec<StorefrontAccountPresenter>,
+    providers: Vec<StorefrontProviderPresenter>,
+    account_input: String,
+    rating_scales: Vec<RatingScalePresenter>,
+    two_factor_enabled: bool,
+    recovery_codes_left: i64,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor_login_form.html")]
+struct TwoFactorLoginFormTemplate {
+    _parent: BaseTemplate,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor_setup.html")]
+struct TwoFactorSetupTemplate {
+    _parent: BaseTemplate,
+    qr_code: String,
+    secret: String,
+}
+
+#[derive(Template)]
+#[template(path = "recovery_codes.html")]
+struct RecoveryCodesTemplate {
+    _parent: BaseTemplate,
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "delete_account_form.html")]
+struct DeleteAccountFormTemplate {
+    _parent: BaseTemplate,
+    username: String,
+}
+
+#[derive(Template)]
+#[template(path = "import_csv.html")]
+struct ImportCsvTemplate {
+    _parent: BaseTemplate,
+    mapping: Vec<MappingFieldPresenter>,
+    csv_text: String,
+    has_preview: bool,
+    rows: Vec<ImportRowPresenter>,
+    insert_count: usize,
+    update_count: usize,
+    skip_count: usize,
 }
 
 struct UserGamePresenter {
     name: String,
     user_game: model::UserGame,
+    achievements: String,
+    can_promote: bool,
+    rating: String,
+    notes_html: String,
+    review_html: String,
+    tags: Vec<TagPresenter>,
+}
+
+struct TagPresenter {
+    name: String,
+    slug: String,
+    selected: bool,
+}
+
+struct StorefrontAccountPresenter {
+    id: i64,
+    provider_name: String,
+    account_id: String,
+}
+
+struct RatingScalePresenter {
+    value: i64,
+    selected: bool,
+}
+
+struct StorefrontProviderPresenter {
+    slug: String,
+    name: String,
+}
+
+struct CurrentlyPlayingPresenter {
+    name: String,
+    user_game_id: i64,
+    recent_playtime: String,
+}
+
+struct MappingFieldPresenter {
+    field: String,
+    label: String,
+    column: String,
 }
 
-struct PlayState<'a> {
-    display: &'a str,
-    value: &'a str,
+struct ImportRowPresenter {
+    line: u64,
+    name: String,
+    action: String,
+    row_class: String,
+    details: String,
 }
 
-#[derive(Serialize, Deserialize)]
-struct Platform {
+struct GameListPresenter {
     name: String,
-    short_name: String,
     slug: String,
+    ranked: bool,
 }
 
-fn get_play_states<'a>() -> Vec<PlayState<'a>> {
-    vec![
-        PlayState{
-            display: "Unplayed",
-            value: "unplayed",
-        },
-        PlayState{
-            display: "Unfinished",
-            value: "unfinished",
-        },
-        PlayState{
-            display: "Beaten",
-            value: "beaten",
-        },
-        PlayState{
-            display: "Completed",
-            value: "completed",
-        },
-        PlayState{
-            display: "100%",
-            value: "100_percent",
-        },
-        PlayState{
-            display: "Won't Beat",
-            value: "wont_beat",
-        },
-        PlayState{
-            display: "Multiplayer",
-            value: "multiplayer",
-        },
-        PlayState{
-            display: "Null",
-            value: "null",
-        },
-    ]
+struct GameListEntryPresenter {
+    position: usize,
+    user_game_id: i64,
+    name: String,
+    play_state: String,
+    rating: String,
 }
 
-fn home(req: &mut Request) -> IronResult<Response> {
-    let logged_in = req.extensions.get::<SessionKey>().is_some();
-    let mut response = Response::with((
-        status::Ok,
-        itry!(BaseTemplate{
-            logged_in: logged_in,
-            alerts: vec![],
-        }.render()),
-    ));
+struct UpNextEntryPresenter {
+    position: usize,
+    user_game_id: i64,
+    name: String,
+    play_state: String,
+    priority: i64,
+    priority_display: String,
+    estimated_hours: String,
+    now_playing: bool,
+}
 
-    response.headers.set(ContentType::html());
+struct StatPresenter {
+    label: String,
+    count: i64,
+    /// Of the largest count in the same group, for sizing bars.
+    percent: i64,
+}
 
-    Ok(response)
+struct TrendPresenter {
+    month: String,
+    acquired: i64,
+    beaten: i64,
+    change: String,
+}
+
+struct GoalKindPresenter {
+    slug: String,
+    display: String,
+}
+
+struct AddableGamePresenter {
+    user_game_id: i64,
+    name: String,
+}
+
+struct WishlistGamePresenter {
+    name: String,
+    added_date: String,
+}
+
+fn home(req: &mut Request) -> IronResult<Response> {
+    render_home(req.extensions.get::<SessionKey>().is_some(), vec![])
 }
 
 fn user_log(req: &mut Request) -> IronResult<Response> {
@@ -188,19 +423,105 @@ fn user_log(req: &mut Request) -> IronResult<Response> {
         }
     };
 
-    let user_games_with_names = itry!(model::get_user_games_with_names(user.id));
-    let games = user_games_with_names.into_iter().map(|(name, game)| UserGamePresenter{
-        name: name,
-        user_game: game,
+    let is_owner = match req.extensions.get::<SessionKey>() {
+        Some(session) => session.user_id == user.id,
+        None => false,
+    };
+
+    // ?tags=co-op,couch shows only games with every one of those tags
+    let filter = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_string_from_param_map(params, "tags").unwrap_or_default()
+    };
+    let filter_slugs: Vec<String> = filter.split(',').map(slugify).filter(|slug| slug != "").collect();
+
+    let mut user_game_tags = itry!(model::get_user_game_tags(user.id));
+    let user_games_with_names: Vec<(String, model::UserGame)> = itry!(
+        model::get_user_games_with_names(user.id)
+    ).into_iter().filter(|&(_, ref game)| {
+        let game_tags = user_game_tags.get(&game.id);
+        filter_slugs.iter().all(
+            |slug| game_tags.map(|game_tags| game_tags.iter().any(|tag| &tag.slug == slug)).unwrap_or(false)
+        )
+    }).collect();
+    let achievement_counts = itry!(model::get_achievement_counts(user.id));
+    let mut notes = itry!(model::get_user_game_notes(user.id));
+    let games = user_games_with_names.into_iter().map(|(name, game)| {
+        let (achievements, all_achievements_unlocked) = match achievement_counts.get(&game.id) {
+            Some(count) => (
+                format!("{}/{} achievements ({}%)", count.unlocked, count.total, count.percent()),
+                count.is_complete(),
+            ),
+            None => ("".to_string(), false),
+        };
+        let (rating, notes_html, review_html) = match notes.remove(&game.id) {
+            Some(note) => (
+                note.rating.map(
+                    |rating| format!("{}/{}", rating_to_scale(rating, user.rating_scale), user.rating_scale)
+                ).unwrap_or_default(),
+                markdown::render(&note.notes),
+                note.review.map(|review| markdown::render(&review)).unwrap_or_default(),
+            ),
+            None => ("".to_string(), "".to_string(), "".to_string()),
+        };
+        UserGamePresenter{
+            name: name,
+            achievements: achievements,
+            can_promote: is_owner && all_achievements_unlocked && game.play_state != "100_percent",
+            rating: rating,
+            notes_html: notes_html,
+            review_html: review_html,
+            tags: user_game_tags.remove(&game.id).unwrap_or_default().into_iter().map(|tag| TagPresenter{
+                selected: filter_slugs.contains(&tag.slug),
+                name: tag.name,
+                slug: tag.slug,
+            }).collect(),
+            user_game: game,
+        }
+    }).collect();
+
+    let tags = itry!(model::get_tags(user.id)).into_iter().map(|tag| TagPresenter{
+        selected: filter_slugs.contains(&tag.slug),
+        name: tag.name,
+        slug: tag.slug,
     }).collect();
 
+    let currently_playing = itry!(model::get_currently_playing(user.id)).into_iter().map(
+        |(name, playtime)| CurrentlyPlayingPresenter{
+            name: name,
+            user_game_id: playtime.user_game_id,
+            recent_playtime: format!("{:.1} hours in the last two weeks", playtime.playtime_2weeks as f64 / 60.0),
+        }
+    ).collect();
+
+    // goals are announced to their owner the first time they're seen to be met
+    let now = time::get_time().sec;
+    let mut alerts = vec![];
+    let mut goal_progress = vec![];
+    for (goal, progress) in itry!(goals::get_progress(user.id, now)) {
+        if is_owner && progress.met && goal.met_date.is_none() {
+            itry!(model::mark_goal_met(goal.id, now));
+            alerts.push(Alert{
+                level: "success".to_string(),
+                message: format!("Goal met: {}!", progress.description),
+            });
+        }
+        goal_progress.push(progress);
+    }
+
     let template_context = UserLogTemplate {
         _parent: BaseTemplate{
             logged_in: req.extensions.get::<SessionKey>().is_some(),
-            alerts: vec![],
+            alerts: alerts,
         },
         username: user.username,
+        user_id: user.id,
+        is_owner: is_owner,
+        currently_playing: currently_playing,
         games: games,
+        tags: tags,
+        filter: filter_slugs.join(","),
+        goals: goal_progress,
     };
 
     let mut response = Response::with((
@@ -213,14 +534,36 @@ fn user_log(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn signup_form(req: &mut Request) -> IronResult<Response> {
+fn get_log_user(req: &Request) -> errors::Result<model::User> {
+    let params = req.extensions.get::<Router>().ok_or::<Error>("no router".into())?;
+    let user_string = params.find("user").ok_or::<Error>("no user id or username provided".into())?;
+
+    match user_string.parse::<i64>() {
+        Ok(user_id) => model::get_user_by_id(user_id),
+        Err(_) => model::get_user_by_name(user_string.to_string()),
+    }
+}
+
+fn wishlist(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+
+    let games = itry!(model::get_wishlist_games_with_names(user.id)).into_iter().map(
+        |(name, wishlist_game)| WishlistGamePresenter{
+            name: name,
+            added_date: format_date(wishlist_game.added_date),
+        }
+    ).collect();
+
     let mut response = Response::with((
         status::Ok,
-        itry!(SignupFormTemplate{
+        itry!(WishlistTemplate{
             _parent: BaseTemplate{
                 logged_in: req.extensions.get::<SessionKey>().is_some(),
                 alerts: vec![],
             },
+            username: user.username,
+            user_id: user.id,
+            games: games,
         }.render()),
     ));
     response.headers.set(ContentType::html());
@@ -228,14 +571,19 @@ fn signup_form(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn login_form(req: &mut Request) -> IronResult<Response> {
+fn signup_form(req: &mut Request) -> IronResult<Response> {
     let mut response = Response::with((
         status::Ok,
-        itry!(LoginFormTemplate{
+        itry!(SignupFormTemplate{
             _parent: BaseTemplate{
                 logged_in: req.extensions.get::<SessionKey>().is_some(),
                 alerts: vec![],
             },
+            username: "".to_string(),
+            email: "".to_string(),
+            username_error: "".to_string(),
+            email_error: "".to_string(),
+            password_error: "".to_string(),
         }.render()),
     ));
     response.headers.set(ContentType::html());
@@ -243,57 +591,177 @@ fn login_form(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn signup(req: &mut Request) -> IronResult<Response> {
-    let user_signup_info = itry!(get_user_signup_info(req));
-    itry!(model::signup(user_signup_info));
+fn render_login_form(logged_in: bool, alerts: Vec<Alert>) -> IronResult<Response> {
+    let mut response = Response::with((
+        status::Ok,
+        itry!(LoginFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: logged_in,
+                alerts: alerts,
+            },
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
 
-    Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))))
+    Ok(response)
 }
 
-fn get_login_info_from_request(req: &mut Request) -> errors::Result<model::LoginInfo> {
-    let params = req.get_ref::<Params>().chain_err(|| "unable to get params map")?;
+fn login_form(req: &mut Request) -> IronResult<Response> {
+    render_login_form(req.extensions.get::<SessionKey>().is_some(), vec![])
+}
 
-    let username_or_email = get_param_string_from_param_map(params, "username-or-email")?;
-    let password = get_param_string_from_param_map(params, "password")?;
+/// How long emailed links stay good for, in seconds.
+const VERIFY_EMAIL_LIFETIME: i64 = 7 * 24 * 60 * 60;
+const RESET_PASSWORD_LIFETIME: i64 = 60 * 60;
+const CHANGE_EMAIL_LIFETIME: i64 = 24 * 60 * 60;
 
-    Ok(model::LoginInfo{
-        username_or_email: username_or_email,
-        password: password,
+fn render_home(logged_in: bool, alerts: Vec<Alert>) -> IronResult<Response> {
+    let mut response = Response::with((
+        status::Ok,
+        itry!(BaseTemplate{
+            logged_in: logged_in,
+            alerts: alerts,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn send_verification_email(user_id: i64, email: &str) -> errors::Result<()> {
+    let token = model::create_user_token(user_id, model::TOKEN_VERIFY_EMAIL, None, VERIFY_EMAIL_LIFETIME)?;
+    mail::send(mail::Message{
+        to: email.to_string(),
+        subject: "Confirm your gamelog email address".to_string(),
+        body: format!(
+            "Follow this link to confirm this is your email address:\n\n{}\n\nIt's good for a week.",
+            mail::get_link(&format!("/verify-email/{}", token)),
+        ),
     })
 }
 
-fn login(req: &mut Request) -> IronResult<Response> {
-    let login_info = itry!(get_login_info_from_request(req));
-    let user_id = itry!(model::login(login_info));
-    req.extensions.insert::<SessionKey>(Session{user_id: user_id});
+/// Turns the result of sending an email into an alert saying to go check for it.
+fn get_mail_alert(result: errors::Result<()>, sent_message: &str) -> Alert {
+    match result {
+        Ok(_) => Alert{
+            level: "info".to_string(),
+            message: sent_message.to_string(),
+        },
+        Err(error) => {
+            // mail errors can give away server details, so they only go in the log
+            eprintln!("unable to send email: {}", error);
+            Alert{
+                level: "warning".to_string(),
+                message: "Couldn't send an email right now, please try again later.".to_string(),
+            }
+        },
+    }
+}
 
-    Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))))
+/// Sends an email without waiting for it, logging any failure. For pages that have to look and
+/// take the same whether or not an email goes out.
+fn send_mail_in_background(message: mail::Message) {
+    thread::spawn(move || {
+        if let Err(error) = mail::send(message) {
+            eprintln!("unable to send email: {}", error);
+        }
+    });
 }
 
-fn logout(req: &mut Request) -> IronResult<Response> {
-    req.extensions.remove::<SessionKey>();
+fn signup(req: &mut Request) -> IronResult<Response> {
+    let user_signup_info = itry!(get_user_signup_info(req));
 
-    Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))))
+    let username_error = match check_username(&user_signup_info.username) {
+        Some(error) => Some(error),
+        None if itry!(model::get_optional_user_by_name(&user_signup_info.username)).is_some() => {
+            Some("That username is taken.".to_string())
+        },
+        None => None,
+    };
+    let email_error = match check_email(&user_signup_info.email) {
+        Some(error) => Some(error),
+        None if itry!(model::get_optional_user_by_email(&user_signup_info.email)).is_some() => {
+            Some("There's already an account with that email address.".to_string())
+        },
+        None => None,
+    };
+    let password_error = check_password(&user_signup_info.password, &user_signup_info.username, &user_signup_info.email);
+    if username_error.is_some() || email_error.is_some() || password_error.is_some() {
+        let mut response = Response::with((
+            status::BadRequest,
+            itry!(SignupFormTemplate{
+                _parent: BaseTemplate{
+                    logged_in: req.extensions.get::<SessionKey>().is_some(),
+                    alerts: vec![Alert{
+                        level: "danger".to_string(),
+                        message: "Couldn't sign up, see below for what needs fixing.".to_string(),
+                    }],
+                },
+                username: user_signup_info.username,
+                email: user_signup_info.email,
+                username_error: username_error.unwrap_or_default(),
+                email_error: email_error.unwrap_or_default(),
+                password_error: password_error.unwrap_or_default(),
+            }.render()),
+        ));
+        response.headers.set(ContentType::html());
+        return Ok(response);
+    }
+
+    let email = user_signup_info.email.clone();
+    let user_id = itry!(model::signup(user_signup_info));
+
+    let alert = get_mail_alert(
+        send_verification_email(user_id, &email),
+        "Welcome! We've sent you an email to confirm your address.",
+    );
+    render_home(req.extensions.get::<SessionKey>().is_some(), vec![alert])
 }
 
-fn add_user_game_form(req: &mut Request) -> IronResult<Response> {
-    redirect_logged_out_user!(req);
+fn get_token_from_url(req: &Request) -> errors::Result<String> {
+    let params = req.extensions.get::<Router>().ok_or::<Error>("no router".into())?;
+    Ok(params.find("token").ok_or::<Error>("no token provided".into())?.to_string())
+}
+
+fn verify_email(req: &mut Request) -> IronResult<Response> {
+    let token = itry!(get_token_from_url(req));
+    let alert = match itry!(model::use_user_token(&token, model::TOKEN_VERIFY_EMAIL)) {
+        Some(user_token) => {
+            itry!(model::mark_email_verified(user_token.user_id));
+            Alert{
+                level: "success".to_string(),
+                message: "Your email address is confirmed.".to_string(),
+            }
+        },
+        None => Alert{
+            level: "danger".to_string(),
+            message: "That link has expired or was already used.".to_string(),
+        },
+    };
+    render_home(req.extensions.get::<SessionKey>().is_some(), vec![alert])
+}
 
+fn resend_verification_email(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    let alert = get_mail_alert(
+        send_verification_email(user.id, &user.email),
+        &format!("We've sent a confirmation link to {}.", user.email),
+    );
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![alert])
+}
+
+fn forgot_password_form(req: &mut Request) -> IronResult<Response> {
     let mut response = Response::with((
         status::Ok,
-        itry!(UserGameFormTemplate{
+        itry!(ForgotPasswordFormTemplate{
             _parent: BaseTemplate{
                 logged_in: req.extensions.get::<SessionKey>().is_some(),
                 alerts: vec![],
             },
-            page_title: "Add a Game".to_string(),
-            submit_button: "Add Game".to_string(),
-            play_states: get_play_states(),
-            platforms: itry!(get_platforms()),
-            name: "".to_string(),
-            disabled_name: false,
-            set_user_game_state: "".to_string(),
-            set_platform: "".to_string(),
         }.render()),
     ));
     response.headers.set(ContentType::html());
@@ -301,39 +769,413 @@ fn add_user_game_form(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn add_user_game(req: &mut Request) -> IronResult<Response> {
-    let user = {
-        let session = try_session!(req);
-        itry!(get_user_from_session(session))
+fn forgot_password(req: &mut Request) -> IronResult<Response> {
+    let email = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        itry!(get_param_string_from_param_map(params, "email")).trim().to_string()
     };
 
-    let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
-    let name = itry!(get_param_string_from_param_map(params, "name"));
-    let state = itry!(get_param_string_from_param_map(params, "state"));
-    let platform = itry!(get_param_string_from_param_map(params, "platform"));
-    if !(
-        itry!(get_platforms()).iter().any(|valid_platform| platform == valid_platform.slug) &&
-        get_play_states().iter().any(|valid_play_state| state == valid_play_state.value)
-    ) {
-        return Ok(Response::with((status::BadRequest, "platform or play state not valid!")));
+    // the same thing is shown just as quickly whether or not there's an account or the email
+    // could be sent, so this can't be used to find out who has one
+    if let Some(user) = itry!(model::get_optional_user_by_email(&email)) {
+        let token = itry!(model::create_user_token(user.id, model::TOKEN_RESET_PASSWORD, None, RESET_PASSWORD_LIFETIME));
+        send_mail_in_background(mail::Message{
+            to: user.email,
+            subject: "Reset your gamelog password".to_string(),
+            body: format!(
+                "Follow this link to choose a new password for {}:\n\n{}\n\nIt's good for an hour. If you didn't ask to reset your password, you can ignore this email.",
+                user.username,
+                mail::get_link(&format!("/reset-password/{}", token)),
+            ),
+        });
     }
 
-    // TODO make sure that games with the same name don't get mixed up
-    let game_id = itry!(model::upsert_game(name));
-
-    itry!(
-        model::add_user_game(model::NewUserGame{
-            game_id: game_id,
-            user_id: user.id,
-            play_state: state,
-            platform: platform,
-            acquisition_date: time::get_time().sec,
-            start_date: None,
-            beat_date: None,
-        })
-    );
-
-    Ok(Response::with((status::SeeOther, RedirectRaw("/me".to_string()))))
+    render_home(
+        req.extensions.get::<SessionKey>().is_some(),
+        vec![Alert{
+            level: "info".to_string(),
+            message: format!("If {} belongs to an account, a link to reset its password is on its way.", email),
+        }],
+    )
+}
+
+fn reset_password_form(req: &mut Request) -> IronResult<Response> {
+    let token = itry!(get_token_from_url(req));
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(ResetPasswordFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: req.extensions.get::<SessionKey>().is_some(),
+                alerts: vec![],
+            },
+            token: token,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn reset_password(req: &mut Request) -> IronResult<Response> {
+    let token = itry!(get_token_from_url(req));
+    let (password, password_confirmation) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(get_param_string_from_param_map(params, "password")),
+            itry!(get_param_string_from_param_map(params, "password_confirmation")),
+        )
+    };
+    let password_error = if password != password_confirmation {
+        Some("The passwords don't match.".to_string())
+    } else {
+        check_password(&password, "", "")
+    };
+    if let Some(password_error) = password_error {
+        let mut response = Response::with((
+            status::BadRequest,
+            itry!(ResetPasswordFormTemplate{
+                _parent: BaseTemplate{
+                    logged_in: req.extensions.get::<SessionKey>().is_some(),
+                    alerts: vec![Alert{
+                        level: "danger".to_string(),
+                        message: password_error,
+                    }],
+                },
+                token: token,
+            }.render()),
+        ));
+        response.headers.set(ContentType::html());
+        return Ok(response);
+    }
+
+    let alert = match itry!(model::use_user_token(&token, model::TOKEN_RESET_PASSWORD)) {
+        Some(user_token) => {
+            itry!(model::update_password(user_token.user_id, &password));
+            itry!(model::expire_user_tokens(user_token.user_id, model::TOKEN_RESET_PASSWORD));
+            Alert{
+                level: "success".to_string(),
+                message: "Your password has been changed, you can log in with it now.".to_string(),
+            }
+        },
+        None => Alert{
+            level: "danger".to_string(),
+            message: "That link has expired or was already used.".to_string(),
+        },
+    };
+    render_home(req.extensions.get::<SessionKey>().is_some(), vec![alert])
+}
+
+/// Sends a confirmation link to the new address; the email only changes once it's followed.
+fn change_email(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let (new_email, password) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(get_param_string_from_param_map(params, "email")).trim().to_string(),
+            itry!(get_param_string_from_param_map(params, "password")),
+        )
+    };
+
+    if new_email == "" || new_email == user.email {
+        return render_user_settings_form(user.id, user.username, "".to_string(), vec![]);
+    }
+    // whoever controls the email can reset the password, so changing it needs the password too
+    let reauthentication_error = itry!(check_reauthentication(
+        req,
+        user.id,
+        &password,
+        "That password wasn't right, so your email address hasn't been changed.",
+    ));
+    if let Some(reauthentication_error) = reauthentication_error {
+        let alert = Alert{
+            level: "danger".to_string(),
+            message: reauthentication_error,
+        };
+        return render_user_settings_form(user.id, user.username, "".to_string(), vec![alert]);
+    }
+    let email_error = match check_email(&new_email) {
+        Some(error) => Some(error),
+        None if itry!(model::get_optional_user_by_email(&new_email)).is_some() => {
+            Some(format!("{} is already used by another account.", new_email))
+        },
+        None => None,
+    };
+    if let Some(email_error) = email_error {
+        let alert = Alert{
+            level: "danger".to_string(),
+            message: email_error,
+        };
+        return render_user_settings_form(user.id, user.username, "".to_string(), vec![alert]);
+    }
+
+    let token = itry!(model::create_user_token(
+        user.id,
+        model::TOKEN_CHANGE_EMAIL,
+        Some(new_email.clone()),
+        CHANGE_EMAIL_LIFETIME,
+    ));
+    let result = mail::send(mail::Message{
+        to: new_email.clone(),
+        subject: "Confirm your new gamelog email address".to_string(),
+        body: format!(
+            "Follow this link to change the email address for {} to this one:\n\n{}\n\nIt's good for a day.",
+            user.username,
+            mail::get_link(&format!("/confirm-email/{}", token)),
+        ),
+    });
+    let alert = get_mail_alert(
+        result,
+        &format!("We've sent a confirmation link to {}; your email changes once you follow it.", new_email),
+    );
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![alert])
+}
+
+fn confirm_email_change(req: &mut Request) -> IronResult<Response> {
+    let token = itry!(get_token_from_url(req));
+    let user_token = match itry!(model::use_user_token(&token, model::TOKEN_CHANGE_EMAIL)) {
+        Some(user_token) => user_token,
+        None => return render_home(
+            req.extensions.get::<SessionKey>().is_some(),
+            vec![Alert{
+                level: "danger".to_string(),
+                message: "That link has expired or was already used.".to_string(),
+            }],
+        ),
+    };
+    let new_email = itry!(user_token.new_email.ok_or::<Error>("token has no email".into()));
+    let old_email = itry!(model::get_user_by_id(user_token.user_id)).email;
+
+    // another account could have taken the address since the link was sent
+    let email_taken = itry!(model::get_optional_user_by_email(&new_email)).map_or(
+        false, |other_user| other_user.id != user_token.user_id
+    );
+    if email_taken {
+        return render_home(
+            req.extensions.get::<SessionKey>().is_some(),
+            vec![Alert{
+                level: "danger".to_string(),
+                message: format!("{} is already used by another account, so your email address hasn't been changed.", new_email),
+            }],
+        );
+    }
+
+    itry!(model::update_email(user_token.user_id, new_email.clone()));
+    // let the old address know, in case it wasn't its owner who changed it
+    let _ = mail::send(mail::Message{
+        to: old_email,
+        subject: "Your gamelog email address changed".to_string(),
+        body: format!("The email address for your gamelog account is now {}.", new_email),
+    });
+
+    render_home(
+        req.extensions.get::<SessionKey>().is_some(),
+        vec![Alert{
+            level: "success".to_string(),
+            message: format!("Your email address is now {}.", new_email),
+        }],
+    )
+}
+
+fn get_login_info_from_request(req: &mut Request) -> errors::Result<model::LoginInfo> {
+    let params = req.get_ref::<Params>().chain_err(|| "unable to get params map")?;
+
+    let username_or_email = get_param_string_from_param_map(params, "username-or-email")?;
+    let password = get_param_string_from_param_map(params, "password")?;
+
+    Ok(model::LoginInfo{
+        username_or_email: username_or_email,
+        password: password,
+    })
+}
+
+fn login(req: &mut Request) -> IronResult<Response> {
+    let login_info = itry!(get_login_info_from_request(req));
+    let ip_address = req.remote_addr.ip().to_string();
+
+    let message = match itry!(model::login(login_info, &ip_address)) {
+        model::LoginOutcome::LoggedIn(user_id) => {
+            req.extensions.insert::<SessionKey>(Session{user_id: user_id});
+            return Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))));
+        },
+        model::LoginOutcome::NeedsSecondFactor(user_id) => {
+            req.extensions.insert::<PendingLoginKey>(PendingLogin{
+                user_id: user_id,
+                expires: time::get_time().sec + PENDING_LOGIN_LIFETIME,
+            });
+            return Ok(Response::with((status::SeeOther, RedirectRaw("/login/two-factor".to_string()))));
+        },
+        model::LoginOutcome::Failed => "That username or password wasn't right.".to_string(),
+        model::LoginOutcome::Throttled(wait) => describe_login_wait(wait),
+    };
+    render_login_form(
+        req.extensions.get::<SessionKey>().is_some(),
+        vec![Alert{
+            level: "danger".to_string(),
+            message: message,
+        }],
+    )
+}
+
+/// How long someone has to give their two-factor code after their password, in seconds.
+const PENDING_LOGIN_LIFETIME: i64 = 5 * 60;
+
+fn describe_login_wait(wait: i64) -> String {
+    format!(
+        "There have been too many failed attempts to log in, try again in {}.",
+        if wait < 60 { format!("{} seconds", wait) } else { format!("{} minutes", (wait + 59) / 60) },
+    )
+}
+
+/// Checks the password someone gave to confirm a sensitive change, returning what to tell them
+/// if it wasn't accepted.
+fn check_reauthentication(req: &Request, user_id: i64, password: &str, wrong_password_message: &str) -> errors::Result<Option<String>> {
+    let ip_address = req.remote_addr.ip().to_string();
+    Ok(match model::reauthenticate(user_id, password, &ip_address)? {
+        model::LoginOutcome::LoggedIn(_) => None,
+        model::LoginOutcome::Throttled(wait) => Some(describe_login_wait(wait)),
+        _ => Some(wrong_password_message.to_string()),
+    })
+}
+
+/// Whose login is waiting on a two-factor code, if anyone's is and it hasn't expired.
+fn get_pending_login_user_id(req: &Request) -> Option<i64> {
+    req.extensions.get::<PendingLoginKey>().and_then(|pending_login| {
+        if pending_login.expires > time::get_time().sec {
+            Some(pending_login.user_id)
+        } else {
+            None
+        }
+    })
+}
+
+fn render_two_factor_login_form(alerts: Vec<Alert>) -> IronResult<Response> {
+    let mut response = Response::with((
+        status::Ok,
+        itry!(TwoFactorLoginFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: false,
+                alerts: alerts,
+            },
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn two_factor_login_form(req: &mut Request) -> IronResult<Response> {
+    if get_pending_login_user_id(req).is_none() {
+        return Ok(Response::with((status::SeeOther, RedirectRaw("/login".to_string()))));
+    }
+
+    render_two_factor_login_form(vec![])
+}
+
+fn two_factor_login(req: &mut Request) -> IronResult<Response> {
+    let user_id = match get_pending_login_user_id(req) {
+        Some(user_id) => user_id,
+        None => {
+            req.extensions.remove::<PendingLoginKey>();
+            return Ok(Response::with((status::SeeOther, RedirectRaw("/login".to_string()))));
+        },
+    };
+    let code = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        itry!(get_param_string_from_param_map(params, "code"))
+    };
+    let ip_address = req.remote_addr.ip().to_string();
+
+    let message = match itry!(model::login_second_factor(user_id, &code, &ip_address)) {
+        model::LoginOutcome::LoggedIn(user_id) => {
+            req.extensions.remove::<PendingLoginKey>();
+            req.extensions.insert::<SessionKey>(Session{user_id: user_id});
+            return Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))));
+        },
+        model::LoginOutcome::Throttled(wait) => describe_login_wait(wait),
+        _ => "That code wasn't right.".to_string(),
+    };
+    render_two_factor_login_form(vec![Alert{
+        level: "danger".to_string(),
+        message: message,
+    }])
+}
+
+fn logout(req: &mut Request) -> IronResult<Response> {
+    req.extensions.remove::<SessionKey>();
+    req.extensions.remove::<PendingLoginKey>();
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/".to_string()))))
+}
+
+fn add_user_game_form(req: &mut Request) -> IronResult<Response> {
+    redirect_logged_out_user!(req);
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(UserGameFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: req.extensions.get::<SessionKey>().is_some(),
+                alerts: vec![],
+            },
+            page_title: "Add a Game".to_string(),
+            submit_button: "Add Game".to_string(),
+            play_states: get_play_states(),
+            platforms: itry!(get_platforms()),
+            name: "".to_string(),
+            disabled_name: false,
+            set_user_game_state: "".to_string(),
+            set_platform: "".to_string(),
+            wishlisted_since: "".to_string(),
+            show_notes: false,
+            notes: "".to_string(),
+            review: "".to_string(),
+            rating: "".to_string(),
+            rating_scale: 0,
+            tags: "".to_string(),
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn add_user_game(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+    let name = itry!(get_param_string_from_param_map(params, "name"));
+    let state = itry!(get_param_string_from_param_map(params, "state"));
+    let platform = itry!(get_param_string_from_param_map(params, "platform"));
+    if !(
+        itry!(get_platforms()).iter().any(|valid_platform| platform == valid_platform.slug) &&
+        get_play_states().iter().any(|valid_play_state| state == valid_play_state.value)
+    ) {
+        return Ok(Response::with((status::BadRequest, "platform or play state not valid!")));
+    }
+
+    // TODO make sure that games with the same name don't get mixed up
+    let game_id = itry!(model::upsert_game(name));
+
+    itry!(
+        model::add_user_game(model::NewUserGame{
+            game_id: game_id,
+            user_id: user.id,
+            play_state: state,
+            platform: platform,
+            acquisition_date: time::get_time().sec,
+            start_date: None,
+            beat_date: None,
+        })
+    );
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/me".to_string()))))
 }
 
 fn me(req: &mut Request) -> IronResult<Response> {
@@ -343,28 +1185,818 @@ fn me(req: &mut Request) -> IronResult<Response> {
     ))
 }
 
-fn user_settings_form(req: &mut Request) -> IronResult<Response> {
+fn get_provider_name(slug: &str) -> String {
+    match storefront::PROVIDERS.iter().find(|&&(provider_slug, _)| provider_slug == slug) {
+        Some(&(_, name)) => name.to_string(),
+        None => slug.to_string(),
+    }
+}
+
+fn render_user_settings_form(
+    user_id: i64,
+    username: String,
+    account_input: String,
+    alerts: Vec<Alert>,
+) -> IronResult<Response> {
+    let accounts = itry!(model::get_storefront_accounts(user_id)).into_iter().map(
+        |account| StorefrontAccountPresenter{
+            id: account.id,
+            provider_name: get_provider_name(&account.provider),
+            account_id: account.account_id,
+        }
+    ).collect();
+    let user = itry!(model::get_user_by_id(user_id));
+    let two_factor_enabled = itry!(model::get_two_factor(user_id)).map(
+        |two_factor| two_factor.enabled
+    ).unwrap_or(false);
+    let recovery_codes_left = if two_factor_enabled {
+        itry!(model::count_unused_recovery_codes(user_id))
+    } else {
+        0
+    };
+    let rating_scale = user.rating_scale;
+    let rating_scales = RATING_SCALES.iter().map(|&value| RatingScalePresenter{
+        value: value,
+        selected: value == rating_scale,
+    }).collect();
+    let providers = storefront::PROVIDERS.iter().map(
+        |&(slug, name)| StorefrontProviderPresenter{
+            slug: slug.to_string(),
+            name: name.to_string(),
+        }
+    ).collect();
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(UserSettingsFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: true,
+                alerts: alerts,
+            },
+            username: username,
+            email: user.email,
+            email_verified: user.email_verified,
+            accounts: accounts,
+            providers: providers,
+            account_input: account_input,
+            rating_scales: rating_scales,
+            two_factor_enabled: two_factor_enabled,
+            recovery_codes_left: recovery_codes_left,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn user_settings_form(req: &mut Request) -> IronResult<Response> {
+    redirect_logged_out_user!(req);
+
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![])
+}
+
+fn user_settings_update(req: &mut Request) -> IronResult<Response> {
+    redirect_logged_out_user!(req);
+
+    let (username, rating_scale) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        let username = itry!(get_param_string_from_param_map(params, "username"));
+        let rating_scale = get_param_string_from_param_map(params, "rating_scale").ok().and_then(
+            |rating_scale| rating_scale.parse::<i64>().ok()
+        );
+        (username, rating_scale)
+    };
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let username = username.trim().to_string();
+    if username != user.username {
+        let username_error = match check_username(&username) {
+            Some(error) => Some(error),
+            None if itry!(model::get_optional_user_by_name(&username)).is_some() => {
+                Some("That username is taken.".to_string())
+            },
+            None => None,
+        };
+        if let Some(username_error) = username_error {
+            let alert = Alert{
+                level: "danger".to_string(),
+                message: username_error,
+            };
+            return render_user_settings_form(user.id, user.username, "".to_string(), vec![alert]);
+        }
+    }
+    if let Some(rating_scale) = rating_scale {
+        if !RATING_SCALES.contains(&rating_scale) {
+            return Ok(Response::with((status::BadRequest, "rating scale not valid!")));
+        }
+    }
+
+    // everything is checked before anything is saved, so a bad field doesn't leave a half update
+    if username != user.username {
+        itry!(model::update_username(user.id, username));
+    }
+    if let Some(rating_scale) = rating_scale {
+        itry!(model::update_rating_scale(user.id, rating_scale));
+    }
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))))
+}
+
+fn change_password(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let (current_password, password, password_confirmation) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(get_param_string_from_param_map(params, "current_password")),
+            itry!(get_param_string_from_param_map(params, "password")),
+            itry!(get_param_string_from_param_map(params, "password_confirmation")),
+        )
+    };
+
+    let reauthentication_error = itry!(check_reauthentication(
+        req,
+        user.id,
+        &current_password,
+        "Your current password wasn't right, so your password hasn't been changed.",
+    ));
+    let alert = if let Some(reauthentication_error) = reauthentication_error {
+        Alert{
+            level: "danger".to_string(),
+            message: reauthentication_error,
+        }
+    } else if password != password_confirmation {
+        Alert{
+            level: "danger".to_string(),
+            message: "The new passwords don't match, so your password hasn't been changed.".to_string(),
+        }
+    } else if let Some(password_error) = check_password(&password, &user.username, &user.email) {
+        Alert{
+            level: "danger".to_string(),
+            message: password_error,
+        }
+    } else {
+        itry!(model::update_password(user.id, &password));
+        // a reset link sent before now shouldn't be able to undo this
+        itry!(model::expire_user_tokens(user.id, model::TOKEN_RESET_PASSWORD));
+        Alert{
+            level: "success".to_string(),
+            message: "Your password has been changed.".to_string(),
+        }
+    };
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![alert])
+}
+
+/// Starts setting up two-factor authentication with a new secret, showing it as a QR code to scan.
+fn two_factor_setup_form(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    if itry!(model::get_two_factor(user.id)).map(|two_factor| two_factor.enabled).unwrap_or(false) {
+        return Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))));
+    }
+
+    let secret = itry!(two_factor::generate_secret());
+    itry!(model::start_two_factor(user.id, secret.clone()));
+    render_two_factor_setup(&user.username, secret, vec![])
+}
+
+fn render_two_factor_setup(username: &str, secret: String, alerts: Vec<Alert>) -> IronResult<Response> {
+    let mut response = Response::with((
+        status::Ok,
+        itry!(TwoFactorSetupTemplate{
+            _parent: BaseTemplate{
+                logged_in: true,
+                alerts: alerts,
+            },
+            qr_code: itry!(two_factor::qr_code_svg(&two_factor::get_provisioning_uri(&secret, username))),
+            secret: secret,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+/// Turns on two-factor authentication once a code from the new secret checks out, and shows the
+/// recovery codes, which is the only time they're shown.
+fn two_factor_setup(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let code = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        itry!(get_param_string_from_param_map(params, "code"))
+    };
+
+    let two_factor = match itry!(model::get_two_factor(user.id)) {
+        Some(ref two_factor) if two_factor.enabled => {
+            return Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))));
+        },
+        Some(two_factor) => two_factor,
+        None => return Ok(Response::with((status::SeeOther, RedirectRaw("/settings/two-factor".to_string())))),
+    };
+    let step = match itry!(two_factor::verify_code(&two_factor.secret, &code, time::get_time().sec, None)) {
+        Some(step) => step,
+        None => {
+            let alert = Alert{
+                level: "danger".to_string(),
+                message: "That code wasn't right, check the authenticator app's clock and try again.".to_string(),
+            };
+            return render_two_factor_setup(&user.username, two_factor.secret, vec![alert]);
+        },
+    };
+
+    let recovery_codes = itry!(two_factor::generate_recovery_codes());
+    itry!(model::enable_two_factor(user.id, step, &recovery_codes));
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(RecoveryCodesTemplate{
+            _parent: BaseTemplate{
+                logged_in: true,
+                alerts: vec![Alert{
+                    level: "success".to_string(),
+                    message: "Two-factor authentication is on.".to_string(),
+                }],
+            },
+            recovery_codes: recovery_codes,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn disable_two_factor(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let password = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        itry!(get_param_string_from_param_map(params, "password"))
+    };
+
+    let reauthentication_error = itry!(check_reauthentication(
+        req,
+        user.id,
+        &password,
+        "That password wasn't right, so two-factor authentication is still on.",
+    ));
+    let alert = match reauthentication_error {
+        Some(reauthentication_error) => Alert{
+            level: "danger".to_string(),
+            message: reauthentication_error,
+        },
+        None => {
+            itry!(model::disable_two_factor(user.id));
+            Alert{
+                level: "success".to_string(),
+                message: "Two-factor authentication is off.".to_string(),
+            }
+        },
+    };
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![alert])
+}
+
+fn render_delete_account_form(username: String, alerts: Vec<Alert>) -> IronResult<Response> {
+    let mut response = Response::with((
+        status::Ok,
+        itry!(DeleteAccountFormTemplate{
+            _parent: BaseTemplate{
+                logged_in: true,
+                alerts: alerts,
+            },
+            username: username,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn delete_account_form(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    render_delete_account_form(user.username, vec![])
+}
+
+/// Deletes the logged in user once they've given their password again, downloading their export
+/// on the way out if they asked for it.
+fn delete_account(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let (password, export_first) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(get_param_string_from_param_map(params, "password")),
+            get_param_string_from_param_map(params, "export").is_ok(),
+        )
+    };
+
+    let reauthentication_error = itry!(check_reauthentication(
+        req,
+        user.id,
+        &password,
+        "That password wasn't right, so your account hasn't been deleted.",
+    ));
+    if let Some(reauthentication_error) = reauthentication_error {
+        let alert = Alert{
+            level: "danger".to_string(),
+            message: reauthentication_error,
+        };
+        return render_delete_account_form(user.username, vec![alert]);
+    }
+
+    // the export has to be made before there's nothing left to export
+    let export = if export_first {
+        Some(itry!(export::to_json(&itry!(export::export_user(user.id)))))
+    } else {
+        None
+    };
+    itry!(model::delete_user(user.id));
+    req.extensions.remove::<SessionKey>();
+
+    match export {
+        Some(contents) => {
+            let mut response = Response::with((status::Ok, contents));
+            response.headers.set(ContentType::json());
+            response.headers.set_raw(
+                "Content-Disposition",
+                vec![format!("attachment; filename=\"gamelog-{}.json\"", user.username).into_bytes()],
+            );
+            Ok(response)
+        },
+        None => render_home(false, vec![Alert{
+            level: "info".to_string(),
+            message: "Your account and everything in it has been deleted.".to_string(),
+        }]),
+    }
+}
+
+fn export_account(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let format = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_string_from_param_map(params, "format").unwrap_or("json".to_string())
+    };
+
+    let account_export = itry!(export::export_user(session_user_id));
+    let (contents, content_type, extension) = match format.as_str() {
+        "json" => (itry!(export::to_json(&account_export)), ContentType::json(), "json"),
+        "csv" => (itry!(export::to_csv(&account_export)), ContentType("text/csv".parse().unwrap()), "csv"),
+        _ => return Ok(Response::with((status::BadRequest, "unknown export format!"))),
+    };
+
+    let mut response = Response::with((status::Ok, contents));
+    response.headers.set(content_type);
+    response.headers.set_raw(
+        "Content-Disposition",
+        vec![format!("attachment; filename=\"gamelog-{}.{}\"", account_export.profile.username, extension).into_bytes()],
+    );
+
+    Ok(response)
+}
+
+fn import_account(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    let json = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_file_contents_from_param_map(params, "export_file")
+    };
+
+    let result = json.and_then(|json| export::from_json(&json)).and_then(
+        |account_export| export::import_user(user.id, account_export)
+    );
+    let alert = match result {
+        Ok(summary) => Alert{
+            level: "success".to_string(),
+            message: format!("Imported {} games and {} wishlisted games", summary.imported, summary.wishlisted),
+        },
+        Err(error) => Alert{
+            level: "danger".to_string(),
+            message: format!("Couldn't import that file: {}", error),
+        },
+    };
+
+    render_user_settings_form(user.id, user.username, "".to_string(), vec![alert])
+}
+
+fn add_storefront_account(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+
+    let (provider_slug, account_input) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        let provider_slug = itry!(get_param_string_from_param_map(params, "provider"));
+        let account_input = itry!(get_param_string_from_param_map(params, "account_id"));
+        (provider_slug, account_input)
+    };
+
+    let resolved = storefront::get_provider(&provider_slug).and_then(|provider| {
+        provider.resolve_account_id(&account_input).map(|account_id| (provider.slug(), account_id))
+    });
+    let (provider_slug, account_id) = match resolved {
+        Ok(resolved) => resolved,
+        Err(error) => return render_user_settings_form(
+            user.id,
+            user.username,
+            account_input,
+            vec![Alert{
+                level: "danger".to_string(),
+                message: format!("Couldn't link that {} account: {}", get_provider_name(&provider_slug), error),
+            }],
+        ),
+    };
+
+    let already_linked = itry!(model::get_storefront_accounts(user.id)).iter().any(
+        |account| account.provider == provider_slug && account.account_id == account_id
+    );
+    if already_linked {
+        return render_user_settings_form(
+            user.id,
+            user.username,
+            account_input,
+            vec![Alert{
+                level: "warning".to_string(),
+                message: format!("That {} account is already linked.", get_provider_name(provider_slug)),
+            }],
+        );
+    }
+
+    itry!(model::add_storefront_account(model::NewStorefrontAccount{
+        user_id: user.id,
+        provider: provider_slug.to_string(),
+        account_id: account_id,
+    }));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))))
+}
+
+fn remove_storefront_account(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let account_id = {
+        let url_params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        let account_id_string = itry!(url_params.find("account_id").ok_or::<Error>("no account id provided".into()));
+        itry!(account_id_string.parse().chain_err(|| "invalid account id"))
+    };
+
+    itry!(model::remove_storefront_account(session_user_id, account_id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))))
+}
+
+fn edit_user_game_form(req: &mut Request) -> IronResult<Response> {
+    let session = try_session!(req);
+
+    let user_game_id = {
+        let params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        let user_game_id_string = itry!(params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
+        itry!(user_game_id_string.parse())
+    };
+
+    let user_game = itry!(model::get_user_game_by_id(user_game_id));
+
+    if user_game.user_id != session.user_id {
+        return Ok(Response::with((status::Forbidden, "You don't own this game!")))
+    }
+
+    let game = itry!(model::get_game_by_id(user_game.game_id));
+    let wishlisted_since = match itry!(model::get_wishlist_date(user_game.id)) {
+        Some(added_date) => format_date(added_date),
+        None => "".to_string(),
+    };
+    let rating_scale = itry!(model::get_user_by_id(session.user_id)).rating_scale;
+    let (notes, review, rating) = match itry!(model::get_user_game_note(user_game.id)) {
+        Some(note) => (
+            note.notes,
+            note.review.unwrap_or_default(),
+            note.rating.map(|rating| rating_to_scale(rating, rating_scale).to_string()).unwrap_or_default(),
+        ),
+        None => ("".to_string(), "".to_string(), "".to_string()),
+    };
+    let tags = itry!(model::get_user_game_tags(session.user_id)).remove(&user_game.id).unwrap_or_default().into_iter().map(
+        |tag| tag.name
+    ).collect::<Vec<String>>().join(", ");
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(UserGameFormTemplate{
+            _parent: BaseTemplate{logged_in: true, alerts: vec![]},
+            page_title: format!("Edit Game: {}", game.name),
+            submit_button: "Update Game".to_string(),
+            play_states: get_play_states(),
+            platforms: itry!(get_platforms()),
+            name: game.name,
+            disabled_name: true,
+            set_user_game_state: user_game.play_state,
+            set_platform: user_game.platform,
+            wishlisted_since: wishlisted_since,
+            show_notes: true,
+            notes: notes,
+            review: review,
+            rating: rating,
+            rating_scale: rating_scale,
+            tags: tags,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn edit_user_game(req: &mut Request) -> IronResult<Response> {
+    let (platform, state, notes, review, rating, tags) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        let platform = itry!(get_param_string_from_param_map(params, "platform"));
+        let state = itry!(get_param_string_from_param_map(params, "state"));
+        let notes = get_param_string_from_param_map(params, "notes").unwrap_or_default();
+        let review = get_param_string_from_param_map(params, "review").unwrap_or_default();
+        let rating = get_param_string_from_param_map(params, "rating").unwrap_or_default();
+        let tags = get_param_string_from_param_map(params, "tags").unwrap_or_default();
+        (platform, state, notes, review, rating, tags)
+    };
+
+    if !(
+        itry!(get_platforms()).iter().any(|valid_platform| platform == valid_platform.slug) &&
+        get_play_states().iter().any(|valid_play_state| state == valid_play_state.value)
+    ) {
+        return Ok(Response::with((status::BadRequest, "platform or play state not valid!")));
+    }
+
+    let user_game_id = {
+        let url_params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        let user_game_id_string = itry!(url_params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
+        itry!(user_game_id_string.parse().chain_err(|| "invalid user_game_id"))
+    };
+
+    let user_game = itry!(model::get_user_game_by_id(user_game_id));
+    let session = try_session!(req);
+    if user_game.user_id != session.user_id {
+        return Ok(Response::with((status::Forbidden, "Not your game!")))
+    }
+
+    let rating_scale = itry!(model::get_user_by_id(session.user_id)).rating_scale;
+    let rating = match rating.trim() {
+        "" => None,
+        rating => match rating.parse::<i64>() {
+            Ok(rating) if rating >= 0 && rating <= rating_scale => Some(rating_from_scale(rating, rating_scale)),
+            _ => return Ok(Response::with((status::BadRequest, "rating not valid!"))),
+        },
+    };
+
+    itry!(model::update_user_game_play_state(user_game_id, state));
+    itry!(model::update_user_game_platform(user_game_id, platform));
+    itry!(model::save_user_game_note(model::NewUserGameNote{
+        user_game_id: user_game_id,
+        notes: notes,
+        review: if review.trim() == "" { None } else { Some(review) },
+        rating: rating,
+    }));
+    let tags: Vec<String> = tags.split(',').filter(|tag| slugify(tag) != "").map(|tag| tag.trim().to_string()).collect();
+    itry!(model::set_user_game_tags(session.user_id, user_game_id, &tags));
+
+    Ok(
+        Response::with((
+            status::SeeOther,
+            RedirectRaw(format!("/collection/edit/{}", user_game_id)),
+        ))
+    )
+}
+
+fn tag_user_games(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    // games are picked with a user_game_<id> checkbox each
+    let (tag_name, action, user_game_ids) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        let user_game_ids: Vec<i64> = params.keys().filter_map(
+            |key| if key.starts_with("user_game_") { key["user_game_".len()..].parse().ok() } else { None }
+        ).collect();
+        (
+            itry!(get_param_string_from_param_map(params, "tag")),
+            get_param_string_from_param_map(params, "action").unwrap_or("add".to_string()),
+            user_game_ids,
+        )
+    };
+    if slugify(&tag_name) == "" {
+        return Ok(Response::with((status::BadRequest, "tag name not valid!")));
+    }
+
+    for user_game_id in &user_game_ids {
+        if itry!(model::get_user_game_by_id(*user_game_id)).user_id != session_user_id {
+            return Ok(Response::with((status::Forbidden, "Not your game!")))
+        }
+    }
+
+    match action.as_str() {
+        "add" => itry!(model::tag_user_games(session_user_id, &tag_name, &user_game_ids)),
+        "remove" => itry!(model::untag_user_games(session_user_id, &tag_name, &user_game_ids)),
+        _ => return Ok(Response::with((status::BadRequest, "unknown tag action!"))),
+    }
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}", session_user_id)))))
+}
+
+fn promote_user_game(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let user_game_id = {
+        let url_params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        let user_game_id_string = itry!(url_params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
+        itry!(user_game_id_string.parse().chain_err(|| "invalid user_game_id"))
+    };
+
+    let user_game = itry!(model::get_user_game_by_id(user_game_id));
+    if user_game.user_id != session_user_id {
+        return Ok(Response::with((status::Forbidden, "Not your game!")))
+    }
+
+    let achievement_counts = itry!(model::get_achievement_counts(session_user_id));
+    match achievement_counts.get(&user_game_id) {
+        Some(count) if count.is_complete() => {},
+        _ => return Ok(Response::with((status::BadRequest, "not all achievements are unlocked!"))),
+    }
+
+    itry!(model::update_user_game_play_state(user_game_id, "100_percent".to_string()));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/me".to_string()))))
+}
+
+fn get_mapping_presenters(mapping: &csv_file::ColumnMapping) -> Vec<MappingFieldPresenter> {
+    csv_file::FIELDS.iter().map(|&(field, label)| MappingFieldPresenter{
+        field: field.to_string(),
+        label: label.to_string(),
+        column: mapping.get(field).cloned().unwrap_or_default(),
+    }).collect()
+}
+
+fn render_import_csv(
+    mapping: &csv_file::ColumnMapping,
+    csv_text: String,
+    plan: Option<csv_file::ImportPlan>,
+    alerts: Vec<Alert>,
+) -> IronResult<Response> {
+    let (insert_count, update_count, skip_count) = match plan {
+        Some(ref plan) => (plan.insert_count(), plan.update_count(), plan.skip_count()),
+        None => (0, 0, 0),
+    };
+    let has_preview = plan.is_some();
+    let rows = plan.map(|plan| plan.rows).unwrap_or_default().into_iter().map(|row| {
+        let (action, row_class) = match row.action {
+            csv_file::Action::Insert => ("insert", "success"),
+            csv_file::Action::Update => ("update", "info"),
+            csv_file::Action::Skip => ("skip", ""),
+        };
+        ImportRowPresenter{
+            line: row.line,
+            name: row.name,
+            action: action.to_string(),
+            row_class: row_class.to_string(),
+            details: row.details.join(", "),
+        }
+    }).collect();
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(ImportCsvTemplate{
+            _parent: BaseTemplate{
+                logged_in: true,
+                alerts: alerts,
+            },
+            mapping: get_mapping_presenters(mapping),
+            csv_text: csv_text,
+            has_preview: has_preview,
+            rows: rows,
+            insert_count: insert_count,
+            update_count: update_count,
+            skip_count: skip_count,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn import_csv_form(req: &mut Request) -> IronResult<Response> {
     redirect_logged_out_user!(req);
 
+    render_import_csv(&csv_file::ColumnMapping::default(), "".to_string(), None, vec![])
+}
+
+fn import_csv(req: &mut Request) -> IronResult<Response> {
     let user = {
         let session = try_session!(req);
         itry!(get_user_from_session(session))
     };
 
-    let steam_id = match user.steam_id {
-        Some(id) => id,
-        None => "".to_string(),
+    let (mapping, csv_text, apply) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        let mut mapping = csv_file::ColumnMapping::default();
+        let mut mapping_error = None;
+        for &(field, _) in csv_file::FIELDS {
+            if let Ok(column) = get_param_string_from_param_map(params, &format!("map_{}", field)) {
+                if let Err(error) = mapping.set(field, column.trim().to_string()) {
+                    mapping_error = Some(error);
+                }
+            }
+        }
+        // a freshly uploaded file wins over the text carried along from a preview
+        let csv_text = get_param_file_contents_from_param_map(params, "csv_file").or_else(
+            |_| get_param_string_from_param_map(params, "csv_text")
+        ).unwrap_or_default();
+        let apply = params.find(&["apply"]).is_some();
+        (mapping_error.map_or(Ok(mapping), Err), csv_text, apply)
+    };
+
+    let mapping = match mapping {
+        Ok(mapping) => mapping,
+        Err(error) => return render_import_csv(
+            &csv_file::ColumnMapping::default(),
+            csv_text,
+            None,
+            vec![Alert{
+                level: "danger".to_string(),
+                message: format!("Invalid column mapping: {}", error),
+            }],
+        ),
+    };
+
+    let plan = csv_file::read_rows(csv_text.as_bytes(), &mapping).and_then(
+        |rows| csv_file::plan(user.id, rows)
+    );
+    let plan = match plan {
+        Ok(plan) => plan,
+        Err(error) => return render_import_csv(
+            &mapping,
+            csv_text,
+            None,
+            vec![Alert{
+                level: "danger".to_string(),
+                message: format!("Couldn't read that CSV file: {}", error),
+            }],
+        ),
+    };
+
+    if apply {
+        itry!(csv_file::apply(user.id, plan));
+        return Ok(Response::with((status::SeeOther, RedirectRaw("/me".to_string()))));
+    }
+
+    render_import_csv(&mapping, csv_text, Some(plan), vec![])
+}
+
+fn game_lists(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+    let is_owner = match req.extensions.get::<SessionKey>() {
+        Some(session) => session.user_id == user.id,
+        None => false,
     };
 
+    let lists = itry!(model::get_game_lists(user.id)).into_iter().map(
+        |game_list| GameListPresenter{
+            name: game_list.name,
+            slug: game_list.slug,
+            ranked: game_list.ranked,
+        }
+    ).collect();
+
     let mut response = Response::with((
         status::Ok,
-        itry!(UserSettingsFormTemplate{
+        itry!(GameListsTemplate{
             _parent: BaseTemplate{
                 logged_in: req.extensions.get::<SessionKey>().is_some(),
                 alerts: vec![],
             },
             username: user.username,
-            steam_id: steam_id,
+            user_id: user.id,
+            is_owner: is_owner,
+            lists: lists,
         }.render()),
     ));
     response.headers.set(ContentType::html());
@@ -372,60 +2004,434 @@ fn user_settings_form(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn get_user_settings_from_request(req: &mut Request) -> errors::Result<(String, String)> {
-    let params = req.get_ref::<Params>().chain_err(|| "unable to get params map")?;
+fn game_list(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+    let is_owner = match req.extensions.get::<SessionKey>() {
+        Some(session) => session.user_id == user.id,
+        None => false,
+    };
+
+    let slug = {
+        let params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        itry!(params.find("slug").ok_or::<Error>("no list provided".into())).to_string()
+    };
+    let game_list = match itry!(model::get_optional_game_list_by_slug(user.id, &slug)) {
+        Some(game_list) => game_list,
+        None => return Ok(Response::with((status::NotFound, "no such list!"))),
+    };
+
+    let notes = itry!(model::get_user_game_notes(user.id));
+    let entries: Vec<GameListEntryPresenter> = itry!(
+        model::get_game_list_entries_with_names(game_list.id)
+    ).into_iter().enumerate().map(|(index, (name, user_game))| GameListEntryPresenter{
+        position: index + 1,
+        user_game_id: user_game.id,
+        name: name,
+        rating: notes.get(&user_game.id).and_then(|note| note.rating).map(
+            |rating| format!("{}/{}", rating_to_scale(rating, user.rating_scale), user.rating_scale)
+        ).unwrap_or_default(),
+        play_state: user_game.play_state,
+    }).collect();
+
+    let addable_games = if is_owner {
+        let mut addable_games: Vec<AddableGamePresenter> = itry!(
+            model::get_user_games_with_names(user.id)
+        ).into_iter().filter(
+            |&(_, ref user_game)| !entries.iter().any(|entry| entry.user_game_id == user_game.id)
+        ).map(|(name, user_game)| AddableGamePresenter{
+            user_game_id: user_game.id,
+            name: name,
+        }).collect();
+        addable_games.sort_by(|a, b| a.name.cmp(&b.name));
+        addable_games
+    } else {
+        vec![]
+    };
 
-    let username = get_param_string_from_param_map(params, "username")?;
-    let steam_id = get_param_string_from_param_map(params, "steam_id")?;
+    let mut response = Response::with((
+        status::Ok,
+        itry!(GameListTemplate{
+            _parent: BaseTemplate{
+                logged_in: req.extensions.get::<SessionKey>().is_some(),
+                alerts: vec![],
+            },
+            username: user.username,
+            user_id: user.id,
+            is_owner: is_owner,
+            list_id: game_list.id,
+            name: game_list.name,
+            ranked: game_list.ranked,
+            entries: entries,
+            addable_games: addable_games,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
 
-    Ok((username, steam_id))
+    Ok(response)
 }
 
-fn user_settings_update(req: &mut Request) -> IronResult<Response> {
-    let (username, steam_id_raw) = { itry!(get_user_settings_from_request(req)) };
-    let steam_id = if steam_id_raw == "" { None } else { Some(steam_id_raw) };
-    let session = try_session!(req);
-    itry!(model::update_username(session.user_id, username));
-    itry!(model::update_steam_id(session.user_id, steam_id));
+fn add_game_list(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
 
-    Ok(Response::with((status::SeeOther, RedirectRaw("/settings".to_string()))))
+    let (name, ranked) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(get_param_string_from_param_map(params, "name")).trim().to_string(),
+            get_param_string_from_param_map(params, "ranked").is_ok(),
+        )
+    };
+    let base_slug = slugify(&name);
+    if base_slug == "" {
+        return Ok(Response::with((status::BadRequest, "list name not valid!")));
+    }
+
+    // two lists can share a name, but not a URL
+    let mut slug = base_slug.clone();
+    let mut suffix = 2;
+    while itry!(model::get_optional_game_list_by_slug(session_user_id, &slug)).is_some() {
+        slug = format!("{}-{}", base_slug, suffix);
+        suffix += 1;
+    }
+
+    itry!(model::add_game_list(model::NewGameList{
+        user_id: session_user_id,
+        name: name,
+        slug: slug.clone(),
+        ranked: ranked,
+    }));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/lists/{}", session_user_id, slug)))))
 }
 
-fn get_platforms() -> Result<Vec<Platform>, Error> {
-    let platform_config = include_str!("config/platforms.json");
-    let manufacturer_to_platforms: HashMap<String, Vec<Platform>> = serde_json::from_str(platform_config).chain_err(|| "unable to parse platforms config")?;
-    Ok(manufacturer_to_platforms.into_iter().flat_map(|(_, platforms)| platforms.into_iter()).collect())
+/// The list named in the URL, if it belongs to the logged in user.
+fn get_owned_game_list(req: &Request, session_user_id: i64) -> errors::Result<Option<model::GameList>> {
+    let params = req.extensions.get::<Router>().ok_or::<Error>("no router".into())?;
+    let list_id = params.find("list_id").ok_or::<Error>("no list id provided".into())?.parse().chain_err(
+        || "invalid list id"
+    )?;
+    let game_list = model::get_game_list_by_id(list_id)?;
+    Ok(if game_list.user_id == session_user_id { Some(game_list) } else { None })
 }
 
-fn edit_user_game_form(req: &mut Request) -> IronResult<Response> {
-    let session = try_session!(req);
+fn get_list_url(game_list: &model::GameList) -> String {
+    format!("/log/{}/lists/{}", game_list.user_id, game_list.slug)
+}
 
-    let user_game_id = {
-        let params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
-        let user_game_id_string = itry!(params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
-        itry!(user_game_id_string.parse())
+fn remove_game_list(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+    let game_list = match itry!(get_owned_game_list(req, session_user_id)) {
+        Some(game_list) => game_list,
+        None => return Ok(Response::with((status::Forbidden, "Not your list!"))),
     };
 
+    itry!(model::remove_game_list(session_user_id, game_list.id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/lists", session_user_id)))))
+}
+
+fn add_game_list_entry(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+    let game_list = match itry!(get_owned_game_list(req, session_user_id)) {
+        Some(game_list) => game_list,
+        None => return Ok(Response::with((status::Forbidden, "Not your list!"))),
+    };
+
+    let user_game_id = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        itry!(itry!(get_param_string_from_param_map(params, "user_game_id")).parse().chain_err(|| "invalid user_game_id"))
+    };
     let user_game = itry!(model::get_user_game_by_id(user_game_id));
+    if user_game.user_id != session_user_id {
+        return Ok(Response::with((status::Forbidden, "Not your game!")))
+    }
 
-    if user_game.user_id != session.user_id {
-        return Ok(Response::with((status::Forbidden, "You don't own this game!")))
+    itry!(model::add_game_list_entry(game_list.id, user_game_id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(get_list_url(&game_list)))))
+}
+
+fn remove_game_list_entry(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+    let game_list = match itry!(get_owned_game_list(req, session_user_id)) {
+        Some(game_list) => game_list,
+        None => return Ok(Response::with((status::Forbidden, "Not your list!"))),
+    };
+
+    let user_game_id = {
+        let url_params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        let user_game_id_string = itry!(url_params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
+        itry!(user_game_id_string.parse().chain_err(|| "invalid user_game_id"))
+    };
+
+    itry!(model::remove_game_list_entry(game_list.id, user_game_id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(get_list_url(&game_list)))))
+}
+
+fn reorder_game_list(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+    let game_list = match itry!(get_owned_game_list(req, session_user_id)) {
+        Some(game_list) => game_list,
+        None => return Ok(Response::with((status::Forbidden, "Not your list!"))),
+    };
+
+    let entries = itry!(model::get_game_list_entries_with_names(game_list.id));
+    let current_ids: Vec<i64> = entries.iter().map(|&(_, ref user_game)| user_game.id).collect();
+    let user_game_ids = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_ordered_user_game_ids_from_param_map(params, &current_ids)
+    };
+
+    itry!(model::reorder_game_list(game_list.id, &user_game_ids));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(get_list_url(&game_list)))))
+}
+
+fn up_next(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+    render_up_next(req, user, vec![])
+}
+
+fn render_up_next(req: &Request, user: model::User, alerts: Vec<Alert>) -> IronResult<Response> {
+    let is_owner = match req.extensions.get::<SessionKey>() {
+        Some(session) => session.user_id == user.id,
+        None => false,
+    };
+
+    let user_games = itry!(model::get_user_games_with_names(user.id));
+    let play_states: HashMap<i64, String> = user_games.iter().map(
+        |&(_, ref user_game)| (user_game.id, user_game.play_state.clone())
+    ).collect();
+    let priorities = get_priorities();
+
+    let entries: Vec<UpNextEntryPresenter> = itry!(model::get_up_next_with_names(user.id)).into_iter().enumerate().map(
+        |(index, (name, entry))| UpNextEntryPresenter{
+            position: index + 1,
+            user_game_id: entry.user_game_id,
+            name: name,
+            play_state: play_states.get(&entry.user_game_id).cloned().unwrap_or_default(),
+            priority: entry.priority,
+            priority_display: priorities.iter().find(
+                |priority| priority.value == entry.priority
+            ).map(|priority| priority.display.to_string()).unwrap_or_default(),
+            estimated_hours: entry.estimated_hours.map(|hours| hours.to_string()).unwrap_or_default(),
+            now_playing: entry.now_playing,
+        }
+    ).collect();
+    let total_hours: i64 = entries.iter().filter_map(|entry| entry.estimated_hours.parse::<i64>().ok()).sum();
+
+    // only games that are still waiting to be played can be queued
+    let addable_games = if is_owner {
+        let mut addable_games: Vec<AddableGamePresenter> = user_games.into_iter().filter(
+            |&(_, ref user_game)| (
+                !FINISHED_PLAY_STATES.contains(&user_game.play_state.as_str()) &&
+                !entries.iter().any(|entry| entry.user_game_id == user_game.id)
+            )
+        ).map(|(name, user_game)| AddableGamePresenter{
+            user_game_id: user_game.id,
+            name: name,
+        }).collect();
+        addable_games.sort_by(|a, b| a.name.cmp(&b.name));
+        addable_games
+    } else {
+        vec![]
+    };
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(UpNextTemplate{
+            _parent: BaseTemplate{
+                logged_in: req.extensions.get::<SessionKey>().is_some(),
+                alerts: alerts,
+            },
+            username: user.username,
+            user_id: user.id,
+            is_owner: is_owner,
+            now_playing_count: entries.iter().filter(|entry| entry.now_playing).count(),
+            now_playing_limit: user.now_playing_limit,
+            total_hours: total_hours,
+            entries: entries,
+            priorities: priorities,
+            addable_games: addable_games,
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn get_stat_presenters<I: Iterator<Item=(String, i64)>>(counts: I) -> Vec<StatPresenter> {
+    let counts: Vec<(String, i64)> = counts.collect();
+    let largest = counts.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    counts.into_iter().map(|(label, count)| StatPresenter{
+        label: label,
+        count: count,
+        percent: if largest > 0 { count * 100 / largest } else { 0 },
+    }).collect()
+}
+
+fn format_change(change: i64) -> String {
+    if change > 0 { format!("+{}", change) } else { change.to_string() }
+}
+
+/// How many months of acquisitions and completions the stats page compares.
+const TREND_MONTHS: usize = 12;
+
+fn stats(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+    let format = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_string_from_param_map(params, "format").unwrap_or("html".to_string())
+    };
+
+    let stats = itry!(model::get_collection_stats(user.id));
+    match format.as_str() {
+        "html" => {},
+        "json" => {
+            let mut response = Response::with((
+                status::Ok,
+                itry!(serde_json::to_string_pretty(&stats).chain_err(|| "unable to serialize stats")),
+            ));
+            response.headers.set(ContentType::json());
+            return Ok(response);
+        },
+        _ => return Ok(Response::with((status::BadRequest, "unknown stats format!"))),
     }
 
-    let game = itry!(model::get_game_by_id(user_game.game_id));
+    let play_states = get_stat_presenters(get_play_states().into_iter().filter_map(
+        |play_state| stats.by_play_state.get(play_state.value).map(|&count| (play_state.display.to_string(), count))
+    ));
+    let platform_names: HashMap<String, String> = itry!(get_platforms()).into_iter().map(
+        |platform| (platform.slug, platform.name)
+    ).collect();
+    let mut platform_counts: Vec<(String, i64)> = stats.by_platform.iter().map(
+        |(slug, &count)| (platform_names.get(slug).cloned().unwrap_or(slug.clone()), count)
+    ).collect();
+    platform_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let platforms = get_stat_presenters(platform_counts.into_iter());
+    let beaten_by_year = get_stat_presenters(stats.beaten_by_year.iter().map(|(year, &count)| (year.clone(), count)));
+
+    // the most recent months anything was acquired or beaten in
+    let mut months: Vec<&String> = stats.acquired_by_month.keys().chain(stats.beaten_by_month.keys()).collect();
+    months.sort();
+    months.dedup();
+    let recent_months = &months[months.len().saturating_sub(TREND_MONTHS)..];
+    let trend: Vec<TrendPresenter> = recent_months.iter().map(|month| {
+        let acquired = stats.acquired_by_month.get(*month).cloned().unwrap_or(0);
+        let beaten = stats.beaten_by_month.get(*month).cloned().unwrap_or(0);
+        TrendPresenter{
+            month: month.to_string(),
+            acquired: acquired,
+            beaten: beaten,
+            change: format_change(acquired - beaten),
+        }
+    }).collect();
+    let backlog_change: i64 = trend.iter().map(|month| month.acquired - month.beaten).sum();
+
+    let play_state_names: Vec<String> = get_play_states().into_iter().map(|play_state| play_state.display.to_string()).collect();
+    let play_states_by_platform: Vec<charts::Bar> = stats.by_platform_and_play_state.iter().map(
+        |(slug, counts)| charts::Bar{
+            label: platform_names.get(slug).cloned().unwrap_or(slug.clone()),
+            values: get_play_states().into_iter().map(
+                |play_state| counts.get(play_state.value).cloned().unwrap_or(0)
+            ).collect(),
+        }
+    ).collect();
+    let backlog_sizes: Vec<(String, i64)> = stats.backlog_by_month.iter().map(
+        |(month, &backlog_size)| (month.clone(), backlog_size)
+    ).collect();
 
     let mut response = Response::with((
         status::Ok,
-        itry!(UserGameFormTemplate{
+        itry!(StatsTemplate{
+            _parent: BaseTemplate{
+                logged_in: req.extensions.get::<SessionKey>().is_some(),
+                alerts: vec![],
+            },
+            username: user.username,
+            user_id: user.id,
+            total: stats.total,
+            completion_rate: stats.completion_rate.map(
+                |rate| format!("{:.0}%", rate * 100.0)
+            ).unwrap_or("n/a".to_string()),
+            average_days_to_beat: stats.average_days_to_beat.map(
+                |days| format!("{:.0} days", days)
+            ).unwrap_or("n/a".to_string()),
+            total_playtime: format!("{:.1} hours", stats.total_playtime as f64 / 60.0),
+            play_states: play_states,
+            platforms: platforms,
+            beaten_by_year: beaten_by_year,
+            trend: trend,
+            backlog_change: backlog_change,
+            play_states_by_platform_chart: charts::stacked_bar_chart(&play_state_names, &play_states_by_platform),
+            backlog_chart: charts::line_chart(&backlog_sizes),
+            completions_chart: charts::calendar_heatmap(&stats.beaten_by_day, time::now_utc()),
+        }.render()),
+    ));
+    response.headers.set(ContentType::html());
+
+    Ok(response)
+}
+
+fn year_in_review_page(req: &mut Request) -> IronResult<Response> {
+    let user = itry!(get_log_user(req));
+    let year = {
+        let params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        itry!(itry!(params.find("year").ok_or::<Error>("no year provided".into())).parse().chain_err(|| "invalid year"))
+    };
+    let format = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_string_from_param_map(params, "format").unwrap_or("html".to_string())
+    };
+
+    let review = itry!(year_in_review::year_in_review(&user, year));
+    match format.as_str() {
+        "html" => {
+            let mut response = Response::with((
+                status::Ok,
+                itry!(YearInReviewTemplate{
+                    _parent: BaseTemplate{
+                        logged_in: req.extensions.get::<SessionKey>().is_some(),
+                        alerts: vec![],
+                    },
+                    review: review,
+                }.render()),
+            ));
+            response.headers.set(ContentType::html());
+            Ok(response)
+        },
+        "export" => {
+            let mut response = Response::with((status::Ok, itry!(year_in_review::render_export(&review))));
+            response.headers.set(ContentType::html());
+            response.headers.set_raw(
+                "Content-Disposition",
+                vec![format!("attachment; filename=\"gamelog-{}-{}.html\"", review.username, year).into_bytes()],
+            );
+            Ok(response)
+        },
+        _ => Ok(Response::with((status::BadRequest, "unknown year in review format!"))),
+    }
+}
+
+fn goals_form(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let goal_progress: Vec<goals::GoalProgress> = itry!(goals::get_progress(session_user_id, time::get_time().sec)).into_iter().map(
+        |(_, progress)| progress
+    ).collect();
+    let kinds: Vec<GoalKindPresenter> = goals::GoalKind::all().into_iter().map(|kind| GoalKindPresenter{
+        slug: kind.slug().to_string(),
+        display: kind.display().to_string(),
+    }).collect();
+
+    let mut response = Response::with((
+        status::Ok,
+        itry!(GoalsTemplate{
             _parent: BaseTemplate{logged_in: true, alerts: vec![]},
-            page_title: format!("Edit Game: {}", game.name),
-            submit_button: "Update Game".to_string(),
-            play_states: get_play_states(),
+            user_id: session_user_id,
+            goals: goal_progress,
+            kinds: kinds,
             platforms: itry!(get_platforms()),
-            name: game.name,
-            disabled_name: true,
-            set_user_game_state: user_game.play_state,
-            set_platform: user_game.platform,
         }.render()),
     ));
     response.headers.set(ContentType::html());
@@ -433,59 +2439,292 @@ fn edit_user_game_form(req: &mut Request) -> IronResult<Response> {
     Ok(response)
 }
 
-fn edit_user_game(req: &mut Request) -> IronResult<Response> {
-    let (platform, state) = {
+fn add_goal(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let (kind, target, platform, start_date, end_date) = {
         let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
-        let platform = itry!(get_param_string_from_param_map(params, "platform"));
-        let state = itry!(get_param_string_from_param_map(params, "state"));
-        (platform, state)
+        (
+            get_param_string_from_param_map(params, "kind").unwrap_or_default(),
+            get_param_string_from_param_map(params, "target").unwrap_or_default(),
+            get_param_string_from_param_map(params, "platform").unwrap_or_default(),
+            get_param_string_from_param_map(params, "start_date").unwrap_or_default(),
+            get_param_string_from_param_map(params, "end_date").unwrap_or_default(),
+        )
     };
 
-    if !(
-        itry!(get_platforms()).iter().any(|valid_platform| platform == valid_platform.slug) &&
-        get_play_states().iter().any(|valid_play_state| state == valid_play_state.value)
-    ) {
-        return Ok(Response::with((status::BadRequest, "platform or play state not valid!")));
+    let kind = match goals::GoalKind::from_slug(&kind) {
+        Some(kind) => kind,
+        None => return Ok(Response::with((status::BadRequest, "goal kind not valid!"))),
+    };
+    let target = match kind {
+        goals::GoalKind::FinishPlatform => None,
+        _ => match target.trim().parse::<i64>() {
+            Ok(target) if target > 0 => Some(target),
+            _ => return Ok(Response::with((status::BadRequest, "goal target not valid!"))),
+        },
+    };
+    let platform = match kind {
+        goals::GoalKind::FinishPlatform => {
+            if !itry!(get_platforms()).iter().any(|valid_platform| platform == valid_platform.slug) {
+                return Ok(Response::with((status::BadRequest, "platform not valid!")));
+            }
+            Some(platform)
+        },
+        _ => None,
+    };
+    let parse_optional_date = |date: &str| match date.trim() {
+        "" => Ok(None),
+        date => csv_file::parse_date(date).map(Some),
+    };
+    let start_date = match parse_optional_date(&start_date) {
+        Ok(start_date) => start_date,
+        Err(_) => return Ok(Response::with((status::BadRequest, "start date not valid!"))),
+    };
+    // the end date is the last day of the goal, so it runs until the end of that day
+    let end_date = match parse_optional_date(&end_date) {
+        Ok(end_date) => end_date.map(|end_date| end_date + 86399),
+        Err(_) => return Ok(Response::with((status::BadRequest, "end date not valid!"))),
+    };
+
+    itry!(model::add_goal(model::NewGoal{
+        user_id: session_user_id,
+        kind: kind.slug().to_string(),
+        target: target,
+        platform: platform,
+        start_date: start_date,
+        end_date: end_date,
+        met_date: None,
+    }));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/goals".to_string()))))
+}
+
+fn remove_goal(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let goal_id = {
+        let params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
+        itry!(itry!(params.find("goal_id").ok_or::<Error>("no goal id provided".into())).parse().chain_err(|| "invalid goal id"))
+    };
+
+    itry!(model::remove_goal(session_user_id, goal_id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw("/goals".to_string()))))
+}
+
+fn parse_priority(priority: &str) -> Option<i64> {
+    priority.parse().ok().and_then(
+        |priority| if get_priorities().iter().any(|valid| valid.value == priority) { Some(priority) } else { None }
+    )
+}
+
+fn parse_estimated_hours(estimated_hours: &str) -> Result<Option<i64>, ()> {
+    match estimated_hours.trim() {
+        "" => Ok(None),
+        estimated_hours => match estimated_hours.parse::<i64>() {
+            Ok(hours) if hours >= 0 => Ok(Some(hours)),
+            _ => Err(()),
+        },
     }
+}
 
-    let user_game_id = {
-        let url_params = itry!(req.extensions.get::<Router>().ok_or::<Error>("no router".into()));
-        let user_game_id_string = itry!(url_params.find("user_game_id").ok_or::<Error>("no user game id provided".into()));
-        itry!(user_game_id_string.parse().chain_err(|| "invalid user_game_id"))
+fn add_up_next_entry(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let (user_game_id, priority, estimated_hours) = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        (
+            itry!(itry!(get_param_string_from_param_map(params, "user_game_id")).parse().chain_err(|| "invalid user_game_id")),
+            get_param_string_from_param_map(params, "priority").unwrap_or_default(),
+            get_param_string_from_param_map(params, "estimated_hours").unwrap_or_default(),
+        )
+    };
+    let priority = match parse_priority(&priority) {
+        Some(priority) => priority,
+        None => return Ok(Response::with((status::BadRequest, "priority not valid!"))),
+    };
+    let estimated_hours = match parse_estimated_hours(&estimated_hours) {
+        Ok(estimated_hours) => estimated_hours,
+        Err(_) => return Ok(Response::with((status::BadRequest, "estimated length not valid!"))),
     };
 
     let user_game = itry!(model::get_user_game_by_id(user_game_id));
-    let session = try_session!(req);
-    if user_game.user_id != session.user_id {
+    if user_game.user_id != session_user_id {
         return Ok(Response::with((status::Forbidden, "Not your game!")))
     }
 
-    itry!(model::update_user_game_play_state(user_game_id, state));
-    itry!(model::update_user_game_platform(user_game_id, platform));
+    itry!(model::add_up_next_entry(session_user_id, user_game_id, priority, estimated_hours));
 
-    Ok(
-        Response::with((
-            status::SeeOther,
-            RedirectRaw(format!("/collection/edit/{}", user_game_id)),
-        ))
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/up-next", session_user_id)))))
+}
+
+fn save_up_next(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let entries = itry!(model::get_up_next_with_names(session_user_id));
+    let current_ids: Vec<i64> = entries.iter().map(|&(_, ref entry)| entry.user_game_id).collect();
+    // like ranked lists, each entry has a position field, plus its own priority and estimated length
+    let mut updates = vec![];
+    let user_game_ids = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        for &(_, ref entry) in &entries {
+            let priority = get_param_string_from_param_map(
+                params,
+                &format!("priority_{}", entry.user_game_id),
+            ).ok().and_then(|priority| parse_priority(&priority)).unwrap_or(entry.priority);
+            let estimated_hours = match get_param_string_from_param_map(
+                params,
+                &format!("estimated_hours_{}", entry.user_game_id),
+            ) {
+                Ok(estimated_hours) => match parse_estimated_hours(&estimated_hours) {
+                    Ok(estimated_hours) => estimated_hours,
+                    Err(_) => return Ok(Response::with((status::BadRequest, "estimated length not valid!"))),
+                },
+                Err(_) => entry.estimated_hours,
+            };
+            if priority != entry.priority || estimated_hours != entry.estimated_hours {
+                updates.push((entry.user_game_id, priority, estimated_hours));
+            }
+        }
+        get_ordered_user_game_ids_from_param_map(params, &current_ids)
+    };
+
+    for (user_game_id, priority, estimated_hours) in updates {
+        itry!(model::update_up_next_entry(session_user_id, user_game_id, priority, estimated_hours));
+    }
+    itry!(model::reorder_up_next(session_user_id, &user_game_ids));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/up-next", session_user_id)))))
+}
+
+fn get_up_next_user_game_id(req: &Request) -> errors::Result<i64> {
+    let params = req.extensions.get::<Router>().ok_or::<Error>("no router".into())?;
+    params.find("user_game_id").ok_or::<Error>("no user game id provided".into())?.parse().chain_err(
+        || "invalid user_game_id"
     )
 }
 
+/// Starts or stops playing a queued game, as long as that doesn't go over the user's limit of
+/// games being played at once. Starting a game also marks it as started in the collection.
+fn toggle_up_next_now_playing(req: &mut Request) -> IronResult<Response> {
+    let user = {
+        let session = try_session!(req);
+        itry!(get_user_from_session(session))
+    };
+    let user_game_id = itry!(get_up_next_user_game_id(req));
+
+    let entries = itry!(model::get_up_next_with_names(user.id));
+    let now_playing = match entries.iter().find(|&&(_, ref entry)| entry.user_game_id == user_game_id) {
+        Some(&(_, ref entry)) => !entry.now_playing,
+        None => return Ok(Response::with((status::NotFound, "that game isn't up next!"))),
+    };
+
+    if now_playing {
+        let now_playing_count = entries.iter().filter(|&&(_, ref entry)| entry.now_playing).count() as i64;
+        if now_playing_count >= user.now_playing_limit {
+            let alert = Alert{
+                level: "danger".to_string(),
+                message: format!(
+                    "You're already playing {} games from your queue; finish or stop one first",
+                    now_playing_count,
+                ),
+            };
+            return render_up_next(req, user, vec![alert]);
+        }
+        itry!(model::mark_user_game_started(user_game_id, time::get_time().sec));
+    }
+    itry!(model::set_up_next_now_playing(user.id, user_game_id, now_playing));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/up-next", user.id)))))
+}
+
+fn remove_up_next_entry(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+    let user_game_id = itry!(get_up_next_user_game_id(req));
+
+    itry!(model::remove_up_next_entry(session_user_id, user_game_id));
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/up-next", session_user_id)))))
+}
+
+fn update_now_playing_limit(req: &mut Request) -> IronResult<Response> {
+    let session_user_id = try_session!(req).user_id;
+
+    let now_playing_limit = {
+        let params = itry!(req.get_ref::<Params>().chain_err(|| "unable to get params map"));
+        get_param_string_from_param_map(params, "now_playing_limit").ok().and_then(
+            |now_playing_limit| now_playing_limit.trim().parse::<i64>().ok()
+        )
+    };
+    match now_playing_limit {
+        Some(now_playing_limit) if now_playing_limit >= 1 => {
+            itry!(model::update_now_playing_limit(session_user_id, now_playing_limit))
+        },
+        _ => return Ok(Response::with((status::BadRequest, "now playing limit not valid!"))),
+    }
+
+    Ok(Response::with((status::SeeOther, RedirectRaw(format!("/log/{}/up-next", session_user_id)))))
+}
+
 pub fn routes() -> Router {
     let mut router = Router::new();
     router.get("/", home, "home");
     router.get("/log/:user", user_log, "user_log");
+    router.get("/log/:user/wishlist", wishlist, "wishlist");
+    router.get("/log/:user/lists", game_lists, "game_lists");
+    router.get("/log/:user/up-next", up_next, "up_next");
+    router.get("/log/:user/stats", stats, "stats");
+    router.get("/log/:user/year/:year", year_in_review_page, "year_in_review");
+    router.get("/log/:user/lists/:slug", game_list, "game_list");
     router.get("/me", me, "me");
     router.get("/signup", signup_form, "signup_form");
     router.post("/signup", signup, "signup");
     router.get("/login", login_form, "login_form");
     router.post("/login", login, "login");
+    router.get("/login/two-factor", two_factor_login_form, "two_factor_login_form");
+    router.post("/login/two-factor", two_factor_login, "two_factor_login");
+    router.get("/forgot-password", forgot_password_form, "forgot_password_form");
+    router.post("/forgot-password", forgot_password, "forgot_password");
+    router.get("/reset-password/:token", reset_password_form, "reset_password_form");
+    router.post("/reset-password/:token", reset_password, "reset_password");
+    router.get("/verify-email/:token", verify_email, "verify_email");
+    router.get("/confirm-email/:token", confirm_email_change, "confirm_email_change");
     router.get("/collection/add", add_user_game_form, "add_user_game_form");
     router.post("/collection/add", add_user_game, "add_user_game");
     router.get("/collection/edit/:user_game_id", edit_user_game_form, "edit_user_game_form");
     router.post("/collection/edit/:user_game_id", edit_user_game, "edit_user_game_form");
+    router.post("/collection/promote/:user_game_id", promote_user_game, "promote_user_game");
+    router.post("/collection/tags", tag_user_games, "tag_user_games");
+    router.post("/lists", add_game_list, "add_game_list");
+    router.post("/lists/:list_id/remove", remove_game_list, "remove_game_list");
+    router.post("/lists/:list_id/entries", add_game_list_entry, "add_game_list_entry");
+    router.post("/lists/:list_id/entries/:user_game_id/remove", remove_game_list_entry, "remove_game_list_entry");
+    router.post("/lists/:list_id/order", reorder_game_list, "reorder_game_list");
+    router.get("/goals", goals_form, "goals_form");
+    router.post("/goals", add_goal, "add_goal");
+    router.post("/goals/:goal_id/remove", remove_goal, "remove_goal");
+    router.post("/up-next", add_up_next_entry, "add_up_next_entry");
+    router.post("/up-next/order", save_up_next, "save_up_next");
+    router.post("/up-next/limit", update_now_playing_limit, "update_now_playing_limit");
+    router.post("/up-next/:user_game_id/now-playing", toggle_up_next_now_playing, "toggle_up_next_now_playing");
+    router.post("/up-next/:user_game_id/remove", remove_up_next_entry, "remove_up_next_entry");
+    router.get("/import/csv", import_csv_form, "import_csv_form");
+    router.post("/import/csv", import_csv, "import_csv");
     router.get("/settings", user_settings_form, "user_settings_form");
     router.post("/settings", user_settings_update, "user_settings_update");
+    router.get("/settings/export", export_account, "export_account");
+    router.post("/settings/import", import_account, "import_account");
+    router.post("/settings/email", change_email, "change_email");
+    router.post("/settings/password", change_password, "change_password");
+    router.get("/settings/two-factor", two_factor_setup_form, "two_factor_setup_form");
+    router.post("/settings/two-factor", two_factor_setup, "two_factor_setup");
+    router.post("/settings/two-factor/disable", disable_two_factor, "disable_two_factor");
+    router.get("/settings/delete", delete_account_form, "delete_account_form");
+    router.post("/settings/delete", delete_account, "delete_account");
+    router.post("/settings/verify-email", resend_verification_email, "resend_verification_email");
+    router.post("/settings/accounts", add_storefront_account, "add_storefront_account");
+    router.post("/settings/accounts/:account_id/remove", remove_storefront_account, "remove_storefront_account");
     router.get("/logout", logout, "logout");
 
     router
@@ -1,3 +1,7 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
 use iron::Request;
 use iron::prelude::*;
 use params::Params;
@@ -7,7 +11,9 @@ use errors::Error;
 use errors::ResultExt;
 use errors;
 use model;
+use serde_json;
 use session::Session;
+use time;
 
 pub fn get_user_signup_info(req: &mut Request) -> Result<model::UserSignupInfo, Error> {
     let params = req.get_ref::<Params>().chain_err(|| "unable to get params map")?;
@@ -17,12 +23,90 @@ pub fn get_user_signup_info(req: &mut Request) -> Result<model::UserSignupInfo,
     let password = get_param_string_from_param_map(params, "password")?;
 
     Ok(model::UserSignupInfo{
-        username: username,
-        email: email,
+        username: username.trim().to_string(),
+        email: email.trim().to_string(),
         password: password,
     })
 }
 
+const MIN_USERNAME_LENGTH: usize = 3;
+const MAX_USERNAME_LENGTH: usize = 32;
+const MAX_EMAIL_LENGTH: usize = 254;
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 1024;
+
+// names that would be confusing in a log URL, or that pages might want to use in the future
+const RESERVED_USERNAMES: &'static [&'static str] = &[
+    "admin", "gamelog", "login", "logout", "me", "root", "settings", "signup", "support",
+];
+
+const COMMON_PASSWORDS: &'static [&'static str] = &[
+    "12345678", "123456789", "1234567890", "password", "password1", "qwertyuiop", "iloveyou",
+    "11111111", "abc12345", "letmein1", "sunshine", "football", "baseball", "trustno1",
+];
+
+/// What's wrong with a username, if anything. Usernames show up in `/log/:user` URLs, where an
+/// all-digit name would be read as a user id.
+pub fn check_username(username: &str) -> Option<String> {
+    let length = username.chars().count();
+    if length < MIN_USERNAME_LENGTH || length > MAX_USERNAME_LENGTH {
+        return Some(format!(
+            "Usernames have to be between {} and {} characters long.",
+            MIN_USERNAME_LENGTH,
+            MAX_USERNAME_LENGTH,
+        ));
+    }
+    if !username.chars().all(|c| c.is_ascii() && (c.is_alphanumeric() || c == '_' || c == '-')) {
+        return Some("Usernames can only have letters, numbers, underscores and dashes.".to_string());
+    }
+    if username.chars().all(|c| c.is_ascii() && c.is_numeric()) {
+        return Some("Usernames need at least one letter, underscore or dash.".to_string());
+    }
+    if RESERVED_USERNAMES.contains(&username.to_lowercase().as_str()) {
+        return Some(format!("\"{}\" can't be used as a username.", username));
+    }
+    None
+}
+
+/// What's wrong with an email address, if anything. This only checks its shape; whether it's
+/// real gets checked by emailing it.
+pub fn check_email(email: &str) -> Option<String> {
+    let mut parts = email.splitn(2, '@');
+    let local = parts.next().unwrap_or("");
+    let domain = parts.next().unwrap_or("");
+    let valid = email.len() <= MAX_EMAIL_LENGTH &&
+        !email.chars().any(|c| c.is_whitespace()) &&
+        local != "" &&
+        !domain.contains('@') &&
+        domain.split('.').count() > 1 &&
+        domain.split('.').all(|label| label != "");
+    if valid {
+        None
+    } else {
+        Some("That doesn't look like an email address.".to_string())
+    }
+}
+
+/// What's wrong with a password, if anything, given the rest of what's being signed up with.
+pub fn check_password(password: &str, username: &str, email: &str) -> Option<String> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Some(format!("Passwords have to be at least {} characters long.", MIN_PASSWORD_LENGTH));
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Some(format!("Passwords can't be more than {} characters long.", MAX_PASSWORD_LENGTH));
+    }
+    let lowercase_password = password.to_lowercase();
+    if COMMON_PASSWORDS.contains(&lowercase_password.as_str()) || password.chars().all(|c| Some(c) == password.chars().next()) {
+        return Some("That password is too easy to guess.".to_string());
+    }
+    if (username != "" && lowercase_password.contains(&username.to_lowercase())) ||
+        (email != "" && lowercase_password.contains(&email.to_lowercase())) {
+        return Some("Passwords can't contain the username or email address.".to_string());
+    }
+    None
+}
+
 pub fn get_user_from_session(session: &Session) -> Result<model::User, Error> {
     model::get_user_by_id(session.user_id).chain_err(|| "can't get user from database")
 }
@@ -36,3 +120,166 @@ pub fn get_param_string_from_param_map(param_map: &params::Map, key: &str) -> er
     }
 }
 
+/// Orders user games by the `position_<user game id>` fields ranked pages post, which dragging
+/// rewrites and which can also be typed in. Missing or unreadable positions keep the current
+/// spot, and ties keep the current order.
+pub fn get_ordered_user_game_ids_from_param_map(param_map: &params::Map, user_game_ids: &[i64]) -> Vec<i64> {
+    let mut positions: Vec<(i64, usize, i64)> = user_game_ids.iter().enumerate().map(|(index, &user_game_id)| {
+        let position = get_param_string_from_param_map(
+            param_map,
+            &format!("position_{}", user_game_id),
+        ).ok().and_then(|position| position.trim().parse().ok()).unwrap_or(index as i64 + 1);
+        (position, index, user_game_id)
+    }).collect();
+    positions.sort();
+    positions.into_iter().map(|(_, _, user_game_id)| user_game_id).collect()
+}
+
+pub fn get_param_file_contents_from_param_map(param_map: &params::Map, key: &str) -> errors::Result<String> {
+    match param_map.find(
+        &[key]
+    ).ok_or::<Error>(format!("{} not provided", key).into())? {
+        &params::Value::File(ref file) => {
+            let mut contents = String::new();
+            File::open(&file.path).and_then(
+                |mut opened| opened.read_to_string(&mut contents)
+            ).chain_err(|| "unable to read uploaded file")?;
+            Ok(contents)
+        },
+        _ => Err("param isn't a file".into()),
+    }
+}
+
+/// Turns a name into something that reads well in a URL, e.g. "Best of 2017!" into `best-of-2017`.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for character in name.to_lowercase().chars() {
+        if character.is_ascii_alphanumeric() {
+            slug.push(character);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_right_matches('-').to_string()
+}
+
+pub fn format_date(timestamp: i64) -> String {
+    format_timestamp(timestamp, "%Y-%m-%d")
+}
+
+/// Formats a timestamp in UTC with a `strftime` format.
+pub fn format_timestamp(timestamp: i64, format: &str) -> String {
+    time::at_utc(time::Timespec::new(timestamp, 0)).strftime(format).map(
+        |date| date.to_string()
+    ).unwrap_or_default()
+}
+
+/// The scales a user can rate games on.
+pub const RATING_SCALES: &'static [i64] = &[5, 10, 100];
+
+/// Converts a rating on a user's scale into the out-of-100 rating that gets stored.
+pub fn rating_from_scale(rating: i64, scale: i64) -> i64 {
+    rating * 100 / scale
+}
+
+pub fn rating_to_scale(rating: i64, scale: i64) -> i64 {
+    (rating * scale + 50) / 100
+}
+
+pub struct PlayState<'a> {
+    pub display: &'a str,
+    pub value: &'a str,
+}
+
+/// Play states that mean a game is done with, one way or another.
+pub const FINISHED_PLAY_STATES: &'static [&'static str] = &["beaten", "completed", "100_percent", "wont_beat"];
+
+/// When each game was first finished: its beat date, or failing that the first time its history
+/// reached a finished play state.
+pub fn get_finish_dates(user_games: &[model::UserGame], history: &[model::UserGameHistory]) -> HashMap<i64, i64> {
+    let mut finish_dates: HashMap<i64, i64> = HashMap::new();
+    // history is oldest first, so the first entry kept is the earliest
+    for entry in history {
+        if FINISHED_PLAY_STATES.contains(&entry.play_state.as_str()) && entry.play_state != "wont_beat" {
+            finish_dates.entry(entry.user_game_id).or_insert(entry.date);
+        }
+    }
+    for user_game in user_games {
+        if let Some(beat_date) = user_game.beat_date {
+            finish_dates.insert(user_game.id, beat_date);
+        }
+    }
+    finish_dates
+}
+
+pub struct Priority<'a> {
+    pub display: &'a str,
+    pub value: i64,
+}
+
+pub fn get_priorities<'a>() -> Vec<Priority<'a>> {
+    vec![
+        Priority{
+            display: "High",
+            value: 1,
+        },
+        Priority{
+            display: "Normal",
+            value: 2,
+        },
+        Priority{
+            display: "Low",
+            value: 3,
+        },
+    ]
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Platform {
+    pub name: String,
+    pub short_name: String,
+    pub slug: String,
+}
+
+pub fn get_play_states<'a>() -> Vec<PlayState<'a>> {
+    vec![
+        PlayState{
+            display: "Unplayed",
+            value: "unplayed",
+        },
+        PlayState{
+            display: "Unfinished",
+            value: "unfinished",
+        },
+        PlayState{
+            display: "Beaten",
+            value: "beaten",
+        },
+        PlayState{
+            display: "Completed",
+            value: "completed",
+        },
+        PlayState{
+            display: "100%",
+            value: "100_percent",
+        },
+        PlayState{
+            display: "Won't Beat",
+            value: "wont_beat",
+        },
+        PlayState{
+            display: "Multiplayer",
+            value: "multiplayer",
+        },
+        PlayState{
+            display: "Null",
+            value: "null",
+        },
+    ]
+}
+
+pub fn get_platforms() -> Result<Vec<Platform>, Error> {
+    let platform_config = include_str!("config/platforms.json");
+    let manufacturer_to_platforms: HashMap<String, Vec<Platform>> = serde_json::from_str(platform_config).chain_err(|| "unable to parse platforms config")?;
+    Ok(manufacturer_to_platforms.into_iter().flat_map(|(_, platforms)| platforms.into_iter()).collect())
+}
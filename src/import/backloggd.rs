@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use errors::Error;
+use model;
+use super::ImportSummary;
+use super::TrackedGame;
+use super::csv_file::Record;
+use super::import_tracker_export;
+use super::vocabulary::Vocabulary;
+
+// Backloggd has a coarse status plus, for played games, how the playthrough ended
+const STATUSES: &'static [(&'static str, &'static str)] = &[
+    ("playing", "unfinished"),
+    ("backlog", "unplayed"),
+    ("played", "unfinished"),
+    ("completed", "beaten"),
+    ("mastered", "100_percent"),
+    ("retired", "null"),
+    ("shelved", "unfinished"),
+    ("abandoned", "wont_beat"),
+];
+
+const WISHLIST_STATUS: &'static str = "wishlist";
+
+const PLATFORMS: &'static [(&'static str, &'static str)] = &[
+    ("windows pc", "win"),
+    ("playstation portable", "psp"),
+    ("nintendo 64", "n64"),
+];
+
+fn read_game(record: &Record, vocabulary: &Vocabulary, summary: &mut ImportSummary) -> Option<TrackedGame> {
+    let name = match record.get(&["Game Name", "Game", "Name", "Title"]) {
+        Some(name) => name.clone(),
+        None => {
+            summary.add_unmapped("untitled row".to_string());
+            return None;
+        },
+    };
+
+    let status = record.get(&["Status"]).map(|status| status.to_lowercase());
+    let wishlisted = status.as_ref().map_or(false, |status| status == WISHLIST_STATUS);
+    // the more specific play type wins over the plain status
+    let play_state = if wishlisted {
+        None
+    } else {
+        record.get(&["Play Type", "Played Status"]).or(
+            record.get(&["Status"])
+        ).and_then(|status| vocabulary.play_state(status, summary))
+    };
+
+    Some(TrackedGame{
+        game: model::ImportedUserGame{
+            name: name,
+            platform: record.get(&["Platform", "Played On"]).and_then(|platform| vocabulary.platform(platform, summary)),
+            play_state: play_state,
+            acquisition_date: record.get(&["Date Added", "Added"]).and_then(|date| vocabulary.date(date, summary)),
+            start_date: record.get(&["Start Date", "Started"]).and_then(|date| vocabulary.date(date, summary)),
+            beat_date: record.get(&["Finish Date", "Finished", "Completion Date"]).and_then(
+                |date| vocabulary.date(date, summary)
+            ),
+            notes: None,
+            review: record.get(&["Review"]).cloned(),
+            // Backloggd rates out of five stars, in halves
+            rating: record.get(&["Rating"]).and_then(|rating| vocabulary.rating(rating, 5.0, summary)),
+            history: vec![],
+        },
+        wishlisted: wishlisted,
+    })
+}
+
+/// Imports a Backloggd CSV export.
+pub fn import(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    import_tracker_export(path, username, STATUSES, PLATFORMS, read_game)
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use errors::Error;
+use model;
+use super::ImportSummary;
+use super::TrackedGame;
+use super::csv_file::Record;
+use super::import_tracker_export;
+use super::vocabulary::Vocabulary;
+
+// gamelog's play states started out as Backloggery's, so most of these line up
+const STATUSES: &'static [(&'static str, &'static str)] = &[
+    ("unplayed", "unplayed"),
+    ("unfinished", "unfinished"),
+    ("beaten", "beaten"),
+    ("completed", "completed"),
+    ("mastered", "100_percent"),
+    ("endless", "null"),
+    ("none", "null"),
+    ("null", "null"),
+];
+
+const PLATFORMS: &'static [(&'static str, &'static str)] = &[
+    ("gen", "genesis"),
+    ("sat", "saturn"),
+    ("xb", "xbox"),
+    ("xb360", "360"),
+    ("x360", "360"),
+    ("xbone", "xbo"),
+    ("nsw", "switch"),
+    ("wiiu", "wii-u"),
+];
+
+fn read_game(record: &Record, vocabulary: &Vocabulary, summary: &mut ImportSummary) -> Option<TrackedGame> {
+    let name = match record.get(&["Title", "Name"]) {
+        Some(name) => name.clone(),
+        None => {
+            summary.add_unmapped("untitled row".to_string());
+            return None;
+        },
+    };
+    let priority = record.get(&["Priority"]).map(|priority| priority.to_lowercase()).unwrap_or_default();
+
+    let mut play_state = record.get(&["Status"]).and_then(|status| vocabulary.play_state(status, summary));
+    // Backloggery puts games you've given up on under a "Shelved" priority rather than a status
+    if priority == "shelved" && play_state.as_ref().map_or(true, |state| state == "unplayed" || state == "unfinished") {
+        play_state = Some("wont_beat".to_string());
+    }
+
+    Some(TrackedGame{
+        game: model::ImportedUserGame{
+            name: name,
+            platform: record.get(&["Platform", "Console", "System"]).and_then(
+                |platform| vocabulary.platform(platform, summary)
+            ),
+            play_state: play_state,
+            acquisition_date: None,
+            start_date: None,
+            beat_date: None,
+            notes: record.get(&["Notes"]).cloned(),
+            review: None,
+            rating: None,
+            history: vec![],
+        },
+        wishlisted: priority == "wishlist" || record.get(&["Ownership"]).map_or(
+            false, |ownership| ownership.to_lowercase() == "wishlist"
+        ),
+    })
+}
+
+/// Imports a Backloggery CSV export.
+pub fn import(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    import_tracker_export(path, username, STATUSES, PLATFORMS, read_game)
+}
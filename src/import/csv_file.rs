@@ -0,0 +1,381 @@
+use std::collections::HashMap;
+use std::io::Read;
+
+use csv;
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use helpers::Platform;
+use helpers::get_platforms;
+use helpers::get_play_states;
+use model;
+
+/// The fields a CSV column can be mapped to, with a label for each.
+pub const FIELDS: &'static [(&'static str, &'static str)] = &[
+    ("name", "Name"),
+    ("platform", "Platform"),
+    ("play_state", "Play State"),
+    ("acquisition_date", "Acquisition Date"),
+    ("start_date", "Start Date"),
+    ("beat_date", "Beat Date"),
+    ("notes", "Notes"),
+];
+
+/// Which CSV column holds each field. Only the name is required.
+pub struct ColumnMapping {
+    pub name: String,
+    pub platform: Option<String>,
+    pub play_state: Option<String>,
+    pub acquisition_date: Option<String>,
+    pub start_date: Option<String>,
+    pub beat_date: Option<String>,
+    pub notes: Option<String>,
+}
+
+impl ColumnMapping {
+    /// Columns named after the fields themselves.
+    pub fn default() -> ColumnMapping {
+        ColumnMapping{
+            name: "name".to_string(),
+            platform: Some("platform".to_string()),
+            play_state: Some("play_state".to_string()),
+            acquisition_date: Some("acquisition_date".to_string()),
+            start_date: Some("start_date".to_string()),
+            beat_date: Some("beat_date".to_string()),
+            notes: Some("notes".to_string()),
+        }
+    }
+
+    /// Parses a mapping like `name=Title,platform=System`, starting from the default mapping.
+    pub fn parse(spec: &str) -> Result<ColumnMapping, Error> {
+        let mut mapping = ColumnMapping::default();
+        for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let mut parts = pair.splitn(2, '=');
+            let field = parts.next().unwrap_or("").trim();
+            let column = parts.next().ok_or::<Error>(
+                format!("\"{}\" should look like field=column", pair).into()
+            )?.trim().to_string();
+            mapping.set(field, column)?;
+        }
+        Ok(mapping)
+    }
+
+    /// The column a field is mapped to, if any.
+    pub fn get(&self, field: &str) -> Option<&String> {
+        match field {
+            "name" => Some(&self.name),
+            "platform" => self.platform.as_ref(),
+            "play_state" => self.play_state.as_ref(),
+            "acquisition_date" => self.acquisition_date.as_ref(),
+            "start_date" => self.start_date.as_ref(),
+            "beat_date" => self.beat_date.as_ref(),
+            "notes" => self.notes.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Maps a field to a column, or leaves it out if the column is empty.
+    pub fn set(&mut self, field: &str, column: String) -> Result<(), Error> {
+        let column = if column == "" { None } else { Some(column) };
+        match field {
+            "name" => self.name = column.ok_or::<Error>("the name column can't be left out".into())?,
+            "platform" => self.platform = column,
+            "play_state" => self.play_state = column,
+            "acquisition_date" => self.acquisition_date = column,
+            "start_date" => self.start_date = column,
+            "beat_date" => self.beat_date = column,
+            "notes" => self.notes = column,
+            _ => return Err(format!("unknown field \"{}\"", field).into()),
+        }
+        Ok(())
+    }
+}
+
+/// A CSV row with its values picked out by a `ColumnMapping`, but not checked yet.
+pub struct RawRow {
+    pub line: u64,
+    pub name: String,
+    pub platform: Option<String>,
+    pub play_state: Option<String>,
+    pub acquisition_date: Option<String>,
+    pub start_date: Option<String>,
+    pub beat_date: Option<String>,
+    pub notes: Option<String>,
+}
+
+pub fn read_rows<R: Read>(reader: R, mapping: &ColumnMapping) -> Result<Vec<RawRow>, Error> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let headers = csv_reader.headers().chain_err(|| "unable to read CSV header")?.clone();
+
+    let find_column = |column: &String| -> Result<usize, Error> {
+        headers.iter().position(|header| header.trim() == column.as_str()).ok_or(
+            format!("there's no \"{}\" column", column).into()
+        )
+    };
+    let name_index = find_column(&mapping.name)?;
+    let optional_index = |column: &Option<String>| -> Result<Option<usize>, Error> {
+        match *column {
+            Some(ref column) => find_column(column).map(Some),
+            None => Ok(None),
+        }
+    };
+    let platform_index = optional_index(&mapping.platform)?;
+    let play_state_index = optional_index(&mapping.play_state)?;
+    let acquisition_date_index = optional_index(&mapping.acquisition_date)?;
+    let start_date_index = optional_index(&mapping.start_date)?;
+    let beat_date_index = optional_index(&mapping.beat_date)?;
+    let notes_index = optional_index(&mapping.notes)?;
+
+    let mut rows = Vec::new();
+    for record in csv_reader.records() {
+        let record = record.chain_err(|| "unable to read CSV row")?;
+        let line = record.position().map(|position| position.line()).unwrap_or(0);
+        let get = |index: Option<usize>| {
+            index.and_then(|index| record.get(index)).map(|value| value.trim().to_string()).and_then(
+                |value| if value == "" { None } else { Some(value) }
+            )
+        };
+        rows.push(RawRow{
+            line: line,
+            name: get(Some(name_index)).unwrap_or_default(),
+            platform: get(platform_index),
+            play_state: get(play_state_index),
+            acquisition_date: get(acquisition_date_index),
+            start_date: get(start_date_index),
+            beat_date: get(beat_date_index),
+            notes: get(notes_index),
+        });
+    }
+    Ok(rows)
+}
+
+/// A CSV row keyed by header, for formats whose columns are known up front.
+pub struct Record {
+    pub line: u64,
+    values: HashMap<String, String>,
+}
+
+impl Record {
+    /// The first non-empty value among the given columns, which are matched case-insensitively.
+    pub fn get(&self, columns: &[&str]) -> Option<&String> {
+        columns.iter().filter_map(
+            |column| self.values.get(&column.to_lowercase())
+        ).find(|value| value.as_str() != "")
+    }
+}
+
+pub fn read_records<R: Read>(reader: R) -> Result<Vec<Record>, Error> {
+    let mut csv_reader = csv::Reader::from_reader(reader);
+    let headers: Vec<String> = csv_reader.headers().chain_err(|| "unable to read CSV header")?.iter().map(
+        |header| header.trim().to_lowercase()
+    ).collect();
+
+    let mut records = Vec::new();
+    for record in csv_reader.records() {
+        let record = record.chain_err(|| "unable to read CSV row")?;
+        records.push(Record{
+            line: record.position().map(|position| position.line()).unwrap_or(0),
+            values: headers.iter().cloned().zip(record.iter().map(|value| value.trim().to_string())).collect(),
+        });
+    }
+    Ok(records)
+}
+
+pub enum Action {
+    Insert,
+    Update,
+    Skip,
+}
+
+/// What importing one row would do.
+pub struct PlannedRow {
+    pub line: u64,
+    pub name: String,
+    pub action: Action,
+    /// What changes for updates, or why the row is skipped.
+    pub details: Vec<String>,
+    pub imported: Option<model::ImportedUserGame>,
+}
+
+pub struct ImportPlan {
+    pub rows: Vec<PlannedRow>,
+}
+
+impl ImportPlan {
+    fn count(&self, matches: fn(&Action) -> bool) -> usize {
+        self.rows.iter().filter(|row| matches(&row.action)).count()
+    }
+
+    pub fn insert_count(&self) -> usize {
+        self.count(|action| match *action { Action::Insert => true, _ => false })
+    }
+
+    pub fn update_count(&self) -> usize {
+        self.count(|action| match *action { Action::Update => true, _ => false })
+    }
+
+    pub fn skip_count(&self) -> usize {
+        self.count(|action| match *action { Action::Skip => true, _ => false })
+    }
+
+    /// A diff-like description of the plan, one line per row.
+    pub fn describe(&self) -> Vec<String> {
+        self.rows.iter().map(|row| {
+            let marker = match row.action {
+                Action::Insert => "+",
+                Action::Update => "~",
+                Action::Skip => " ",
+            };
+            if row.details.is_empty() {
+                format!("{} line {}: {}", marker, row.line, row.name)
+            } else {
+                format!("{} line {}: {} ({})", marker, row.line, row.name, row.details.join(", "))
+            }
+        }).collect()
+    }
+}
+
+/// Matches a play state by value or display name, e.g. `100_percent` or `100%`.
+pub fn find_play_state(value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    get_play_states().into_iter().find(|play_state| {
+        play_state.value == value || play_state.display.to_lowercase() == value
+    }).map(|play_state| play_state.value.to_string())
+}
+
+/// Matches a platform by slug, name or short name, e.g. `ps4`, `PlayStation 4` or `PS4`.
+pub fn find_platform(platforms: &[Platform], value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    platforms.iter().find(|platform| {
+        platform.slug == value || platform.name.to_lowercase() == value || platform.short_name.to_lowercase() == value
+    }).map(|platform| platform.slug.clone())
+}
+
+/// Parses a `YYYY-MM-DD` date into a unix timestamp.
+pub fn parse_date(value: &str) -> Result<i64, Error> {
+    let tm = time::strptime(value, "%Y-%m-%d").chain_err(|| format!("\"{}\" isn't a YYYY-MM-DD date", value))?;
+    Ok(tm.to_timespec().sec)
+}
+
+fn parse_optional_date(value: &Option<String>) -> Result<Option<i64>, Error> {
+    match *value {
+        Some(ref value) => parse_date(value).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn check_row(row: &RawRow, platforms: &[Platform]) -> Result<model::ImportedUserGame, Error> {
+    if row.name == "" {
+        return Err("no name".into());
+    }
+    let platform = match row.platform {
+        Some(ref platform) => Some(find_platform(platforms, platform).ok_or::<Error>(
+            format!("unknown platform \"{}\"", platform).into()
+        )?),
+        None => None,
+    };
+    let play_state = match row.play_state {
+        Some(ref play_state) => Some(find_play_state(play_state).ok_or::<Error>(
+            format!("unknown play state \"{}\"", play_state).into()
+        )?),
+        None => None,
+    };
+
+    Ok(model::ImportedUserGame{
+        name: row.name.clone(),
+        platform: platform,
+        play_state: play_state,
+        acquisition_date: parse_optional_date(&row.acquisition_date)?,
+        start_date: parse_optional_date(&row.start_date)?,
+        beat_date: parse_optional_date(&row.beat_date)?,
+        notes: row.notes.clone(),
+        review: None,
+        rating: None,
+        history: vec![],
+    })
+}
+
+fn describe_changes(
+    existing: &model::UserGame,
+    existing_notes: Option<&String>,
+    imported: &model::ImportedUserGame,
+) -> Vec<String> {
+    let mut changes = Vec::new();
+    if let Some(ref platform) = imported.platform {
+        if *platform != existing.platform {
+            changes.push(format!("platform {} -> {}", existing.platform, platform));
+        }
+    }
+    if let Some(ref play_state) = imported.play_state {
+        if *play_state != existing.play_state {
+            changes.push(format!("play state {} -> {}", existing.play_state, play_state));
+        }
+    }
+    if imported.acquisition_date.map_or(false, |date| date != existing.acquisition_date) {
+        changes.push("acquisition date".to_string());
+    }
+    if imported.start_date.is_some() && imported.start_date != existing.start_date {
+        changes.push("start date".to_string());
+    }
+    if imported.beat_date.is_some() && imported.beat_date != existing.beat_date {
+        changes.push("beat date".to_string());
+    }
+    if imported.notes.is_some() && imported.notes.as_ref() != existing_notes {
+        changes.push("notes".to_string());
+    }
+    changes
+}
+
+/// Works out what importing the rows would do to a user's collection, without changing anything.
+pub fn plan(user_id: i64, rows: Vec<RawRow>) -> Result<ImportPlan, Error> {
+    let platforms = get_platforms()?;
+
+    let mut planned_rows = Vec::new();
+    for row in rows {
+        let imported = match check_row(&row, &platforms) {
+            Ok(imported) => imported,
+            Err(error) => {
+                planned_rows.push(PlannedRow{
+                    line: row.line,
+                    name: row.name,
+                    action: Action::Skip,
+                    details: vec![error.to_string()],
+                    imported: None,
+                });
+                continue;
+            },
+        };
+
+        let mut details = vec![];
+        let action = match model::get_optional_user_game_by_name(user_id, &imported.name)? {
+            None => Action::Insert,
+            Some(existing) => {
+                let existing_note = model::get_user_game_note(existing.id)?;
+                let changes = describe_changes(&existing, existing_note.as_ref().map(|note| &note.notes), &imported);
+                if changes.is_empty() {
+                    details.push("already up to date".to_string());
+                    Action::Skip
+                } else {
+                    details.extend(changes);
+                    Action::Update
+                }
+            },
+        };
+
+        planned_rows.push(PlannedRow{
+            line: row.line,
+            name: row.name,
+            imported: match action { Action::Skip => None, _ => Some(imported) },
+            action: action,
+            details: details,
+        });
+    }
+    Ok(ImportPlan{rows: planned_rows})
+}
+
+/// Carries out a plan in a single transaction.
+pub fn apply(user_id: i64, plan: ImportPlan) -> Result<(), Error> {
+    let imported: Vec<model::ImportedUserGame> = plan.rows.into_iter().filter_map(|row| row.imported).collect();
+    model::import_user_games(user_id, &imported)
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use diesel::connection::Connection;
+use diesel::expression::sql;
+use diesel::prelude::LoadDsl;
+use diesel::sqlite::SqliteConnection;
+use diesel::types::BigInt;
+use diesel::types::Nullable;
+use diesel::types::Text;
+use serde_json;
+
+use errors::Error;
+use errors::ResultExt;
+use model;
+use storefront::upsert_owned_game;
+use super::ImportSummary;
+
+// one row per owned release, DLC left out
+const OWNED_GAMES_QUERY: &'static str = "
+    SELECT LibraryReleases.releaseKey, GamePieces.value, GameTimes.minutesInGame
+    FROM LibraryReleases
+    JOIN GamePieces ON GamePieces.releaseKey = LibraryReleases.releaseKey
+    JOIN GamePieceTypes ON GamePieceTypes.id = GamePieces.gamePieceTypeId AND GamePieceTypes.type = 'title'
+    LEFT JOIN GameTimes ON GameTimes.releaseKey = LibraryReleases.releaseKey
+    LEFT JOIN ReleaseProperties ON ReleaseProperties.releaseKey = LibraryReleases.releaseKey
+    WHERE COALESCE(ReleaseProperties.isDlc, 0) = 0
+    GROUP BY LibraryReleases.releaseKey
+";
+
+#[derive(Serialize, Deserialize)]
+struct TitlePiece {
+    title: Option<String>,
+}
+
+struct GalaxyGame {
+    release_key: String,
+    title: String,
+    minutes_in_game: Option<i64>,
+}
+
+impl GalaxyGame {
+    /// Galaxy release keys look like `<integration>_<id>`, e.g. `steam_220` or `gog_1207658930`.
+    fn integration(&self) -> &str {
+        self.release_key.split('_').next().unwrap_or("")
+    }
+
+    fn steam_appid(&self) -> Option<u64> {
+        if self.integration() == "steam" {
+            self.release_key["steam_".len()..].parse().ok()
+        } else {
+            None
+        }
+    }
+}
+
+fn get_platform_slug(integration: &str) -> Option<&'static str> {
+    match integration {
+        "gog" | "steam" | "epic" | "origin" | "uplay" => Some("win"),
+        "psn" => Some("ps4"),
+        "xboxone" => Some("xbo"),
+        _ => None,
+    }
+}
+
+fn load_games(path: &Path) -> Result<Vec<GalaxyGame>, Error> {
+    // establishing a connection to a path that doesn't exist would make an empty database there
+    if !path.is_file() {
+        return Err(format!("{} doesn't exist", path.display()).into());
+    }
+    let conn = SqliteConnection::establish(
+        &path.to_string_lossy(),
+    ).chain_err(|| "unable to open GOG Galaxy database")?;
+
+    let rows: Vec<(String, String, Option<i64>)> = sql::<(Text, Text, Nullable<BigInt>)>(
+        OWNED_GAMES_QUERY,
+    ).load(&conn).chain_err(|| "unable to read owned games from GOG Galaxy database")?;
+
+    let mut games = Vec::new();
+    for (release_key, title_json, minutes_in_game) in rows {
+        let title_piece: TitlePiece = serde_json::from_str(&title_json).chain_err(
+            || format!("unable to parse title of {}", release_key)
+        )?;
+        if let Some(title) = title_piece.title {
+            games.push(GalaxyGame{
+                release_key: release_key,
+                title: title,
+                minutes_in_game: minutes_in_game,
+            });
+        }
+    }
+    Ok(games)
+}
+
+fn find_or_insert_game(game: &GalaxyGame) -> Result<i64, Error> {
+    if let Some(appid) = game.steam_appid() {
+        return match model::get_optional_game_by_steam_id(appid)? {
+            Some(game_row) => Ok(game_row.id),
+            None => model::insert_game(model::NewGame{
+                name: game.title.clone(),
+                steam_id: Some(appid as i64),
+            }),
+        };
+    }
+    model::upsert_game(game.title.clone())
+}
+
+/// Adds everything owned in a GOG Galaxy 2.0 database (`galaxy-2.0.db`) to a user's collection.
+pub fn import(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    let user = model::get_user_by_name(username.to_string())?;
+    let games = load_games(path)?;
+
+    let mut summary = ImportSummary::new();
+    for game in games {
+        let platform = match get_platform_slug(game.integration()) {
+            Some(slug) => slug.to_string(),
+            None => {
+                summary.add_unmapped(format!("platform \"{}\"", game.integration()));
+                "other".to_string()
+            },
+        };
+
+        let game_id = find_or_insert_game(&game)?;
+        upsert_owned_game(user.id, game_id, platform, game.minutes_in_game)?;
+        summary.imported += 1;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use errors::Error;
+use model;
+use super::ImportSummary;
+use super::TrackedGame;
+use super::csv_file::Record;
+use super::import_tracker_export;
+use super::vocabulary::Vocabulary;
+
+// Grouvee's default shelves, in the order they take precedence when a game is on several
+const STATUSES: &'static [(&'static str, &'static str)] = &[
+    ("playing", "unfinished"),
+    // finished playthroughs are recorded in the dates column, which overrides this
+    ("played", "unfinished"),
+    ("beaten", "beaten"),
+    ("completed", "completed"),
+    ("backlog", "unplayed"),
+    ("nope", "wont_beat"),
+    ("abandoned", "wont_beat"),
+];
+
+const WISHLIST_SHELF: &'static str = "wish list";
+
+// how far a finished playthrough got, which says more than the shelf does
+const COMPLETION_LEVELS: &'static [(&'static str, &'static str)] = &[
+    ("main story", "beaten"),
+    ("main story + extras", "completed"),
+    ("100% completion", "100_percent"),
+];
+
+const PLATFORMS: &'static [(&'static str, &'static str)] = &[
+    ("mac", "mac"),
+    ("playstation network (ps3)", "ps3"),
+    ("playstation network (vita)", "vita"),
+    ("xbox 360 games store", "360"),
+    ("wii shop", "wii"),
+];
+
+/// One entry of the `dates` column.
+#[derive(Deserialize)]
+struct Playthrough {
+    date_started: Option<String>,
+    date_finished: Option<String>,
+    level_of_completion: Option<String>,
+}
+
+/// Grouvee stores shelves, platforms and playthroughs as JSON inside the CSV.
+fn parse_json_column<T: DeserializeOwned + Default>(record: &Record, column: &str, summary: &mut ImportSummary) -> T {
+    match record.get(&[column]) {
+        Some(value) => serde_json::from_str(value).unwrap_or_else(|_| {
+            summary.add_unmapped(format!("{} \"{}\"", column, value));
+            T::default()
+        }),
+        None => T::default(),
+    }
+}
+
+fn read_game(record: &Record, vocabulary: &Vocabulary, summary: &mut ImportSummary) -> Option<TrackedGame> {
+    let name = match record.get(&["name"]) {
+        Some(name) => name.clone(),
+        None => {
+            summary.add_unmapped("untitled row".to_string());
+            return None;
+        },
+    };
+    let shelves: BTreeMap<String, serde_json::Value> = parse_json_column(record, "shelves", summary);
+    let platforms: BTreeMap<String, serde_json::Value> = parse_json_column(record, "platforms", summary);
+    let playthroughs: Vec<Playthrough> = parse_json_column(record, "dates", summary);
+
+    let shelf_names: Vec<String> = shelves.keys().map(|shelf| shelf.to_lowercase()).collect();
+    let wishlisted = shelf_names.iter().any(|shelf| shelf == WISHLIST_SHELF) && shelf_names.len() == 1;
+
+    let mut play_state = None;
+    for &(shelf, state) in STATUSES {
+        if shelf_names.iter().any(|name| name == shelf) {
+            play_state = Some(state.to_string());
+            break;
+        }
+    }
+    // custom shelves are the user's own categories, so they only matter when nothing else says what state a game is in
+    if play_state.is_none() && !wishlisted {
+        for shelf in shelves.keys().filter(|shelf| shelf.to_lowercase() != WISHLIST_SHELF) {
+            summary.add_unmapped(format!("shelf \"{}\"", shelf));
+        }
+    }
+
+    let finished = playthroughs.iter().rev().find(|playthrough| playthrough.date_finished.is_some());
+    if let Some(level) = finished.and_then(|playthrough| playthrough.level_of_completion.as_ref()) {
+        match COMPLETION_LEVELS.iter().find(|&&(name, _)| name == level.to_lowercase()) {
+            Some(&(_, state)) => play_state = Some(state.to_string()),
+            None => summary.add_unmapped(format!("level of completion \"{}\"", level)),
+        }
+    }
+
+    let start_date = playthroughs.iter().filter_map(
+        |playthrough| playthrough.date_started.as_ref()
+    ).next().and_then(|date| vocabulary.date(date, summary));
+    let beat_date = finished.and_then(
+        |playthrough| playthrough.date_finished.as_ref()
+    ).and_then(|date| vocabulary.date(date, summary));
+
+    // a game can be on more than one platform, but the collection only has room for one
+    let platform = platforms.keys().next().and_then(|platform| vocabulary.platform(platform, summary));
+
+    Some(TrackedGame{
+        game: model::ImportedUserGame{
+            name: name,
+            platform: platform,
+            play_state: play_state,
+            acquisition_date: None,
+            start_date: start_date,
+            beat_date: beat_date,
+            notes: None,
+            review: record.get(&["review"]).cloned(),
+            // Grouvee rates out of five stars
+            rating: record.get(&["rating"]).and_then(|rating| vocabulary.rating(rating, 5.0, summary)),
+            history: vec![],
+        },
+        wishlisted: wishlisted,
+    })
+}
+
+/// Imports a Grouvee CSV export.
+pub fn import(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    import_tracker_export(path, username, STATUSES, PLATFORMS, read_game)
+}
@@ -0,0 +1,87 @@
+use std::path::Path;
+
+use errors::Error;
+use model;
+use super::ImportSummary;
+use super::TrackedGame;
+use super::csv_file::Record;
+use super::import_tracker_export;
+use super::vocabulary::Vocabulary;
+
+// HowLongToBeat marks each list a game is on with its own column rather than a single status,
+// checked here in order of precedence
+const STATUSES: &'static [(&'static str, &'static str)] = &[
+    ("completed", "beaten"),
+    ("retired", "wont_beat"),
+    ("playing", "unfinished"),
+    ("replay", "beaten"),
+    ("backlog", "unplayed"),
+];
+
+const WISHLIST_COLUMN: &'static str = "wishlist";
+
+// the list columns that don't say anything about play state
+const CUSTOM_LISTS: &'static [&'static str] = &["custom", "custom 2", "custom 3"];
+
+const PLATFORMS: &'static [(&'static str, &'static str)] = &[
+    ("pc", "win"),
+    ("nintendo 64", "n64"),
+    ("game cube", "gc"),
+];
+
+fn is_on_list(record: &Record, list: &str) -> bool {
+    match record.get(&[list]) {
+        Some(value) => match value.to_lowercase().as_str() {
+            "0" | "no" | "false" => false,
+            _ => true,
+        },
+        None => false,
+    }
+}
+
+fn read_game(record: &Record, vocabulary: &Vocabulary, summary: &mut ImportSummary) -> Option<TrackedGame> {
+    let name = match record.get(&["Title", "Game", "Name"]) {
+        Some(name) => name.clone(),
+        None => {
+            summary.add_unmapped("untitled row".to_string());
+            return None;
+        },
+    };
+
+    let mut play_state = STATUSES.iter().find(
+        |&&(list, _)| is_on_list(record, list)
+    ).map(|&(_, state)| state.to_string());
+    // a completion time in the completionist column means everything was done
+    if play_state.as_ref().map_or(false, |state| state == "beaten") && record.get(&["Completionist", "100%"]).is_some() {
+        play_state = Some("100_percent".to_string());
+    }
+    let wishlisted = play_state.is_none() && is_on_list(record, WISHLIST_COLUMN);
+    if play_state.is_none() && !wishlisted && !CUSTOM_LISTS.iter().any(|list| is_on_list(record, list)) {
+        summary.add_unmapped("game on no list".to_string());
+    }
+
+    Some(TrackedGame{
+        game: model::ImportedUserGame{
+            name: name,
+            platform: record.get(&["Platform"]).and_then(|platform| vocabulary.platform(platform, summary)),
+            play_state: play_state,
+            acquisition_date: record.get(&["Added Date", "Date Added", "Added"]).and_then(
+                |date| vocabulary.date(date, summary)
+            ),
+            start_date: record.get(&["Start Date", "Started"]).and_then(|date| vocabulary.date(date, summary)),
+            beat_date: record.get(&["Completion Date", "Finish Date", "Finished"]).and_then(
+                |date| vocabulary.date(date, summary)
+            ),
+            notes: None,
+            review: record.get(&["Review Notes"]).cloned(),
+            rating: record.get(&["Review"]).and_then(|rating| vocabulary.rating(rating, 100.0, summary)),
+            history: vec![],
+        },
+        wishlisted: wishlisted,
+    })
+}
+
+/// Imports a HowLongToBeat CSV export.
+pub fn import(path: &Path, username: &str) -> Result<ImportSummary, Error> {
+    import_tracker_export(path, username, STATUSES, PLATFORMS, read_game)
+}
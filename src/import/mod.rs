@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::Path;
+
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use model;
+use self::csv_file::Record;
+use self::vocabulary::Vocabulary;
+
+pub mod backloggd;
+pub mod backloggery;
+pub mod csv_file;
+pub mod gog_galaxy;
+pub mod grouvee;
+pub mod howlongtobeat;
+pub mod vocabulary;
+
+/// What an import did, for reporting back to whoever ran it.
+pub struct ImportSummary {
+    pub imported: usize,
+    pub wishlisted: usize,
+    /// Values from the source that had no gamelog equivalent, with how often each came up.
+    pub unmapped: BTreeMap<String, usize>,
+}
+
+impl ImportSummary {
+    pub fn new() -> ImportSummary {
+        ImportSummary{
+            imported: 0,
+            wishlisted: 0,
+            unmapped: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_unmapped(&mut self, value: String) {
+        *self.unmapped.entry(value).or_insert(0) += 1;
+    }
+
+    pub fn print(&self) {
+        println!("imported {} games", self.imported);
+        if self.wishlisted > 0 {
+            println!("wishlisted {} games", self.wishlisted);
+        }
+        for (value, count) in &self.unmapped {
+            println!("unmapped: {} ({} games)", value, count);
+        }
+    }
+}
+
+/// A game read from another tracker's export.
+pub struct TrackedGame {
+    pub game: model::ImportedUserGame,
+    /// The tracker has it as wanted rather than owned.
+    pub wishlisted: bool,
+}
+
+fn read_csv_file(path: &Path) -> Result<Vec<csv_file::Record>, Error> {
+    let file = File::open(path).chain_err(|| format!("unable to open {}", path.display()))?;
+    csv_file::read_records(file)
+}
+
+/// Adds owned games to the collection in one transaction, and wanted games that aren't owned
+/// yet to the wishlist.
+fn save_tracked_games(username: &str, games: Vec<TrackedGame>, summary: &mut ImportSummary) -> Result<(), Error> {
+    let user = model::get_user_by_name(username.to_string())?;
+    let (wishlisted, owned): (Vec<TrackedGame>, Vec<TrackedGame>) = games.into_iter().partition(
+        |game| game.wishlisted
+    );
+
+    let owned: Vec<model::ImportedUserGame> = owned.into_iter().map(|game| game.game).collect();
+    model::import_user_games(user.id, &owned)?;
+    summary.imported += owned.len();
+
+    for game in wishlisted {
+        if model::get_optional_user_game_by_name(user.id, &game.game.name)?.is_some() {
+            continue;
+        }
+        let added_date = game.game.acquisition_date.unwrap_or(time::get_time().sec);
+        let game_id = model::upsert_game(game.game.name)?;
+        model::upsert_wishlist_game(model::NewWishlistGame{
+            user_id: user.id,
+            game_id: game_id,
+            added_date: added_date,
+            user_game_id: None,
+        })?;
+        summary.wishlisted += 1;
+    }
+    Ok(())
+}
+
+/// Imports another tracker's CSV export, turning each record into a game with `read_game`.
+///
+/// `statuses` and `platforms` map the tracker's names to gamelog's, see `Vocabulary`.
+pub fn import_tracker_export(
+    path: &Path,
+    username: &str,
+    statuses: &'static [(&'static str, &'static str)],
+    platforms: &'static [(&'static str, &'static str)],
+    read_game: fn(&Record, &Vocabulary, &mut ImportSummary) -> Option<TrackedGame>,
+) -> Result<ImportSummary, Error> {
+    let records = read_csv_file(path)?;
+    let vocabulary = Vocabulary::new(statuses, platforms)?;
+
+    let mut summary = ImportSummary::new();
+    let games = records.iter().filter_map(|record| read_game(record, &vocabulary, &mut summary)).collect();
+    save_tracked_games(username, games, &mut summary)?;
+    Ok(summary)
+}
@@ -0,0 +1,115 @@
+use errors::Error;
+use helpers::Platform;
+use helpers::get_platforms;
+use super::ImportSummary;
+use super::csv_file::find_platform;
+use super::csv_file::find_play_state;
+use super::csv_file::parse_date;
+
+/// Platform names trackers commonly use that aren't gamelog names or short names.
+const COMMON_PLATFORMS: &'static [(&'static str, &'static str)] = &[
+    ("pc", "win"),
+    ("pc (microsoft windows)", "win"),
+    ("microsoft windows", "win"),
+    ("steam", "win"),
+    ("macintosh", "mac"),
+    ("mac os", "mac"),
+    ("macos", "mac"),
+    ("playstation 1", "psx"),
+    ("ps1", "psx"),
+    ("ps", "psx"),
+    ("sony playstation", "psx"),
+    ("playstation vita", "vita"),
+    ("ps vita", "vita"),
+    ("xbox one", "xbo"),
+    ("xb1", "xbo"),
+    ("xone", "xbo"),
+    ("nes", "nes"),
+    ("nintendo entertainment system (nes)", "nes"),
+    ("super nintendo", "snes"),
+    ("super nes", "snes"),
+    ("super nintendo entertainment system (snes)", "snes"),
+    ("nintendo gamecube", "gc"),
+    ("gcn", "gc"),
+    ("wii u", "wii-u"),
+    ("wiiu", "wii-u"),
+    ("nintendo switch", "switch"),
+    ("nintendo ds", "ds"),
+    ("nintendo 3ds", "3ds"),
+    ("new nintendo 3ds", "3ds"),
+    ("sega genesis", "genesis"),
+    ("sega mega drive/genesis", "genesis"),
+    ("mega drive", "genesis"),
+    ("sega saturn", "saturn"),
+    ("sega dreamcast", "dc"),
+    ("dreamcast", "dc"),
+];
+
+/// Translates one tracker's play statuses and platform names into gamelog's.
+///
+/// The tracker's own tables are checked first, then gamelog's names, and anything left over is
+/// reported on the import summary.
+pub struct Vocabulary {
+    statuses: &'static [(&'static str, &'static str)],
+    platform_aliases: &'static [(&'static str, &'static str)],
+    platforms: Vec<Platform>,
+}
+
+fn find_alias(aliases: &[(&str, &'static str)], value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    aliases.iter().find(|&&(alias, _)| alias == value).map(|&(_, slug)| slug.to_string())
+}
+
+impl Vocabulary {
+    /// Both tables are keyed by lowercase names.
+    pub fn new(
+        statuses: &'static [(&'static str, &'static str)],
+        platform_aliases: &'static [(&'static str, &'static str)],
+    ) -> Result<Vocabulary, Error> {
+        Ok(Vocabulary{
+            statuses: statuses,
+            platform_aliases: platform_aliases,
+            platforms: get_platforms()?,
+        })
+    }
+
+    pub fn play_state(&self, status: &str, summary: &mut ImportSummary) -> Option<String> {
+        let play_state = find_alias(self.statuses, status).or_else(|| find_play_state(status));
+        if play_state.is_none() {
+            summary.add_unmapped(format!("status \"{}\"", status));
+        }
+        play_state
+    }
+
+    pub fn platform(&self, name: &str, summary: &mut ImportSummary) -> Option<String> {
+        let platform = find_alias(self.platform_aliases, name).or_else(
+            || find_alias(COMMON_PLATFORMS, name)
+        ).or_else(
+            || find_platform(&self.platforms, name)
+        );
+        if platform.is_none() {
+            summary.add_unmapped(format!("platform \"{}\"", name));
+        }
+        platform
+    }
+
+    /// Reads the date part of a `YYYY-MM-DD` or ISO 8601 timestamp.
+    pub fn date(&self, value: &str, summary: &mut ImportSummary) -> Option<i64> {
+        let date = value.get(..10).and_then(|date| parse_date(date).ok());
+        if date.is_none() {
+            summary.add_unmapped(format!("date \"{}\"", value));
+        }
+        date
+    }
+
+    /// Converts a rating out of `out_of` into one out of 100, the way gamelog stores them.
+    pub fn rating(&self, value: &str, out_of: f64, summary: &mut ImportSummary) -> Option<i64> {
+        let rating = value.parse::<f64>().ok().and_then(|rating| {
+            if rating >= 0.0 && rating <= out_of { Some((rating * 100.0 / out_of).round() as i64) } else { None }
+        });
+        if rating.is_none() {
+            summary.add_unmapped(format!("rating \"{}\"", value));
+        }
+        rating
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs::File;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use lettre::Transport;
+use lettre::smtp::SmtpClient;
+use lettre::smtp::authentication::Credentials;
+use lettre_email::EmailBuilder;
+use rand::OsRng;
+use rand::Rng;
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use secrets::MailConfig;
+use secrets::get_secrets;
+
+/// Where links in emails point when `base_url` isn't set in `secrets.json`.
+const DEFAULT_BASE_URL: &'static str = "http://localhost:3000";
+
+pub struct Message {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Something that can deliver email, picked by `get_transport`.
+pub trait MailTransport {
+    fn send(&self, message: &Message) -> Result<(), Error>;
+}
+
+pub struct SmtpMailTransport {
+    host: String,
+    credentials: Option<Credentials>,
+    from: String,
+}
+
+impl MailTransport for SmtpMailTransport {
+    fn send(&self, message: &Message) -> Result<(), Error> {
+        let email = EmailBuilder::new().to(
+            message.to.as_str(),
+        ).from(
+            self.from.as_str(),
+        ).subject(
+            message.subject.as_str(),
+        ).text(
+            message.body.as_str(),
+        ).build().chain_err(|| "unable to build email")?;
+
+        let mut client = SmtpClient::new_simple(
+            self.host.as_str(),
+        ).chain_err(|| format!("unable to connect to {}", self.host))?;
+        if let Some(ref credentials) = self.credentials {
+            client = client.credentials(credentials.clone());
+        }
+        client.transport().send(email.into()).chain_err(|| format!("unable to send email to {}", message.to))?;
+        Ok(())
+    }
+}
+
+/// Writes each email to its own file in a directory, for local development and tests.
+pub struct FileMailTransport {
+    directory: PathBuf,
+}
+
+impl MailTransport for FileMailTransport {
+    fn send(&self, message: &Message) -> Result<(), Error> {
+        fs::create_dir_all(&self.directory).chain_err(|| "unable to create mail directory")?;
+        let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
+        let path = self.directory.join(format!("{}-{:08x}.eml", time::get_time().sec, rng.next_u32()));
+        File::create(&path).and_then(
+            |mut file| file.write_all(format_message(message).as_bytes())
+        ).chain_err(|| format!("unable to write {}", path.display()))
+    }
+}
+
+pub struct StdoutMailTransport;
+
+impl MailTransport for StdoutMailTransport {
+    fn send(&self, message: &Message) -> Result<(), Error> {
+        println!("{}", format_message(message));
+        Ok(())
+    }
+}
+
+fn format_message(message: &Message) -> String {
+    format!("To: {}\nSubject: {}\n\n{}\n", message.to, message.subject, message.body)
+}
+
+/// The transport configured under `mail` in `secrets.json`, or stdout if there isn't one.
+pub fn get_transport() -> Result<Box<MailTransport>, Error> {
+    let config = match get_secrets() {
+        Ok(secrets) => secrets.mail,
+        Err(_) => None,
+    };
+    Ok(match config {
+        Some(MailConfig::Smtp{host, username, password, from}) => Box::new(SmtpMailTransport{
+            host: host,
+            credentials: match (username, password) {
+                (Some(username), Some(password)) => Some(Credentials::new(username, password)),
+                _ => None,
+            },
+            from: from,
+        }),
+        Some(MailConfig::File{directory}) => Box::new(FileMailTransport{directory: PathBuf::from(directory)}),
+        Some(MailConfig::Stdout) | None => Box::new(StdoutMailTransport),
+    })
+}
+
+/// Turns a path like `/verify-email/...` into a link that works from an email.
+pub fn get_link(path: &str) -> String {
+    let base_url = get_secrets().ok().and_then(|secrets| secrets.base_url).unwrap_or(DEFAULT_BASE_URL.to_string());
+    format!("{}{}", base_url.trim_right_matches('/'), path)
+}
+
+pub fn send(message: Message) -> Result<(), Error> {
+    get_transport()?.send(&message)
+}
@@ -1,14 +1,27 @@
+extern crate ammonia;
+extern crate argon2;
+extern crate base32;
 extern crate bcrypt;
+extern crate csv;
 extern crate env_logger;
+extern crate flate2;
 extern crate futures;
+extern crate hmac;
 extern crate hyper;
+extern crate lettre;
+extern crate lettre_email;
+extern crate libsqlite3_sys;
 extern crate logger;
 extern crate params;
+extern crate pulldown_cmark;
+extern crate qrcode;
 extern crate rand;
 extern crate router;
 extern crate secure_session;
 extern crate serde;
 extern crate serde_json;
+extern crate sha1;
+extern crate sha2;
 extern crate time;
 extern crate tokio_core;
 extern crate typemap;
@@ -21,6 +34,9 @@ extern crate typemap;
 
 #[macro_use(itry)] extern crate iron;
 
+use std::io::Write;
+use std::path::Path;
+
 use iron::Chain;
 use iron::Iron;
 use logger::Logger;
@@ -29,20 +45,52 @@ use secure_session::middleware::SessionMiddleware;
 use secure_session::session::ChaCha20Poly1305SessionManager;
 use secure_session::session::SessionManager;
 
+mod backup;
+mod charts;
+mod export;
+mod goals;
 mod handlers;
 mod helpers;
+mod import;
+mod mail;
+mod markdown;
 mod model;
+mod password;
 mod secrets;
 mod session;
 mod steam;
+mod storefront;
+mod two_factor;
+mod year_in_review;
 
+use errors::ResultExt;
 use handlers::routes;
-use session::Session;
-use session::SessionKey;
-use steam::sync as steam_sync;
+use session::SessionSplitter;
+use session::StoredSession;
+use session::StoredSessionKey;
+use storefront::SyncOptions;
 
 mod errors {
-    error_chain! { }
+    error_chain! {
+        errors {
+            SteamPrivateProfile(steam_id: String) {
+                description("steam profile is private")
+                display("Steam profile {} is private or hides its game details", steam_id)
+            }
+            SteamInvalidKey {
+                description("steam api key was rejected")
+                display("the Steam Web API key was rejected")
+            }
+            SteamThrottled {
+                description("steam is throttling requests")
+                display("Steam is throttling requests, try again later")
+            }
+            StorefrontAccountPrivate(account_id: String) {
+                description("storefront account is private")
+                display("account {} is private or hides its games", account_id)
+            }
+        }
+    }
 }
 
 fn webapp() {
@@ -53,10 +101,12 @@ fn webapp() {
     chain.link(Logger::new(None));
 
     // TODO make password configurable
-    let session_manager = ChaCha20Poly1305SessionManager::<Session>::from_password(b"foo");
+    let session_manager = ChaCha20Poly1305SessionManager::<StoredSession>::from_password(b"foo");
     let session_config = SessionConfig::default();
+    // the splitter has to be inside the session middleware, so it's linked first
+    chain.link_around(SessionSplitter);
     chain.link_around(
-        SessionMiddleware::<Session, SessionKey, ChaCha20Poly1305SessionManager<Session>>::new(
+        SessionMiddleware::<StoredSession, StoredSessionKey, ChaCha20Poly1305SessionManager<StoredSession>>::new(
             session_manager,
             session_config,
         )
@@ -65,11 +115,140 @@ fn webapp() {
     Iron::new(chain).http("0.0.0.0:3000").unwrap();
 }
 
+/// The value after a command line option, e.g. the username in `--user <username>`.
+fn get_option<'a>(args: &'a [String], option: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == option).and_then(|position| args.get(position + 1))
+}
+
+fn run_import(args: &[String]) -> errors::Result<()> {
+    let username = get_option(args, "--user").ok_or::<errors::Error>("--user is required".into())?;
+
+    let summary = match (args.get(2).map(|arg| arg.as_str()), args.get(3)) {
+        (Some("gog-galaxy"), Some(path)) => import::gog_galaxy::import(Path::new(path), username)?,
+        (Some("backloggery"), Some(path)) => import::backloggery::import(Path::new(path), username)?,
+        (Some("grouvee"), Some(path)) => import::grouvee::import(Path::new(path), username)?,
+        (Some("howlongtobeat"), Some(path)) => import::howlongtobeat::import(Path::new(path), username)?,
+        (Some("backloggd"), Some(path)) => import::backloggd::import(Path::new(path), username)?,
+        (Some("gamelog"), Some(path)) => export::import_file(Path::new(path), username)?,
+        (Some("csv"), Some(path)) => return run_csv_import(
+            Path::new(path),
+            username,
+            get_option(args, "--map").map(|spec| spec.as_str()),
+            args.iter().any(|arg| arg == "--apply"),
+        ),
+        _ => return Err(
+            "usage: gamelog import (gamelog|gog-galaxy|backloggery|grouvee|howlongtobeat|backloggd|csv) <path> --user <username> [--map field=column,...] [--apply]".into()
+        ),
+    };
+    summary.print();
+    Ok(())
+}
+
+fn run_csv_import(path: &Path, username: &String, map: Option<&str>, apply: bool) -> errors::Result<()> {
+    let mapping = match map {
+        Some(spec) => import::csv_file::ColumnMapping::parse(spec)?,
+        None => import::csv_file::ColumnMapping::default(),
+    };
+    let user = model::get_user_by_name(username.clone())?;
+    let file = std::fs::File::open(path).chain_err(|| format!("unable to open {}", path.display()))?;
+    let rows = import::csv_file::read_rows(file, &mapping)?;
+    let plan = import::csv_file::plan(user.id, rows)?;
+
+    for line in plan.describe() {
+        println!("{}", line);
+    }
+    println!(
+        "{} to insert, {} to update, {} skipped",
+        plan.insert_count(),
+        plan.update_count(),
+        plan.skip_count(),
+    );
+    if apply {
+        import::csv_file::apply(user.id, plan)?;
+        println!("applied");
+    } else {
+        println!("dry run, pass --apply to import");
+    }
+    Ok(())
+}
+
+fn run_export(args: &[String]) -> errors::Result<()> {
+    let username = get_option(args, "--user").ok_or::<errors::Error>(
+        "usage: gamelog export --user <username> [--format json|csv] [--output <path>]".into()
+    )?;
+
+    let user = model::get_user_by_name(username.clone())?;
+    let account_export = export::export_user(user.id)?;
+    let contents = match get_option(args, "--format").map(|format| format.as_str()) {
+        None | Some("json") => export::to_json(&account_export)?,
+        Some("csv") => export::to_csv(&account_export)?,
+        Some(format) => return Err(format!("unknown format \"{}\"", format).into()),
+    };
+
+    match get_option(args, "--output") {
+        Some(path) => std::fs::File::create(path).and_then(
+            |mut file| file.write_all(contents.as_bytes())
+        ).chain_err(|| format!("unable to write {}", path))?,
+        None => println!("{}", contents),
+    }
+    Ok(())
+}
+
+fn run_year_in_review(args: &[String]) -> errors::Result<()> {
+    let usage = "usage: gamelog year-in-review --user <username> --year <year> [--output <path>]";
+    let username = get_option(args, "--user").ok_or::<errors::Error>(usage.into())?;
+    let year = get_option(args, "--year").ok_or::<errors::Error>(usage.into())?.parse().chain_err(|| "--year needs a number")?;
+
+    let user = model::get_user_by_name(username.clone())?;
+    let contents = year_in_review::render_export(&year_in_review::year_in_review(&user, year)?)?;
+
+    match get_option(args, "--output") {
+        Some(path) => std::fs::File::create(path).and_then(
+            |mut file| file.write_all(contents.as_bytes())
+        ).chain_err(|| format!("unable to write {}", path))?,
+        None => println!("{}", contents),
+    }
+    Ok(())
+}
+
+fn run_backup(args: &[String]) -> errors::Result<()> {
+    let usage = "usage: gamelog backup <destination> [--gzip] [--keep <count>]";
+    let destination = args.get(2).ok_or::<errors::Error>(usage.into())?;
+    let keep = match get_option(args, "--keep") {
+        Some(count) => Some(count.parse().chain_err(|| "--keep needs a number")?),
+        None if args.iter().any(|arg| arg == "--keep") => return Err(usage.into()),
+        None => None,
+    };
+
+    let backup_path = backup::backup(Path::new(destination), args.iter().any(|arg| arg == "--gzip"), keep)?;
+    println!("backed up to {}", backup_path.display());
+    Ok(())
+}
+
+fn run_restore(args: &[String]) -> errors::Result<()> {
+    let source = args.get(2).ok_or::<errors::Error>("usage: gamelog restore <source>".into())?;
+    backup::restore(Path::new(source))?;
+    println!("restored from {}", source);
+    Ok(())
+}
+
 fn main() {
-    let arg = std::env::args().nth(1).unwrap();
-    match arg.as_str() {
+    let args: Vec<String> = std::env::args().collect();
+    let has_flag = |flag: &str| args.iter().any(|arg| arg == flag);
+    let sync_options = SyncOptions{
+        achievements: has_flag("--achievements"),
+        wishlist: has_flag("--wishlist"),
+        recently_played: has_flag("--recently-played"),
+    };
+    match args[1].as_str() {
         "webapp" => webapp(),
-        "steam-sync" => steam_sync().unwrap(),
+        "sync" => storefront::sync(&sync_options, None).unwrap(),
+        "steam-sync" => storefront::sync(&sync_options, Some("steam")).unwrap(),
+        "import" => run_import(&args).unwrap(),
+        "export" => run_export(&args).unwrap(),
+        "year-in-review" => run_year_in_review(&args).unwrap(),
+        "backup" => run_backup(&args).unwrap(),
+        "restore" => run_restore(&args).unwrap(),
         _ => {
             eprintln!("unrecognized argument");
         }
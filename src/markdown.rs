@@ -0,0 +1,10 @@
+use ammonia;
+use pulldown_cmark::Parser;
+use pulldown_cmark::html;
+
+/// Renders user-written markdown to HTML that's safe to put on a page as is.
+pub fn render(text: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new(text));
+    ammonia::clean(&unsafe_html)
+}
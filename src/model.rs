@@ -1,26 +1,70 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+#[cfg(test)]
+use std::env;
 use std::fmt::Write;
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
+use std::process;
 
-use bcrypt;
 use diesel::ExpressionMethods;
 use diesel::connection::Connection;
+#[cfg(test)]
+use diesel::connection::SimpleConnection;
+use diesel::expression::sql;
 use diesel::prelude::ExecuteDsl;
 use diesel::prelude::FilterDsl;
+use diesel::prelude::GroupByDsl;
 use diesel::prelude::LimitDsl;
 use diesel::prelude::LoadDsl;
 use diesel::prelude::OrderDsl;
+use diesel::prelude::SelectDsl;
 use diesel::result::OptionalExtension;
 use diesel::sqlite::SqliteConnection;
+use diesel::types::BigInt;
+use diesel::types::Bool;
+use diesel::types::Nullable;
+use diesel::types::Text;
 use diesel;
 use rand::OsRng;
 use rand::Rng;
+use sha2::Digest;
+use sha2::Sha256;
 use diesel::BoolExpressionMethods;
+use time;
+
+use helpers::FINISHED_PLAY_STATES;
+use helpers::format_timestamp;
+use helpers::get_finish_dates;
+use helpers::slugify;
+use password;
+use two_factor;
 
 use self::errors::Error;
 use self::errors::ResultExt;
 use self::schema::game;
+use self::schema::game_list;
+use self::schema::game_list_entry;
+use self::schema::goal;
+use self::schema::login_attempt;
+use self::schema::recovery_code;
+use self::schema::storefront_account;
+use self::schema::tag;
+use self::schema::up_next_entry;
 use self::schema::user;
 use self::schema::user_game;
+use self::schema::user_game_achievement;
+use self::schema::user_game_history;
+use self::schema::user_game_note;
+use self::schema::user_game_playtime;
+use self::schema::user_game_tag;
 use self::schema::user_private;
+use self::schema::user_token;
+use self::schema::user_two_factor;
+use self::schema::wishlist_game;
 use errors;
 
 mod schema {
@@ -29,7 +73,9 @@ mod schema {
             id -> BigInt,
             username -> VarChar,
             email -> VarChar,
-            steam_id -> Nullable<VarChar>,
+            rating_scale -> BigInt,
+            now_playing_limit -> BigInt,
+            email_verified -> Bool,
         }
     }
     table! {
@@ -38,6 +84,7 @@ mod schema {
             user_id -> BigInt,
             password_hash -> VarChar,
             salt -> VarChar,
+            hash_version -> BigInt,
         }
     }
     table! {
@@ -66,6 +113,151 @@ mod schema {
             beat_date -> Nullable<BigInt>,
         }
     }
+    table! {
+        user_game_achievement {
+            id -> BigInt,
+            user_game_id -> BigInt,
+            api_name -> VarChar,
+            name -> VarChar,
+            unlock_date -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        wishlist_game {
+            id -> BigInt,
+            user_id -> BigInt,
+            game_id -> BigInt,
+            added_date -> BigInt,
+            user_game_id -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        user_game_playtime {
+            id -> BigInt,
+            user_game_id -> BigInt,
+            playtime_forever -> BigInt,
+            playtime_2weeks -> BigInt,
+            last_played_date -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        user_game_history {
+            id -> BigInt,
+            user_game_id -> BigInt,
+            play_state -> VarChar,
+            date -> BigInt,
+        }
+    }
+    table! {
+        user_game_note {
+            id -> BigInt,
+            user_game_id -> BigInt,
+            notes -> Text,
+            review -> Nullable<Text>,
+            rating -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        game_list {
+            id -> BigInt,
+            user_id -> BigInt,
+            name -> VarChar,
+            slug -> VarChar,
+            ranked -> Bool,
+        }
+    }
+    table! {
+        game_list_entry {
+            id -> BigInt,
+            game_list_id -> BigInt,
+            user_game_id -> BigInt,
+            position -> BigInt,
+        }
+    }
+    table! {
+        tag {
+            id -> BigInt,
+            user_id -> BigInt,
+            name -> VarChar,
+            slug -> VarChar,
+        }
+    }
+    table! {
+        user_game_tag {
+            id -> BigInt,
+            user_game_id -> BigInt,
+            tag_id -> BigInt,
+        }
+    }
+    table! {
+        up_next_entry {
+            id -> BigInt,
+            user_id -> BigInt,
+            user_game_id -> BigInt,
+            position -> BigInt,
+            priority -> BigInt,
+            estimated_hours -> Nullable<BigInt>,
+            now_playing -> Bool,
+        }
+    }
+    table! {
+        goal {
+            id -> BigInt,
+            user_id -> BigInt,
+            kind -> VarChar,
+            target -> Nullable<BigInt>,
+            platform -> Nullable<VarChar>,
+            start_date -> Nullable<BigInt>,
+            end_date -> Nullable<BigInt>,
+            met_date -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        user_token {
+            id -> BigInt,
+            user_id -> BigInt,
+            purpose -> VarChar,
+            token_hash -> VarChar,
+            new_email -> Nullable<VarChar>,
+            expires_date -> BigInt,
+            used_date -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        login_attempt {
+            id -> BigInt,
+            user_id -> Nullable<BigInt>,
+            username_or_email -> VarChar,
+            ip_address -> VarChar,
+            date -> BigInt,
+            succeeded -> Bool,
+        }
+    }
+    table! {
+        user_two_factor {
+            id -> BigInt,
+            user_id -> BigInt,
+            secret -> VarChar,
+            enabled -> Bool,
+            last_used_step -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        recovery_code {
+            id -> BigInt,
+            user_id -> BigInt,
+            code_hash -> VarChar,
+            used_date -> Nullable<BigInt>,
+        }
+    }
+    table! {
+        storefront_account {
+            id -> BigInt,
+            user_id -> BigInt,
+            provider -> VarChar,
+            account_id -> VarChar,
+        }
+    }
 }
 
 #[derive(Queryable)]
@@ -92,12 +284,288 @@ pub struct NewUserGame {
     pub beat_date: Option<i64>,
 }
 
+#[derive(Queryable)]
+pub struct UserGameAchievement {
+    pub id: i64,
+    pub user_game_id: i64,
+    pub api_name: String,
+    pub name: String,
+    pub unlock_date: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="user_game_achievement"]
+pub struct NewUserGameAchievement {
+    pub user_game_id: i64,
+    pub api_name: String,
+    pub name: String,
+    pub unlock_date: Option<i64>,
+}
+
+#[derive(Queryable)]
+pub struct WishlistGame {
+    pub id: i64,
+    pub user_id: i64,
+    pub game_id: i64,
+    pub added_date: i64,
+    pub user_game_id: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="wishlist_game"]
+pub struct NewWishlistGame {
+    pub user_id: i64,
+    pub game_id: i64,
+    pub added_date: i64,
+    pub user_game_id: Option<i64>,
+}
+
+/// Playtime is in minutes, the way Steam reports it.
+#[derive(Queryable)]
+pub struct UserGamePlaytime {
+    pub id: i64,
+    pub user_game_id: i64,
+    pub playtime_forever: i64,
+    pub playtime_2weeks: i64,
+    pub last_played_date: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="user_game_playtime"]
+struct NewUserGamePlaytime {
+    user_game_id: i64,
+    playtime_forever: i64,
+    playtime_2weeks: i64,
+    last_played_date: Option<i64>,
+}
+
+/// A play state a collection entry was put in, and when.
+#[derive(Queryable)]
+pub struct UserGameHistory {
+    pub id: i64,
+    pub user_game_id: i64,
+    pub play_state: String,
+    pub date: i64,
+}
+
+#[derive(Insertable)]
+#[table_name="user_game_history"]
+struct NewUserGameHistory {
+    user_game_id: i64,
+    play_state: String,
+    date: i64,
+}
+
+/// Markdown notes and an optional review on a collection entry. The rating is out of 100.
+#[derive(Queryable)]
+pub struct UserGameNote {
+    pub id: i64,
+    pub user_game_id: i64,
+    pub notes: String,
+    pub review: Option<String>,
+    pub rating: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="user_game_note"]
+pub struct NewUserGameNote {
+    pub user_game_id: i64,
+    pub notes: String,
+    pub review: Option<String>,
+    pub rating: Option<i64>,
+}
+
+/// A user's named list of games from their collection. Ranked lists, like "top 10 of all time",
+/// are kept in the order the user puts them in.
+#[derive(Queryable)]
+pub struct GameList {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub slug: String,
+    pub ranked: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="game_list"]
+pub struct NewGameList {
+    pub user_id: i64,
+    pub name: String,
+    pub slug: String,
+    pub ranked: bool,
+}
+
+/// A game in a user's queue of what to play next. The queue is kept in `position` order;
+/// `priority` (1 is highest) decides where newly added games go.
+#[derive(Queryable)]
+pub struct UpNextEntry {
+    pub id: i64,
+    pub user_id: i64,
+    pub user_game_id: i64,
+    pub position: i64,
+    pub priority: i64,
+    pub estimated_hours: Option<i64>,
+    pub now_playing: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="up_next_entry"]
+struct NewUpNextEntry {
+    user_id: i64,
+    user_game_id: i64,
+    position: i64,
+    priority: i64,
+    estimated_hours: Option<i64>,
+}
+
+/// Something a user wants to get done with their collection, see `goals::GoalKind` for what
+/// each kind uses `target`, `platform` and the dates for.
+#[derive(Queryable)]
+pub struct Goal {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: String,
+    pub target: Option<i64>,
+    pub platform: Option<String>,
+    pub start_date: Option<i64>,
+    pub end_date: Option<i64>,
+    /// When the goal was first seen to be met, so it's only announced once.
+    pub met_date: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="goal"]
+pub struct NewGoal {
+    pub user_id: i64,
+    pub kind: String,
+    pub target: Option<i64>,
+    pub platform: Option<String>,
+    pub start_date: Option<i64>,
+    pub end_date: Option<i64>,
+    pub met_date: Option<i64>,
+}
+
+/// A user's own label for games in their collection, like "co-op".
+#[derive(Queryable, Clone)]
+pub struct Tag {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    pub slug: String,
+}
+
+#[derive(Insertable)]
+#[table_name="tag"]
+struct NewTag {
+    user_id: i64,
+    name: String,
+    slug: String,
+}
+
+#[derive(Queryable)]
+pub struct UserGameTag {
+    pub id: i64,
+    pub user_game_id: i64,
+    pub tag_id: i64,
+}
+
+#[derive(Insertable)]
+#[table_name="user_game_tag"]
+struct NewUserGameTag {
+    user_game_id: i64,
+    tag_id: i64,
+}
+
+/// Position 0 is the top of the list.
+#[derive(Queryable)]
+pub struct GameListEntry {
+    pub id: i64,
+    pub game_list_id: i64,
+    pub user_game_id: i64,
+    pub position: i64,
+}
+
+#[derive(Insertable)]
+#[table_name="game_list_entry"]
+struct NewGameListEntry {
+    game_list_id: i64,
+    user_game_id: i64,
+    position: i64,
+}
+
+/// A collection entry coming in from an import, matched to existing games by name.
+///
+/// Fields left as `None` keep whatever the collection already has.
+pub struct ImportedUserGame {
+    pub name: String,
+    pub platform: Option<String>,
+    pub play_state: Option<String>,
+    pub acquisition_date: Option<i64>,
+    pub start_date: Option<i64>,
+    pub beat_date: Option<i64>,
+    pub notes: Option<String>,
+    pub review: Option<String>,
+    /// Out of 100.
+    pub rating: Option<i64>,
+    /// Play states with the dates they were set, oldest first. When given, this replaces the
+    /// entry's history; otherwise only a change of play state gets recorded.
+    pub history: Vec<(String, i64)>,
+}
+
+pub struct AchievementCount {
+    pub unlocked: i64,
+    pub total: i64,
+}
+
+impl AchievementCount {
+    pub fn is_complete(&self) -> bool {
+        self.total > 0 && self.unlocked == self.total
+    }
+
+    pub fn percent(&self) -> i64 {
+        if self.total == 0 { 0 } else { self.unlocked * 100 / self.total }
+    }
+}
+
 #[derive(Insertable)]
 #[table_name="user"]
 struct NewUser {
     username: String,
     email: String,
-    steam_id: Option<String>,
+}
+
+#[derive(Queryable)]
+pub struct UserTwoFactor {
+    pub id: i64,
+    pub user_id: i64,
+    pub secret: String,
+    pub enabled: bool,
+    pub last_used_step: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="user_two_factor"]
+struct NewUserTwoFactor {
+    user_id: i64,
+    secret: String,
+    enabled: bool,
+}
+
+#[derive(Insertable)]
+#[table_name="recovery_code"]
+struct NewRecoveryCode {
+    user_id: i64,
+    code_hash: String,
+}
+
+#[derive(Insertable)]
+#[table_name="login_attempt"]
+struct NewLoginAttempt {
+    user_id: Option<i64>,
+    username_or_email: String,
+    ip_address: String,
+    date: i64,
+    succeeded: bool,
 }
 
 #[derive(Insertable)]
@@ -106,6 +574,7 @@ struct NewUserPrivate {
     user_id: i64,
     password_hash: String,
     salt: String,
+    hash_version: i64,
 }
 
 #[derive(Queryable)]
@@ -114,6 +583,7 @@ pub struct UserPrivate {
     pub user_id: i64,
     pub password_hash: String,
     pub salt: String,
+    pub hash_version: i64,
 }
 
 #[derive(Queryable)]
@@ -121,7 +591,57 @@ pub struct User {
     pub id: i64,
     pub username: String,
     pub email: String,
-    pub steam_id: Option<String>,
+    /// What the user's ratings are out of; they're stored out of 100 either way.
+    pub rating_scale: i64,
+    /// How many games from the up next queue can be marked as being played at once.
+    pub now_playing_limit: i64,
+    pub email_verified: bool,
+}
+
+/// A single use token emailed to a user to prove they can read mail at an address. Only a hash
+/// of the token is kept.
+#[derive(Queryable)]
+pub struct UserToken {
+    pub id: i64,
+    pub user_id: i64,
+    /// One of the `TOKEN_` constants.
+    pub purpose: String,
+    pub token_hash: String,
+    /// The address being changed to, for `TOKEN_CHANGE_EMAIL`.
+    pub new_email: Option<String>,
+    pub expires_date: i64,
+    pub used_date: Option<i64>,
+}
+
+#[derive(Insertable)]
+#[table_name="user_token"]
+struct NewUserToken {
+    user_id: i64,
+    purpose: String,
+    token_hash: String,
+    new_email: Option<String>,
+    expires_date: i64,
+}
+
+pub const TOKEN_VERIFY_EMAIL: &'static str = "verify_email";
+pub const TOKEN_RESET_PASSWORD: &'static str = "reset_password";
+pub const TOKEN_CHANGE_EMAIL: &'static str = "change_email";
+
+/// A user's account on an external storefront like Steam, see `storefront::StorefrontProvider`.
+#[derive(Queryable)]
+pub struct StorefrontAccount {
+    pub id: i64,
+    pub user_id: i64,
+    pub provider: String,
+    pub account_id: String,
+}
+
+#[derive(Insertable)]
+#[table_name="storefront_account"]
+pub struct NewStorefrontAccount {
+    pub user_id: i64,
+    pub provider: String,
+    pub account_id: String,
 }
 
 #[derive(Queryable)]
@@ -139,8 +659,39 @@ pub struct NewGame {
     pub steam_id: Option<i64>,
 }
 
+pub const DATABASE_PATH: &'static str = "gamelog.db";
+
+// tests run in parallel threads, so each one gets its own database, see `use_test_database`
+#[cfg(test)]
+thread_local!(static TEST_DATABASE_PATH: RefCell<Option<String>> = RefCell::new(None));
+
+/// Points the current thread at a fresh database with the current schema.
+#[cfg(test)]
+pub fn use_test_database(name: &str) -> Result<(), Error> {
+    let path = env::temp_dir().join(format!("gamelog-test-{}-{}.db", process::id(), name));
+    let _ = fs::remove_file(&path);
+    let path = path.to_string_lossy().into_owned();
+
+    let conn = SqliteConnection::establish(&path).chain_err(|| "unable to create test database")?;
+    for table in ::backup::SCHEMA {
+        conn.batch_execute(table).chain_err(|| "unable to create test schema")?;
+    }
+    TEST_DATABASE_PATH.with(|test_path| *test_path.borrow_mut() = Some(path));
+    Ok(())
+}
+
+#[cfg(test)]
+fn get_database_path() -> String {
+    TEST_DATABASE_PATH.with(|path| path.borrow().clone()).unwrap_or(DATABASE_PATH.to_string())
+}
+
+#[cfg(not(test))]
+fn get_database_path() -> String {
+    DATABASE_PATH.to_string()
+}
+
 fn get_diesel_conn() -> Result<SqliteConnection, Error> {
-    SqliteConnection::establish("gamelog.db").chain_err(|| "unable to get sqlite connection")
+    SqliteConnection::establish(&get_database_path()).chain_err(|| "unable to get sqlite connection")
 }
 
 pub fn get_user_by_id(user_id: i64) -> Result<User, Error> {
@@ -157,6 +708,13 @@ pub fn get_user_by_name(username: String) -> Result<User, Error> {
     ).get_result(&conn).chain_err(|| "unable to load user")
 }
 
+pub fn get_optional_user_by_name(username: &str) -> Result<Option<User>, Error> {
+    let conn = get_diesel_conn()?;
+    user::table.filter(
+        user::username.eq(username)
+    ).get_result(&conn).optional().chain_err(|| "unable to load user")
+}
+
 pub fn get_user_games(user_id: i64) -> Result<Vec<UserGame>, Error> {
     let conn = get_diesel_conn().chain_err(|| "unable to get db connection")?;
     schema::user_game::table.filter(
@@ -174,7 +732,10 @@ pub fn get_user_games_with_names(user_id: i64) -> Result<Vec<(String, UserGame)>
     ).load(
         &conn,
     ).chain_err(|| "unable to get game names")?;
-    let user_games_with_names = games.into_iter().map(|game| game.name).zip(user_games).collect();
+    let names: HashMap<i64, String> = games.into_iter().map(|game| (game.id, game.name)).collect();
+    let user_games_with_names = user_games.into_iter().filter_map(|user_game| {
+        names.get(&user_game.game_id).map(|name| (name.clone(), user_game))
+    }).collect();
 
     Ok(user_games_with_names)
 }
@@ -186,30 +747,17 @@ pub struct UserSignupInfo{
 }
 
 
-pub fn signup(user_signup_info: UserSignupInfo) -> Result<(), Error> {
-    let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
-    let mut salt_bytes: Vec<u8> = vec![0; 16];
-    rng.fill_bytes(&mut salt_bytes);
-    let mut salt = String::new();
-    for byte in salt_bytes.iter() {
-        write!(&mut salt, "{:X}", byte).unwrap();
-    }
-
-    let salted_password = format!("{}{}", user_signup_info.password, salt);
-    let password_hash_result = bcrypt::hash(
-        salted_password.as_str(),
-        bcrypt::DEFAULT_COST,
-    );
-    let password_hash: String = password_hash_result.chain_err(|| "unable to hash password")?;
+/// Adds a user, returning their id.
+pub fn signup(user_signup_info: UserSignupInfo) -> Result<i64, Error> {
+    let password_hash = password::hash(&user_signup_info.password)?;
 
     let conn = get_diesel_conn()?;
     let new_user = NewUser{
         username: user_signup_info.username,
         email: user_signup_info.email,
-        steam_id: None,
     };
 
-    conn.transaction(|| {
+    conn.transaction::<_, diesel::result::Error, _>(|| {
         diesel::insert(
             &new_user,
         ).into(
@@ -222,17 +770,18 @@ pub fn signup(user_signup_info: UserSignupInfo) -> Result<(), Error> {
 
         let new_user_private = NewUserPrivate{
             user_id: user_new.id,
-            password_hash: password_hash,
-            salt: salt,
+            password_hash: password_hash.hash,
+            salt: password_hash.salt,
+            hash_version: password_hash.version,
         };
 
         diesel::insert(
             &new_user_private,
         ).into(
             user_private::table,
-        ).execute(&conn)
-    }).chain_err(|| "unable to add new user")?;
-    Ok(())
+        ).execute(&conn)?;
+        Ok(user_new.id)
+    }).chain_err(|| "unable to add new user")
 }
 
 pub struct LoginInfo {
@@ -245,61 +794,264 @@ pub fn get_all_users() -> Result<Vec<User>, Error> {
     user::table.load(&conn).chain_err(|| "can't load users")
 }
 
-fn get_user_from_email(email: String) -> Result<User, Error> {
+fn get_optional_user_from_username_or_email(username_or_email: &str) -> Result<Option<User>, Error> {
     let conn = get_diesel_conn()?;
-    user::table.filter(
-        user::email.eq(&email),
+    let user_row = user::table.filter(
+        user::username.eq(username_or_email),
     ).get_result(
         &conn
-    ).chain_err(|| {format!("user with email '{}' not found", &email)})
-}
+    ).optional().chain_err(|| "unable to load user")?;
 
-fn get_user_from_username_or_email(username_or_email: String) -> Result<User, Error> {
-    let conn = get_diesel_conn()?;
-    let user_row_result = user::table.filter(
-        user::username.eq(&username_or_email),
-    ).get_result(
-        &conn
-    );
-
-    match user_row_result {
-        Ok(user_row) => Ok(user_row),
-        Err(error) => {
-            match error {
-                diesel::result::Error::NotFound => {
-                    get_user_from_email(username_or_email)
-                },
-                _ => Err(
-                    Error::with_chain(
-                        error,
-                        format!("user with username '{}' not found", &username_or_email),
-                    )
-                ),
-            }
-        }
+    match user_row {
+        Some(user_row) => Ok(Some(user_row)),
+        None => get_optional_user_by_email(username_or_email),
     }
 }
 
-pub fn login(login_info: LoginInfo) -> Result<i64, Error> {
-    let conn = get_diesel_conn()?;
-    let user_row = get_user_from_username_or_email(login_info.username_or_email)?;
+/// How a login went. Unknown users and wrong passwords look the same from outside, so nobody
+/// can use logging in to find out who has an account.
+pub enum LoginOutcome {
+    LoggedIn(i64),
+    /// The password was right, but the account has two-factor authentication, so it still needs
+    /// `login_second_factor`.
+    NeedsSecondFactor(i64),
+    Failed,
+    /// Too many attempts have failed lately; the number is how many seconds until trying again.
+    Throttled(i64),
+}
+
+// failed attempts older than this don't count towards throttling
+const LOGIN_ATTEMPT_WINDOW: i64 = 60 * 60;
+// attempts for one account, known or not, and from one address that can fail before backing off
+const FREE_ACCOUNT_LOGIN_ATTEMPTS: usize = 3;
+const FREE_IP_LOGIN_ATTEMPTS: usize = 10;
+// attempts that can fail before locking out, rather than backing off
+const ACCOUNT_LOCKOUT_ATTEMPTS: usize = 10;
+const IP_LOCKOUT_ATTEMPTS: usize = 50;
+const LOCKOUT_SECONDS: i64 = 15 * 60;
+
+/// How long to wait before the next attempt, given the dates of recent failures (oldest first).
+/// Past the free attempts the wait doubles with each failure, until it's a full lockout.
+fn get_login_wait(failure_dates: &[i64], free_attempts: usize, lockout_attempts: usize, now: i64) -> i64 {
+    let failures = failure_dates.len();
+    let last_failure = match failure_dates.last() {
+        Some(&last_failure) if failures > free_attempts => last_failure,
+        _ => return 0,
+    };
+    let delay = if failures >= lockout_attempts {
+        LOCKOUT_SECONDS
+    } else {
+        (1 << (failures - free_attempts).min(20)).min(LOCKOUT_SECONDS)
+    };
+    (last_failure + delay - now).max(0)
+}
+
+fn get_failed_login_dates(
+    user_id: Option<i64>,
+    username_or_email: &str,
+    ip_address: &str,
+    now: i64,
+    conn: &SqliteConnection,
+) -> Result<(Vec<i64>, Vec<i64>), diesel::result::Error> {
+    let window_start = now - LOGIN_ATTEMPT_WINDOW;
+
+    let account_attempts: Vec<(i64, bool)> = match user_id {
+        Some(user_id) => login_attempt::table.select(
+            (login_attempt::date, login_attempt::succeeded),
+        ).filter(
+            login_attempt::user_id.eq(user_id).and(
+                login_attempt::date.gt(window_start)
+            )
+        ).order(
+            login_attempt::date.asc(),
+        ).load(conn)?,
+        // unknown accounts are tracked by what was typed in, so they get throttled just the same
+        None => login_attempt::table.select(
+            (login_attempt::date, login_attempt::succeeded),
+        ).filter(
+            login_attempt::username_or_email.eq(username_or_email).and(
+                login_attempt::user_id.is_null()
+            ).and(
+                login_attempt::date.gt(window_start)
+            )
+        ).order(
+            login_attempt::date.asc(),
+        ).load(conn)?,
+    };
+    // an account's failures start over once someone gets its password right
+    let mut account_failures = vec![];
+    for (date, succeeded) in account_attempts {
+        if succeeded {
+            account_failures.clear();
+        } else {
+            account_failures.push(date);
+        }
+    }
+
+    let ip_failures: Vec<i64> = login_attempt::table.select(
+        login_attempt::date,
+    ).filter(
+        login_attempt::ip_address.eq(ip_address).and(
+            login_attempt::succeeded.eq(false)
+        ).and(
+            login_attempt::date.gt(window_start)
+        )
+    ).order(
+        login_attempt::date.asc(),
+    ).load(conn)?;
+
+    Ok((account_failures, ip_failures))
+}
+
+fn get_login_wait_for(user_id: Option<i64>, username_or_email: &str, ip_address: &str, now: i64, conn: &SqliteConnection) -> Result<i64, Error> {
+    let (account_failures, ip_failures) = get_failed_login_dates(
+        user_id,
+        username_or_email,
+        ip_address,
+        now,
+        conn,
+    ).chain_err(|| "unable to load login attempts")?;
+    Ok(get_login_wait(&account_failures, FREE_ACCOUNT_LOGIN_ATTEMPTS, ACCOUNT_LOCKOUT_ATTEMPTS, now).max(
+        get_login_wait(&ip_failures, FREE_IP_LOGIN_ATTEMPTS, IP_LOCKOUT_ATTEMPTS, now)
+    ))
+}
+
+fn record_login_attempt(
+    user_id: Option<i64>,
+    username_or_email: &str,
+    ip_address: &str,
+    now: i64,
+    succeeded: bool,
+    conn: &SqliteConnection,
+) -> Result<(), Error> {
+    diesel::insert(
+        &NewLoginAttempt{
+            user_id: user_id,
+            username_or_email: username_or_email.to_string(),
+            ip_address: ip_address.to_string(),
+            date: now,
+            succeeded: succeeded,
+        },
+    ).into(
+        login_attempt::table,
+    ).execute(conn).chain_err(|| "unable to record login attempt")?;
+
+    Ok(())
+}
+
+/// Logs a user in, unless there have been too many failed attempts for their account or from
+/// their address lately. Every attempt that gets checked is recorded in `login_attempt`, which
+/// doubles as an audit log of failed logins.
+pub fn login(login_info: LoginInfo, ip_address: &str) -> Result<LoginOutcome, Error> {
+    let now = time::get_time().sec;
+    let user_row = get_optional_user_from_username_or_email(&login_info.username_or_email)?;
+    let user_id = user_row.as_ref().map(|user_row| user_row.id);
+
+    let conn = get_diesel_conn()?;
+    let wait = get_login_wait_for(user_id, &login_info.username_or_email, ip_address, now, &conn)?;
+    if wait > 0 {
+        return Ok(LoginOutcome::Throttled(wait));
+    }
+
+    let user_id = match user_id {
+        Some(user_id) if verify_password(user_id, &login_info.password)? => user_id,
+        Some(user_id) => {
+            record_login_attempt(Some(user_id), &login_info.username_or_email, ip_address, now, false, &conn)?;
+            return Ok(LoginOutcome::Failed);
+        },
+        None => {
+            // hash anyway, so an unknown user takes as long to turn away as a wrong password
+            password::hash(&login_info.password)?;
+            record_login_attempt(None, &login_info.username_or_email, ip_address, now, false, &conn)?;
+            return Ok(LoginOutcome::Failed);
+        },
+    };
+
+    // now that the password is known, an outdated hash of it can be replaced
+    if password::needs_rehash(&get_password_hash(user_id)?) {
+        update_password(user_id, &login_info.password)?;
+    }
+
+    // the attempt only counts as a success once the second step is done too, so getting the
+    // password right doesn't reset the account's failures
+    if get_two_factor(user_id)?.map(|two_factor| two_factor.enabled).unwrap_or(false) {
+        return Ok(LoginOutcome::NeedsSecondFactor(user_id));
+    }
+    record_login_attempt(Some(user_id), &login_info.username_or_email, ip_address, now, true, &conn)?;
+    Ok(LoginOutcome::LoggedIn(user_id))
+}
+
+/// Finishes logging in someone who's already given the right password, with either a code from
+/// their authenticator or one of their recovery codes. Wrong codes count towards throttling the
+/// same way wrong passwords do.
+pub fn login_second_factor(user_id: i64, code: &str, ip_address: &str) -> Result<LoginOutcome, Error> {
+    let now = time::get_time().sec;
+    let username = get_user_by_id(user_id)?.username;
+
+    let conn = get_diesel_conn()?;
+    let wait = get_login_wait_for(Some(user_id), &username, ip_address, now, &conn)?;
+    if wait > 0 {
+        return Ok(LoginOutcome::Throttled(wait));
+    }
+
+    let two_factor = get_two_factor(user_id)?.ok_or::<Error>("two-factor authentication isn't set up".into())?;
+    let succeeded = match two_factor::verify_code(&two_factor.secret, code, now, two_factor.last_used_step)? {
+        Some(step) => {
+            update_two_factor_step(user_id, step)?;
+            true
+        },
+        None => use_recovery_code(user_id, code)?,
+    };
+
+    record_login_attempt(Some(user_id), &username, ip_address, now, succeeded, &conn)?;
+    if succeeded {
+        Ok(LoginOutcome::LoggedIn(user_id))
+    } else {
+        Ok(LoginOutcome::Failed)
+    }
+}
+
+/// Checks the password of someone who's already logged in, before they change something
+/// sensitive. It's throttled and recorded just like logging in, so a hijacked session can't be
+/// used to guess the password either. `LoggedIn` means the password was right.
+pub fn reauthenticate(user_id: i64, password: &str, ip_address: &str) -> Result<LoginOutcome, Error> {
+    let now = time::get_time().sec;
+    let username = get_user_by_id(user_id)?.username;
+
+    let conn = get_diesel_conn()?;
+    let wait = get_login_wait_for(Some(user_id), &username, ip_address, now, &conn)?;
+    if wait > 0 {
+        return Ok(LoginOutcome::Throttled(wait));
+    }
+
+    let succeeded = verify_password(user_id, password)?;
+    record_login_attempt(Some(user_id), &username, ip_address, now, succeeded, &conn)?;
+    if succeeded {
+        Ok(LoginOutcome::LoggedIn(user_id))
+    } else {
+        Ok(LoginOutcome::Failed)
+    }
+}
+
+/// Checks a password against the one a user has. Anything checking a password someone typed in
+/// should go through `login` or `reauthenticate` instead, which throttle guessing.
+fn verify_password(user_id: i64, password: &str) -> Result<bool, Error> {
+    password::verify(password, &get_password_hash(user_id)?)
+}
 
+fn get_password_hash(user_id: i64) -> Result<password::PasswordHash, Error> {
+    let conn = get_diesel_conn()?;
     let user_private_row: UserPrivate = user_private::table.filter(
-        user_private::user_id.eq(user_row.id)
+        user_private::user_id.eq(user_id)
     ).get_result(
         &conn
     ).chain_err(|| "unable to load user_private row")?;
 
-    let salted_password = format!("{}{}", login_info.password, user_private_row.salt);
-
-    if bcrypt::verify(
-        salted_password.as_str(),
-        user_private_row.password_hash.as_str(),
-    ).chain_err(|| "error while verifying hashed password")? {
-        Ok(user_row.id)
-    } else {
-        Err("password does not match".into())
-    }
+    Ok(password::PasswordHash{
+        version: user_private_row.hash_version,
+        hash: user_private_row.password_hash,
+        salt: user_private_row.salt,
+    })
 }
 
 fn get_game_by_name_with_conn(
@@ -311,7 +1063,7 @@ fn get_game_by_name_with_conn(
     ).get_result(conn)
 }
 
-fn get_optional_game_by_name(name: &String) -> Result<Option<Game>, Error> {
+pub fn get_optional_game_by_name(name: &String) -> Result<Option<Game>, Error> {
     let conn = get_diesel_conn()?;
     get_game_by_name_with_conn(name, &conn).optional().chain_err(|| "unable to load game")
 }
@@ -329,6 +1081,14 @@ pub fn get_game_by_steam_id(steam_id: u64) -> Result<Game, Error> {
     ).chain_err(|| "can't load game")
 }
 
+pub fn get_optional_game_by_steam_id(steam_id: u64) -> Result<Option<Game>, Error> {
+    game::table.filter(
+        game::steam_id.eq(steam_id as i64),
+    ).get_result(
+        &get_diesel_conn()?,
+    ).optional().chain_err(|| "can't load game")
+}
+
 pub fn insert_game(game: NewGame) -> Result<i64, Error> {
     let conn = get_diesel_conn()?;
 
@@ -366,7 +1126,7 @@ pub fn get_game_by_id(game_id: i64) -> Result<Game, Error> {
     ).get_result(&conn).chain_err(|| "unable to find game")
 }
 
-fn get_user_game_by_user_id_and_game_id(user_id: i64, game_id: i64) -> Result<UserGame, Error> {
+pub fn get_user_game_by_user_id_and_game_id(user_id: i64, game_id: i64) -> Result<UserGame, Error> {
     let conn = get_diesel_conn()?;
     user_game::table.filter(
         user_game::user_id.eq(user_id).and(
@@ -375,19 +1135,6 @@ fn get_user_game_by_user_id_and_game_id(user_id: i64, game_id: i64) -> Result<Us
     ).get_result(&conn).chain_err(|| "unable to find user game")
 }
 
-pub fn upsert_user_game(user_game: NewUserGame) -> Result<(), Error> {
-    match get_user_game_by_user_id_and_game_id(user_game.user_id, user_game.game_id) {
-        Ok(mut game) => {
-            game.play_state = user_game.play_state;
-            game.acquisition_date = user_game.acquisition_date;
-            game.start_date = user_game.start_date;
-            game.beat_date = user_game.beat_date;
-            update_user_game(game)
-        },
-        Err(_) => add_user_game(user_game)
-    }
-}
-
 pub fn get_user_game_by_id(id: i64) -> Result<UserGame, Error> {
     let conn = get_diesel_conn()?;
     user_game::table.filter(
@@ -395,33 +1142,20 @@ pub fn get_user_game_by_id(id: i64) -> Result<UserGame, Error> {
     ).get_result(&conn).chain_err(|| "unable to find user game")
 }
 
-fn update_user_game(game: UserGame) -> Result<(), Error> {
-    let conn = get_diesel_conn()?;
-    diesel::update(
-        user_game::table.filter(
-            user_game::id.eq(game.id),
-        )
-    ).set((
-        user_game::play_state.eq(game.play_state),
-        user_game::acquisition_date.eq(game.acquisition_date),
-        user_game::start_date.eq(game.start_date),
-        user_game::beat_date.eq(game.beat_date),
-    )).execute(
-        &conn,
-    ).chain_err(|| "unable to update user_game row")?;
-
-    Ok(())
-}
-
 pub fn update_user_game_play_state(id: i64, play_state: String) -> Result<(), Error> {
     let conn = get_diesel_conn()?;
-    diesel::update(
-        user_game::table.filter(
-            user_game::id.eq(id),
-        )
-    ).set(
-        user_game::play_state.eq(play_state),
-    ).execute(&conn).chain_err(|| "unable to update play state")?;
+    conn.transaction(|| {
+        diesel::update(
+            user_game::table.filter(
+                user_game::id.eq(id),
+            )
+        ).set(
+            user_game::play_state.eq(&play_state),
+        ).execute(&conn)?;
+
+        remove_finished_from_up_next_with_conn(id, &play_state, &conn)?;
+        record_play_state_with_conn(id, &play_state, time::get_time().sec, &conn)
+    }).chain_err(|| "unable to update play state")?;
 
     Ok(())
 }
@@ -441,16 +1175,127 @@ pub fn update_user_game_platform(id: i64, platform: String) -> Result<(), Error>
 
 pub fn add_user_game(user_game: NewUserGame) -> Result<(), Error> {
     let conn = get_diesel_conn()?;
+    conn.transaction(|| {
+        let user_game_id = insert_user_game_with_conn(&user_game, &conn)?;
+        record_play_state_with_conn(user_game_id, &user_game.play_state, user_game.acquisition_date, &conn)
+    }).chain_err(|| "unable to save new user game")?;
+    Ok(())
+}
+
+/// Inserts a collection entry and returns its id.
+fn insert_user_game_with_conn(user_game: &NewUserGame, conn: &SqliteConnection) -> Result<i64, diesel::result::Error> {
     diesel::insert(
-        &user_game,
+        user_game,
     ).into(
         user_game::table,
-    ).execute(
-        &conn,
-    ).chain_err(|| "unable to save new user game")?;
+    ).execute(conn)?;
+
+    user_game::table.filter(
+        user_game::user_id.eq(user_game.user_id).and(
+            user_game::game_id.eq(user_game.game_id)
+        )
+    ).order(
+        user_game::id.desc(),
+    ).select(
+        user_game::id,
+    ).get_result(conn)
+}
+
+/// Adds a play state to a collection entry's history, unless it's already the latest one.
+fn record_play_state_with_conn(
+    user_game_id: i64,
+    play_state: &str,
+    date: i64,
+    conn: &SqliteConnection,
+) -> Result<(), diesel::result::Error> {
+    let latest: Option<String> = user_game_history::table.filter(
+        user_game_history::user_game_id.eq(user_game_id),
+    ).order(
+        user_game_history::id.desc(),
+    ).select(
+        user_game_history::play_state,
+    ).limit(1).get_result(conn).optional()?;
+
+    if latest.as_ref().map(|latest| latest.as_str()) == Some(play_state) {
+        return Ok(());
+    }
+
+    diesel::insert(
+        &NewUserGameHistory{
+            user_game_id: user_game_id,
+            play_state: play_state.to_string(),
+            date: date,
+        },
+    ).into(
+        user_game_history::table,
+    ).execute(conn)?;
+
+    Ok(())
+}
+
+fn replace_history_with_conn(
+    user_game_id: i64,
+    history: &[(String, i64)],
+    conn: &SqliteConnection,
+) -> Result<(), diesel::result::Error> {
+    diesel::delete(
+        user_game_history::table.filter(
+            user_game_history::user_game_id.eq(user_game_id),
+        )
+    ).execute(conn)?;
+
+    let entries: Vec<NewUserGameHistory> = history.iter().map(|&(ref play_state, date)| NewUserGameHistory{
+        user_game_id: user_game_id,
+        play_state: play_state.clone(),
+        date: date,
+    }).collect();
+    diesel::insert(
+        &entries[..],
+    ).into(
+        user_game_history::table,
+    ).execute(conn)?;
+
+    Ok(())
+}
+
+/// Every play state change across a user's collection, oldest first.
+pub fn get_user_game_history(user_id: i64) -> Result<Vec<UserGameHistory>, Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    user_game_history::table.filter(
+        user_game_history::user_game_id.eq_any(user_game_ids),
+    ).order(
+        (user_game_history::date.asc(), user_game_history::id.asc()),
+    ).load(&conn).chain_err(|| "unable to load play state history")
+}
+
+
+pub fn update_rating_scale(user_id: i64, rating_scale: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user::table.filter(
+            user::id.eq(user_id),
+        )
+    ).set(
+        user::rating_scale.eq(rating_scale),
+    ).execute(&conn).chain_err(|| "unable to update rating scale")?;
+
     Ok(())
 }
 
+pub fn update_now_playing_limit(user_id: i64, now_playing_limit: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user::table.filter(
+            user::id.eq(user_id),
+        )
+    ).set(
+        user::now_playing_limit.eq(now_playing_limit),
+    ).execute(&conn).chain_err(|| "unable to update now playing limit")?;
+
+    Ok(())
+}
 
 pub fn update_username(user_id: i64, username: String) -> Result<(), Error> {
     let conn = get_diesel_conn()?;
@@ -465,15 +1310,1500 @@ pub fn update_username(user_id: i64, username: String) -> Result<(), Error> {
     Ok(())
 }
 
-pub fn update_steam_id(user_id: i64, steam_id: Option<String>) -> Result<(), Error> {
+pub fn get_storefront_accounts(user_id: i64) -> Result<Vec<StorefrontAccount>, Error> {
+    let conn = get_diesel_conn()?;
+    storefront_account::table.filter(
+        storefront_account::user_id.eq(user_id),
+    ).order(
+        storefront_account::id.asc(),
+    ).load(&conn).chain_err(|| "unable to load storefront accounts")
+}
+
+pub fn get_all_storefront_accounts() -> Result<Vec<StorefrontAccount>, Error> {
+    let conn = get_diesel_conn()?;
+    storefront_account::table.order(
+        storefront_account::id.asc(),
+    ).load(&conn).chain_err(|| "unable to load storefront accounts")
+}
+
+pub fn add_storefront_account(account: NewStorefrontAccount) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::insert(
+        &account,
+    ).into(
+        storefront_account::table,
+    ).execute(&conn).chain_err(|| "unable to link storefront account")?;
+
+    Ok(())
+}
+
+pub fn remove_storefront_account(user_id: i64, id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::delete(
+        storefront_account::table.filter(
+            storefront_account::id.eq(id).and(
+                storefront_account::user_id.eq(user_id)
+            )
+        )
+    ).execute(&conn).chain_err(|| "unable to unlink storefront account")?;
+
+    Ok(())
+}
+
+pub fn replace_user_game_achievements(
+    user_game_id: i64,
+    achievements: Vec<NewUserGameAchievement>,
+) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction(|| {
+        diesel::delete(
+            user_game_achievement::table.filter(
+                user_game_achievement::user_game_id.eq(user_game_id),
+            )
+        ).execute(&conn)?;
+
+        diesel::insert(
+            &achievements[..],
+        ).into(
+            user_game_achievement::table,
+        ).execute(&conn)
+    }).chain_err(|| "unable to save achievements")?;
+
+    Ok(())
+}
+
+pub fn get_user_game_achievements(user_game_id: i64) -> Result<Vec<UserGameAchievement>, Error> {
+    let conn = get_diesel_conn()?;
+    user_game_achievement::table.filter(
+        user_game_achievement::user_game_id.eq(user_game_id),
+    ).load(&conn).chain_err(|| "unable to load achievements")
+}
+
+/// Unlocked and total achievement counts for each of a user's games that has any achievements.
+pub fn get_achievement_counts(user_id: i64) -> Result<HashMap<i64, AchievementCount>, Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    let counts: Vec<(i64, i64, i64)> = user_game_achievement::table.filter(
+        user_game_achievement::user_game_id.eq_any(user_game_ids),
+    ).group_by(
+        user_game_achievement::user_game_id,
+    ).select((
+        user_game_achievement::user_game_id,
+        sql::<BigInt>("COUNT(unlock_date)"),
+        sql::<BigInt>("COUNT(*)"),
+    )).load(&conn).chain_err(|| "unable to count achievements")?;
+
+    Ok(counts.into_iter().map(|(user_game_id, unlocked, total)| {
+        (user_game_id, AchievementCount{unlocked: unlocked, total: total})
+    }).collect())
+}
+
+/// Adds a game to a user's wishlist, keeping the original date if it's already there.
+pub fn upsert_wishlist_game(wishlist_game: NewWishlistGame) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    let existing: Option<WishlistGame> = wishlist_game::table.filter(
+        wishlist_game::user_id.eq(wishlist_game.user_id).and(
+            wishlist_game::game_id.eq(wishlist_game.game_id)
+        )
+    ).get_result(&conn).optional().chain_err(|| "unable to load wishlist game")?;
+
+    if existing.is_some() {
+        return Ok(());
+    }
+
+    diesel::insert(
+        &wishlist_game,
+    ).into(
+        wishlist_game::table,
+    ).execute(&conn).chain_err(|| "unable to save wishlist game")?;
+
+    Ok(())
+}
+
+/// Marks a wishlisted game as acquired once it shows up in the user's collection.
+pub fn move_wishlist_game_to_collection(user_id: i64, game_id: i64, user_game_id: i64) -> Result<(), Error> {
     let conn = get_diesel_conn()?;
     diesel::update(
-        user::table.filter(
-            user::id.eq(user_id),
+        wishlist_game::table.filter(
+            wishlist_game::user_id.eq(user_id).and(
+                wishlist_game::game_id.eq(game_id)
+            ).and(
+                wishlist_game::user_game_id.is_null()
+            )
         )
-    ).set((
-        user::steam_id.eq(steam_id),
-    )).execute(&conn).chain_err(|| "unable to update steam_id")?;
+    ).set(
+        wishlist_game::user_game_id.eq(Some(user_game_id)),
+    ).execute(&conn).chain_err(|| "unable to move wishlist game to collection")?;
+
+    Ok(())
+}
+
+/// Games still on a user's wishlist, oldest first.
+pub fn get_wishlist_games_with_names(user_id: i64) -> Result<Vec<(String, WishlistGame)>, Error> {
+    let conn = get_diesel_conn()?;
+    let wishlist_games: Vec<WishlistGame> = wishlist_game::table.filter(
+        wishlist_game::user_id.eq(user_id).and(
+            wishlist_game::user_game_id.is_null()
+        )
+    ).order(
+        wishlist_game::added_date.asc(),
+    ).load(&conn).chain_err(|| "unable to load wishlist")?;
+
+    let game_ids: Vec<i64> = wishlist_games.iter().map(|wishlist_game| wishlist_game.game_id).collect();
+    let names: HashMap<i64, String> = game::table.filter(
+        game::id.eq_any(game_ids),
+    ).load::<Game>(
+        &conn,
+    ).chain_err(|| "unable to get game names")?.into_iter().map(|game| (game.id, game.name)).collect();
+
+    Ok(wishlist_games.into_iter().filter_map(|wishlist_game| {
+        names.get(&wishlist_game.game_id).map(|name| (name.clone(), wishlist_game))
+    }).collect())
+}
+
+/// When a collection entry was first added to the user's wishlist, if it ever was.
+pub fn get_wishlist_date(user_game_id: i64) -> Result<Option<i64>, Error> {
+    let conn = get_diesel_conn()?;
+    wishlist_game::table.filter(
+        wishlist_game::user_game_id.eq(user_game_id),
+    ).select(
+        wishlist_game::added_date,
+    ).get_result(&conn).optional().chain_err(|| "unable to load wishlist date")
+}
+
+fn get_optional_user_game_playtime(user_game_id: i64, conn: &SqliteConnection) -> Result<Option<UserGamePlaytime>, Error> {
+    user_game_playtime::table.filter(
+        user_game_playtime::user_game_id.eq(user_game_id),
+    ).get_result(conn).optional().chain_err(|| "unable to load playtime")
+}
+
+/// Records total playtime without touching when the game was last played.
+pub fn update_total_playtime(user_game_id: i64, playtime_forever: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    let result = match get_optional_user_game_playtime(user_game_id, &conn)? {
+        Some(playtime) => diesel::update(
+            user_game_playtime::table.filter(
+                user_game_playtime::id.eq(playtime.id),
+            )
+        ).set(
+            user_game_playtime::playtime_forever.eq(playtime_forever),
+        ).execute(&conn),
+        None => diesel::insert(
+            &NewUserGamePlaytime{
+                user_game_id: user_game_id,
+                playtime_forever: playtime_forever,
+                playtime_2weeks: 0,
+                last_played_date: None,
+            },
+        ).into(
+            user_game_playtime::table,
+        ).execute(&conn),
+    };
+    result.chain_err(|| "unable to save playtime")?;
+
+    Ok(())
+}
+
+/// Total playtime in minutes for each of a user's games that has any recorded.
+pub fn get_total_playtimes(user_id: i64) -> Result<HashMap<i64, i64>, Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    let playtimes: Vec<(i64, i64)> = user_game_playtime::table.filter(
+        user_game_playtime::user_game_id.eq_any(user_game_ids),
+    ).select((
+        user_game_playtime::user_game_id,
+        user_game_playtime::playtime_forever,
+    )).load(&conn).chain_err(|| "unable to load playtime")?;
+
+    Ok(playtimes.into_iter().collect())
+}
+
+pub fn get_user_game_playtimes(user_id: i64) -> Result<Vec<UserGamePlaytime>, Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    user_game_playtime::table.filter(
+        user_game_playtime::user_game_id.eq_any(user_game_ids),
+    ).load(&conn).chain_err(|| "unable to load playtime")
+}
+
+/// Records that a game was played recently, as of `played_date`.
+pub fn update_recent_playtime(
+    user_game_id: i64,
+    playtime_forever: i64,
+    playtime_2weeks: i64,
+    played_date: i64,
+) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    let result = match get_optional_user_game_playtime(user_game_id, &conn)? {
+        Some(playtime) => diesel::update(
+            user_game_playtime::table.filter(
+                user_game_playtime::id.eq(playtime.id),
+            )
+        ).set((
+            user_game_playtime::playtime_forever.eq(playtime_forever),
+            user_game_playtime::playtime_2weeks.eq(playtime_2weeks),
+            user_game_playtime::last_played_date.eq(Some(played_date)),
+        )).execute(&conn),
+        None => diesel::insert(
+            &NewUserGamePlaytime{
+                user_game_id: user_game_id,
+                playtime_forever: playtime_forever,
+                playtime_2weeks: playtime_2weeks,
+                last_played_date: Some(played_date),
+            },
+        ).into(
+            user_game_playtime::table,
+        ).execute(&conn),
+    };
+    result.chain_err(|| "unable to save playtime")?;
+
+    Ok(())
+}
+
+/// Zeroes out recent playtime for all of a user's games, before a fresh recently played list comes in.
+pub fn clear_recent_playtime(user_id: i64) -> Result<(), Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user_game_playtime::table.filter(
+            user_game_playtime::user_game_id.eq_any(user_game_ids),
+        )
+    ).set(
+        user_game_playtime::playtime_2weeks.eq(0),
+    ).execute(&conn).chain_err(|| "unable to clear recent playtime")?;
+
+    Ok(())
+}
+
+/// Sets the start date and moves the game out of `unplayed`, unless it was already started.
+pub fn mark_user_game_started(user_game_id: i64, start_date: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::update(
+            user_game::table.filter(
+                user_game::id.eq(user_game_id).and(
+                    user_game::start_date.is_null()
+                )
+            )
+        ).set(
+            user_game::start_date.eq(Some(start_date)),
+        ).execute(&conn)?;
+
+        let started = diesel::update(
+            user_game::table.filter(
+                user_game::id.eq(user_game_id).and(
+                    user_game::play_state.eq("unplayed")
+                )
+            )
+        ).set(
+            user_game::play_state.eq("unfinished"),
+        ).execute(&conn)?;
+
+        if started > 0 {
+            record_play_state_with_conn(user_game_id, "unfinished", start_date, &conn)?;
+        }
+        Ok(())
+    }).chain_err(|| "unable to mark game as started")?;
 
     Ok(())
 }
+
+/// Games with playtime in the last two weeks, most played first.
+pub fn get_currently_playing(user_id: i64) -> Result<Vec<(String, UserGamePlaytime)>, Error> {
+    let user_games_with_names = get_user_games_with_names(user_id)?;
+    let names: HashMap<i64, String> = user_games_with_names.into_iter().map(
+        |(name, user_game)| (user_game.id, name)
+    ).collect();
+    let user_game_ids: Vec<i64> = names.keys().cloned().collect();
+
+    let conn = get_diesel_conn()?;
+    let playtimes: Vec<UserGamePlaytime> = user_game_playtime::table.filter(
+        user_game_playtime::user_game_id.eq_any(user_game_ids).and(
+            user_game_playtime::playtime_2weeks.gt(0)
+        )
+    ).order(
+        user_game_playtime::playtime_2weeks.desc(),
+    ).load(&conn).chain_err(|| "unable to load recent playtime")?;
+
+    Ok(playtimes.into_iter().filter_map(|playtime| {
+        names.get(&playtime.user_game_id).map(|name| (name.clone(), playtime))
+    }).collect())
+}
+
+pub fn get_user_game_note(user_game_id: i64) -> Result<Option<UserGameNote>, Error> {
+    let conn = get_diesel_conn()?;
+    user_game_note::table.filter(
+        user_game_note::user_game_id.eq(user_game_id),
+    ).get_result(&conn).optional().chain_err(|| "unable to load notes")
+}
+
+/// Notes for each of a user's games that has any, by user game id.
+pub fn get_user_game_notes(user_id: i64) -> Result<HashMap<i64, UserGameNote>, Error> {
+    let user_game_ids: Vec<i64> = get_user_games(user_id)?.iter().map(|user_game| user_game.id).collect();
+
+    let conn = get_diesel_conn()?;
+    let notes: Vec<UserGameNote> = user_game_note::table.filter(
+        user_game_note::user_game_id.eq_any(user_game_ids),
+    ).load(&conn).chain_err(|| "unable to load notes")?;
+
+    Ok(notes.into_iter().map(|note| (note.user_game_id, note)).collect())
+}
+
+fn save_user_game_note_with_conn(note: &NewUserGameNote, conn: &SqliteConnection) -> Result<(), diesel::result::Error> {
+    diesel::delete(
+        user_game_note::table.filter(
+            user_game_note::user_game_id.eq(note.user_game_id),
+        )
+    ).execute(conn)?;
+
+    // nothing worth keeping, so leave the entry without a row
+    if note.notes.trim() == "" && note.review.is_none() && note.rating.is_none() {
+        return Ok(());
+    }
+
+    diesel::insert(
+        note,
+    ).into(
+        user_game_note::table,
+    ).execute(conn)?;
+
+    Ok(())
+}
+
+/// Replaces the notes, review and rating on a collection entry.
+pub fn save_user_game_note(note: NewUserGameNote) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction(|| {
+        save_user_game_note_with_conn(&note, &conn)
+    }).chain_err(|| "unable to save notes")
+}
+
+pub fn get_optional_user_game_by_name(user_id: i64, name: &String) -> Result<Option<UserGame>, Error> {
+    let game = match get_optional_game_by_name(name)? {
+        Some(game) => game,
+        None => return Ok(None),
+    };
+
+    let conn = get_diesel_conn()?;
+    user_game::table.filter(
+        user_game::user_id.eq(user_id).and(
+            user_game::game_id.eq(game.id)
+        )
+    ).get_result(&conn).optional().chain_err(|| "unable to load user game")
+}
+
+fn import_user_game_with_conn(
+    user_id: i64,
+    imported: &ImportedUserGame,
+    conn: &SqliteConnection,
+) -> Result<(), diesel::result::Error> {
+    let game_id = match get_game_by_name_with_conn(&imported.name, conn).optional()? {
+        Some(game) => game.id,
+        None => {
+            diesel::insert(
+                &NewGame{
+                    name: imported.name.clone(),
+                    steam_id: None,
+                },
+            ).into(
+                game::table,
+            ).execute(conn)?;
+            get_game_by_name_with_conn(&imported.name, conn)?.id
+        },
+    };
+
+    let existing: Option<UserGame> = user_game::table.filter(
+        user_game::user_id.eq(user_id).and(
+            user_game::game_id.eq(game_id)
+        )
+    ).get_result(conn).optional()?;
+
+    let (user_game_id, play_state, date) = match existing {
+        Some(user_game) => {
+            let play_state = imported.play_state.clone().unwrap_or(user_game.play_state);
+            diesel::update(
+                user_game::table.filter(
+                    user_game::id.eq(user_game.id),
+                )
+            ).set((
+                user_game::platform.eq(imported.platform.clone().unwrap_or(user_game.platform)),
+                user_game::play_state.eq(&play_state),
+                user_game::acquisition_date.eq(imported.acquisition_date.unwrap_or(user_game.acquisition_date)),
+                user_game::start_date.eq(imported.start_date.or(user_game.start_date)),
+                user_game::beat_date.eq(imported.beat_date.or(user_game.beat_date)),
+            )).execute(conn)?;
+            remove_finished_from_up_next_with_conn(user_game.id, &play_state, conn)?;
+            (user_game.id, play_state, time::get_time().sec)
+        },
+        None => {
+            let new_user_game = NewUserGame{
+                game_id: game_id,
+                user_id: user_id,
+                play_state: imported.play_state.clone().unwrap_or("unplayed".to_string()),
+                platform: imported.platform.clone().unwrap_or("other".to_string()),
+                acquisition_date: imported.acquisition_date.unwrap_or(time::get_time().sec),
+                start_date: imported.start_date,
+                beat_date: imported.beat_date,
+            };
+            let user_game_id = insert_user_game_with_conn(&new_user_game, conn)?;
+            (user_game_id, new_user_game.play_state, new_user_game.acquisition_date)
+        },
+    };
+
+    if imported.notes.is_some() || imported.review.is_some() || imported.rating.is_some() {
+        let existing_note: Option<UserGameNote> = user_game_note::table.filter(
+            user_game_note::user_game_id.eq(user_game_id),
+        ).get_result(conn).optional()?;
+        let (notes, review, rating) = match existing_note {
+            Some(note) => (note.notes, note.review, note.rating),
+            None => ("".to_string(), None, None),
+        };
+        save_user_game_note_with_conn(&NewUserGameNote{
+            user_game_id: user_game_id,
+            notes: imported.notes.clone().unwrap_or(notes),
+            review: imported.review.clone().or(review),
+            rating: imported.rating.or(rating),
+        }, conn)?;
+    }
+
+    if imported.history.is_empty() {
+        record_play_state_with_conn(user_game_id, &play_state, date, conn)
+    } else {
+        replace_history_with_conn(user_game_id, &imported.history, conn)
+    }
+}
+
+/// Adds or updates a batch of imported collection entries, all or nothing.
+pub fn import_user_games(user_id: i64, imported: &[ImportedUserGame]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        for imported_game in imported {
+            import_user_game_with_conn(user_id, imported_game, &conn)?;
+        }
+        Ok(())
+    }).chain_err(|| "unable to import games")
+}
+
+pub fn get_game_lists(user_id: i64) -> Result<Vec<GameList>, Error> {
+    let conn = get_diesel_conn()?;
+    game_list::table.filter(
+        game_list::user_id.eq(user_id),
+    ).order(
+        game_list::name.asc(),
+    ).load(&conn).chain_err(|| "unable to load lists")
+}
+
+pub fn get_game_list_by_id(id: i64) -> Result<GameList, Error> {
+    let conn = get_diesel_conn()?;
+    game_list::table.filter(
+        game_list::id.eq(id),
+    ).get_result(&conn).chain_err(|| "unable to find list")
+}
+
+pub fn get_optional_game_list_by_slug(user_id: i64, slug: &String) -> Result<Option<GameList>, Error> {
+    let conn = get_diesel_conn()?;
+    game_list::table.filter(
+        game_list::user_id.eq(user_id).and(
+            game_list::slug.eq(slug)
+        )
+    ).get_result(&conn).optional().chain_err(|| "unable to load list")
+}
+
+pub fn add_game_list(game_list: NewGameList) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::insert(
+        &game_list,
+    ).into(
+        game_list::table,
+    ).execute(&conn).chain_err(|| "unable to save list")?;
+
+    Ok(())
+}
+
+pub fn remove_game_list(user_id: i64, id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let removed = diesel::delete(
+            game_list::table.filter(
+                game_list::id.eq(id).and(
+                    game_list::user_id.eq(user_id)
+                )
+            )
+        ).execute(&conn)?;
+
+        if removed > 0 {
+            diesel::delete(
+                game_list_entry::table.filter(
+                    game_list_entry::game_list_id.eq(id),
+                )
+            ).execute(&conn)?;
+        }
+        Ok(())
+    }).chain_err(|| "unable to remove list")
+}
+
+/// A list's games in order, with their names.
+pub fn get_game_list_entries_with_names(game_list_id: i64) -> Result<Vec<(String, UserGame)>, Error> {
+    let conn = get_diesel_conn()?;
+    let user_game_ids: Vec<i64> = game_list_entry::table.filter(
+        game_list_entry::game_list_id.eq(game_list_id),
+    ).order(
+        game_list_entry::position.asc(),
+    ).select(
+        game_list_entry::user_game_id,
+    ).load(&conn).chain_err(|| "unable to load list entries")?;
+
+    let user_games: Vec<UserGame> = user_game::table.filter(
+        user_game::id.eq_any(user_game_ids.clone()),
+    ).load(&conn).chain_err(|| "unable to load list entries")?;
+    let game_ids: Vec<i64> = user_games.iter().map(|user_game| user_game.game_id).collect();
+    let names: HashMap<i64, String> = game::table.filter(
+        game::id.eq_any(game_ids),
+    ).load::<Game>(&conn).chain_err(|| "unable to get game names")?.into_iter().map(
+        |game| (game.id, game.name)
+    ).collect();
+
+    let mut user_games: HashMap<i64, UserGame> = user_games.into_iter().map(
+        |user_game| (user_game.id, user_game)
+    ).collect();
+    Ok(user_game_ids.into_iter().filter_map(|user_game_id| {
+        user_games.remove(&user_game_id).and_then(|user_game| {
+            names.get(&user_game.game_id).map(|name| (name.clone(), user_game))
+        })
+    }).collect())
+}
+
+/// Adds a game to the bottom of a list, unless it's already on it.
+pub fn add_game_list_entry(game_list_id: i64, user_game_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let entries: Vec<GameListEntry> = game_list_entry::table.filter(
+            game_list_entry::game_list_id.eq(game_list_id),
+        ).load(&conn)?;
+        if entries.iter().any(|entry| entry.user_game_id == user_game_id) {
+            return Ok(());
+        }
+
+        diesel::insert(
+            &NewGameListEntry{
+                game_list_id: game_list_id,
+                user_game_id: user_game_id,
+                position: entries.iter().map(|entry| entry.position + 1).max().unwrap_or(0),
+            },
+        ).into(
+            game_list_entry::table,
+        ).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to add game to list")
+}
+
+pub fn remove_game_list_entry(game_list_id: i64, user_game_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::delete(
+        game_list_entry::table.filter(
+            game_list_entry::game_list_id.eq(game_list_id).and(
+                game_list_entry::user_game_id.eq(user_game_id)
+            )
+        )
+    ).execute(&conn).chain_err(|| "unable to remove game from list")?;
+
+    Ok(())
+}
+
+/// Puts a list's games in the given order. Games on the list but missing from `user_game_ids`
+/// keep their place after the ones that were given.
+pub fn reorder_game_list(game_list_id: i64, user_game_ids: &[i64]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let current_ids: Vec<i64> = game_list_entry::table.filter(
+            game_list_entry::game_list_id.eq(game_list_id),
+        ).order(
+            game_list_entry::position.asc(),
+        ).select(
+            game_list_entry::user_game_id,
+        ).load(&conn)?;
+
+        reorder_with_conn(&current_ids, user_game_ids, |user_game_id, position| {
+            diesel::update(
+                game_list_entry::table.filter(
+                    game_list_entry::game_list_id.eq(game_list_id).and(
+                        game_list_entry::user_game_id.eq(user_game_id)
+                    )
+                )
+            ).set(
+                game_list_entry::position.eq(position),
+            ).execute(&conn)
+        })
+    }).chain_err(|| "unable to reorder list")
+}
+
+/// Renumbers positions so `user_game_ids` come first in that order, followed by the rest of
+/// `current_ids` in their current order. Ids that aren't in `current_ids` are ignored.
+fn reorder_with_conn<F>(current_ids: &[i64], user_game_ids: &[i64], mut set_position: F) -> Result<(), diesel::result::Error>
+    where F: FnMut(i64, i64) -> Result<usize, diesel::result::Error>
+{
+    let mut ordered_ids: Vec<i64> = user_game_ids.iter().cloned().filter(
+        |user_game_id| current_ids.contains(user_game_id)
+    ).collect();
+    for &user_game_id in current_ids {
+        if !ordered_ids.contains(&user_game_id) {
+            ordered_ids.push(user_game_id);
+        }
+    }
+
+    for (position, user_game_id) in ordered_ids.into_iter().enumerate() {
+        set_position(user_game_id, position as i64)?;
+    }
+    Ok(())
+}
+
+pub fn get_tags(user_id: i64) -> Result<Vec<Tag>, Error> {
+    let conn = get_diesel_conn()?;
+    tag::table.filter(
+        tag::user_id.eq(user_id),
+    ).order(
+        tag::name.asc(),
+    ).load(&conn).chain_err(|| "unable to load tags")
+}
+
+/// The tags on each of a user's games that has any, by user game id.
+pub fn get_user_game_tags(user_id: i64) -> Result<HashMap<i64, Vec<Tag>>, Error> {
+    let tags: HashMap<i64, Tag> = get_tags(user_id)?.into_iter().map(|tag| (tag.id, tag)).collect();
+    let tag_ids: Vec<i64> = tags.keys().cloned().collect();
+
+    let conn = get_diesel_conn()?;
+    let user_game_tags: Vec<UserGameTag> = user_game_tag::table.filter(
+        user_game_tag::tag_id.eq_any(tag_ids),
+    ).load(&conn).chain_err(|| "unable to load tags")?;
+
+    let mut tags_by_user_game: HashMap<i64, Vec<Tag>> = HashMap::new();
+    for user_game_tag in user_game_tags {
+        if let Some(tag) = tags.get(&user_game_tag.tag_id) {
+            tags_by_user_game.entry(user_game_tag.user_game_id).or_insert_with(Vec::new).push(tag.clone());
+        }
+    }
+    for user_game_tags in tags_by_user_game.values_mut() {
+        user_game_tags.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+    Ok(tags_by_user_game)
+}
+
+/// Tags are matched by slug, so "Co-op" and "co op" are the same tag.
+fn find_or_add_tag_with_conn(user_id: i64, name: &str, conn: &SqliteConnection) -> Result<i64, diesel::result::Error> {
+    let slug = slugify(name);
+    let existing: Option<Tag> = tag::table.filter(
+        tag::user_id.eq(user_id).and(
+            tag::slug.eq(&slug)
+        )
+    ).get_result(conn).optional()?;
+    if let Some(existing) = existing {
+        return Ok(existing.id);
+    }
+
+    diesel::insert(
+        &NewTag{
+            user_id: user_id,
+            name: name.trim().to_string(),
+            slug: slug.clone(),
+        },
+    ).into(
+        tag::table,
+    ).execute(conn)?;
+    tag::table.filter(
+        tag::user_id.eq(user_id).and(
+            tag::slug.eq(&slug)
+        )
+    ).select(
+        tag::id,
+    ).get_result(conn)
+}
+
+fn add_user_game_tag_with_conn(user_game_id: i64, tag_id: i64, conn: &SqliteConnection) -> Result<(), diesel::result::Error> {
+    let existing: Option<UserGameTag> = user_game_tag::table.filter(
+        user_game_tag::user_game_id.eq(user_game_id).and(
+            user_game_tag::tag_id.eq(tag_id)
+        )
+    ).get_result(conn).optional()?;
+    if existing.is_some() {
+        return Ok(());
+    }
+
+    diesel::insert(
+        &NewUserGameTag{
+            user_game_id: user_game_id,
+            tag_id: tag_id,
+        },
+    ).into(
+        user_game_tag::table,
+    ).execute(conn)?;
+    Ok(())
+}
+
+// tags only exist as long as something is tagged with them
+fn remove_unused_tags_with_conn(user_id: i64, conn: &SqliteConnection) -> Result<(), diesel::result::Error> {
+    let tag_ids: Vec<i64> = tag::table.filter(
+        tag::user_id.eq(user_id),
+    ).select(
+        tag::id,
+    ).load(conn)?;
+    let used_tag_ids: Vec<i64> = user_game_tag::table.filter(
+        user_game_tag::tag_id.eq_any(tag_ids.clone()),
+    ).select(
+        user_game_tag::tag_id,
+    ).load(conn)?;
+    let unused_tag_ids: Vec<i64> = tag_ids.into_iter().filter(|tag_id| !used_tag_ids.contains(tag_id)).collect();
+
+    diesel::delete(
+        tag::table.filter(
+            tag::id.eq_any(unused_tag_ids),
+        )
+    ).execute(conn)?;
+    Ok(())
+}
+
+/// Tags each of the given games, all of which have to be the user's.
+pub fn tag_user_games(user_id: i64, tag_name: &str, user_game_ids: &[i64]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let tag_id = find_or_add_tag_with_conn(user_id, tag_name, &conn)?;
+        for user_game_id in user_game_ids {
+            add_user_game_tag_with_conn(*user_game_id, tag_id, &conn)?;
+        }
+        Ok(())
+    }).chain_err(|| "unable to tag games")
+}
+
+pub fn untag_user_games(user_id: i64, tag_name: &str, user_game_ids: &[i64]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let tag: Option<Tag> = tag::table.filter(
+            tag::user_id.eq(user_id).and(
+                tag::slug.eq(slugify(tag_name))
+            )
+        ).get_result(&conn).optional()?;
+        let tag = match tag {
+            Some(tag) => tag,
+            None => return Ok(()),
+        };
+
+        diesel::delete(
+            user_game_tag::table.filter(
+                user_game_tag::tag_id.eq(tag.id).and(
+                    user_game_tag::user_game_id.eq_any(user_game_ids.to_vec())
+                )
+            )
+        ).execute(&conn)?;
+        remove_unused_tags_with_conn(user_id, &conn)
+    }).chain_err(|| "unable to untag games")
+}
+
+/// Replaces all of a game's tags with the given ones.
+pub fn set_user_game_tags(user_id: i64, user_game_id: i64, tag_names: &[String]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::delete(
+            user_game_tag::table.filter(
+                user_game_tag::user_game_id.eq(user_game_id),
+            )
+        ).execute(&conn)?;
+        for tag_name in tag_names {
+            let tag_id = find_or_add_tag_with_conn(user_id, tag_name, &conn)?;
+            add_user_game_tag_with_conn(user_game_id, tag_id, &conn)?;
+        }
+        remove_unused_tags_with_conn(user_id, &conn)
+    }).chain_err(|| "unable to save tags")
+}
+
+/// A user's up next queue in order, with the names of the games.
+pub fn get_up_next_with_names(user_id: i64) -> Result<Vec<(String, UpNextEntry)>, Error> {
+    let conn = get_diesel_conn()?;
+    let entries: Vec<UpNextEntry> = up_next_entry::table.filter(
+        up_next_entry::user_id.eq(user_id),
+    ).order(
+        up_next_entry::position.asc(),
+    ).load(&conn).chain_err(|| "unable to load up next queue")?;
+
+    let names: HashMap<i64, String> = get_user_games_with_names(user_id)?.into_iter().map(
+        |(name, user_game)| (user_game.id, name)
+    ).collect();
+    Ok(entries.into_iter().filter_map(
+        |entry| names.get(&entry.user_game_id).map(|name| (name.clone(), entry))
+    ).collect())
+}
+
+/// Adds a game to the queue after everything with the same or a higher priority, unless it's
+/// already queued.
+pub fn add_up_next_entry(user_id: i64, user_game_id: i64, priority: i64, estimated_hours: Option<i64>) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let entries: Vec<UpNextEntry> = up_next_entry::table.filter(
+            up_next_entry::user_id.eq(user_id),
+        ).order(
+            up_next_entry::position.asc(),
+        ).load(&conn)?;
+        if entries.iter().any(|entry| entry.user_game_id == user_game_id) {
+            return Ok(());
+        }
+
+        let position = entries.iter().filter(
+            |entry| entry.priority <= priority
+        ).map(|entry| entry.position + 1).max().unwrap_or(0);
+        for entry in entries.iter().filter(|entry| entry.position >= position) {
+            diesel::update(
+                up_next_entry::table.filter(
+                    up_next_entry::id.eq(entry.id),
+                )
+            ).set(
+                up_next_entry::position.eq(entry.position + 1),
+            ).execute(&conn)?;
+        }
+
+        diesel::insert(
+            &NewUpNextEntry{
+                user_id: user_id,
+                user_game_id: user_game_id,
+                position: position,
+                priority: priority,
+                estimated_hours: estimated_hours,
+            },
+        ).into(
+            up_next_entry::table,
+        ).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to add game to up next")
+}
+
+pub fn update_up_next_entry(user_id: i64, user_game_id: i64, priority: i64, estimated_hours: Option<i64>) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        up_next_entry::table.filter(
+            up_next_entry::user_id.eq(user_id).and(
+                up_next_entry::user_game_id.eq(user_game_id)
+            )
+        )
+    ).set((
+        up_next_entry::priority.eq(priority),
+        up_next_entry::estimated_hours.eq(estimated_hours),
+    )).execute(&conn).chain_err(|| "unable to update up next")?;
+
+    Ok(())
+}
+
+pub fn set_up_next_now_playing(user_id: i64, user_game_id: i64, now_playing: bool) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        up_next_entry::table.filter(
+            up_next_entry::user_id.eq(user_id).and(
+                up_next_entry::user_game_id.eq(user_game_id)
+            )
+        )
+    ).set(
+        up_next_entry::now_playing.eq(now_playing),
+    ).execute(&conn).chain_err(|| "unable to update up next")?;
+
+    Ok(())
+}
+
+pub fn remove_up_next_entry(user_id: i64, user_game_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::delete(
+        up_next_entry::table.filter(
+            up_next_entry::user_id.eq(user_id).and(
+                up_next_entry::user_game_id.eq(user_game_id)
+            )
+        )
+    ).execute(&conn).chain_err(|| "unable to remove game from up next")?;
+
+    Ok(())
+}
+
+fn remove_finished_from_up_next_with_conn(
+    user_game_id: i64,
+    play_state: &str,
+    conn: &SqliteConnection,
+) -> Result<(), diesel::result::Error> {
+    if FINISHED_PLAY_STATES.contains(&play_state) {
+        diesel::delete(
+            up_next_entry::table.filter(
+                up_next_entry::user_game_id.eq(user_game_id),
+            )
+        ).execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Puts the queue in the given order. Queued games missing from `user_game_ids` keep their
+/// place after the ones that were given.
+pub fn reorder_up_next(user_id: i64, user_game_ids: &[i64]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let current_ids: Vec<i64> = up_next_entry::table.filter(
+            up_next_entry::user_id.eq(user_id),
+        ).order(
+            up_next_entry::position.asc(),
+        ).select(
+            up_next_entry::user_game_id,
+        ).load(&conn)?;
+
+        reorder_with_conn(&current_ids, user_game_ids, |user_game_id, position| {
+            diesel::update(
+                up_next_entry::table.filter(
+                    up_next_entry::user_id.eq(user_id).and(
+                        up_next_entry::user_game_id.eq(user_game_id)
+                    )
+                )
+            ).set(
+                up_next_entry::position.eq(position),
+            ).execute(&conn)
+        })
+    }).chain_err(|| "unable to reorder up next")
+}
+
+/// Aggregate numbers about a user's collection, for the stats page. Months are `YYYY-MM` and
+/// years `YYYY`, in UTC.
+#[derive(Serialize)]
+pub struct CollectionStats {
+    pub total: i64,
+    pub by_play_state: BTreeMap<String, i64>,
+    pub by_platform: BTreeMap<String, i64>,
+    /// Counts by play state within each platform.
+    pub by_platform_and_play_state: BTreeMap<String, BTreeMap<String, i64>>,
+    /// The share of games that can be finished which have been beaten, completed or 100%'d.
+    /// Multiplayer games and ones marked null don't count.
+    pub completion_rate: Option<f64>,
+    pub beaten_by_year: BTreeMap<String, i64>,
+    pub beaten_by_month: BTreeMap<String, i64>,
+    /// Keyed by `YYYY-MM-DD`.
+    pub beaten_by_day: BTreeMap<String, i64>,
+    pub acquired_by_month: BTreeMap<String, i64>,
+    /// How many games were unplayed or unfinished at the end of each month, from the month the
+    /// first game was added through the current one.
+    pub backlog_by_month: BTreeMap<String, i64>,
+    pub average_days_to_beat: Option<f64>,
+    /// In minutes, from storefront syncs like Steam's.
+    pub total_playtime: i64,
+}
+
+/// Counts a user's games grouped by an SQL expression, only including rows matching `condition`.
+fn count_user_games_by(user_id: i64, group: &str, condition: &str, conn: &SqliteConnection) -> Result<BTreeMap<String, i64>, diesel::result::Error> {
+    let counts: Vec<(String, i64)> = user_game::table.filter(
+        user_game::user_id.eq(user_id).and(
+            sql::<Bool>(condition)
+        )
+    ).group_by(
+        sql::<Text>(group),
+    ).select((
+        sql::<Text>(group),
+        sql::<BigInt>("COUNT(*)"),
+    )).load(conn)?;
+    Ok(counts.into_iter().collect())
+}
+
+fn get_month_start(year: i32, month: i32) -> Result<i64, Error> {
+    let tm = time::strptime(&format!("{}-{:02}-01", year, month), "%Y-%m-%d").chain_err(|| "invalid month")?;
+    Ok(tm.to_timespec().sec)
+}
+
+/// Replays each game's play state history to count its backlog at the end of every month.
+fn get_backlog_by_month(user_games: &[UserGame], history: &[UserGameHistory], now: i64) -> Result<BTreeMap<String, i64>, Error> {
+    let mut history_by_user_game: HashMap<i64, Vec<&UserGameHistory>> = HashMap::new();
+    for entry in history {
+        history_by_user_game.entry(entry.user_game_id).or_insert_with(Vec::new).push(entry);
+    }
+    let first_date = match user_games.iter().map(|user_game| user_game.acquisition_date).chain(
+        history.iter().map(|entry| entry.date)
+    ).min() {
+        Some(first_date) => first_date,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    let first_month = time::at_utc(time::Timespec::new(first_date, 0));
+    let (mut year, mut month) = (first_month.tm_year + 1900, first_month.tm_mon + 1);
+    let mut backlog_by_month = BTreeMap::new();
+    loop {
+        let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        let month_end = get_month_start(next_year, next_month)?;
+        let backlog = user_games.iter().filter(|user_game| {
+            let play_state = match history_by_user_game.get(&user_game.id) {
+                Some(entries) => entries.iter().take_while(
+                    |entry| entry.date < month_end
+                ).last().map(|entry| entry.play_state.as_str()),
+                // without any history, a game has been in its play state since it was acquired
+                None if user_game.acquisition_date < month_end => Some(user_game.play_state.as_str()),
+                None => None,
+            };
+            play_state == Some("unplayed") || play_state == Some("unfinished")
+        }).count() as i64;
+        backlog_by_month.insert(format!("{}-{:02}", year, month), backlog);
+
+        if month_end > now {
+            return Ok(backlog_by_month);
+        }
+        year = next_year;
+        month = next_month;
+    }
+}
+
+pub fn get_collection_stats(user_id: i64) -> Result<CollectionStats, Error> {
+    let conn = get_diesel_conn()?;
+
+    let by_play_state = count_user_games_by(user_id, "play_state", "1", &conn).chain_err(
+        || "unable to count games by play state"
+    )?;
+    let by_platform = count_user_games_by(user_id, "platform", "1", &conn).chain_err(
+        || "unable to count games by platform"
+    )?;
+    let platform_play_state_counts: Vec<(String, String, i64)> = user_game::table.filter(
+        user_game::user_id.eq(user_id),
+    ).group_by(
+        (user_game::platform, user_game::play_state),
+    ).select((
+        user_game::platform,
+        user_game::play_state,
+        sql::<BigInt>("COUNT(*)"),
+    )).load(&conn).chain_err(|| "unable to count games by platform")?;
+    let mut by_platform_and_play_state: BTreeMap<String, BTreeMap<String, i64>> = BTreeMap::new();
+    for (platform, play_state, count) in platform_play_state_counts {
+        by_platform_and_play_state.entry(platform).or_insert_with(BTreeMap::new).insert(play_state, count);
+    }
+    let acquired_by_month = count_user_games_by(
+        user_id,
+        "strftime('%Y-%m', acquisition_date, 'unixepoch')",
+        "1",
+        &conn,
+    ).chain_err(|| "unable to count games acquired")?;
+
+    // games played through the web forms only have play state history, not beat or start dates,
+    // so finishes are worked out the same way goals do it
+    let user_games = get_user_games(user_id)?;
+    let history = get_user_game_history(user_id)?;
+    let finish_dates = get_finish_dates(&user_games, &history);
+    let mut beaten_by_year = BTreeMap::new();
+    let mut beaten_by_month = BTreeMap::new();
+    let mut beaten_by_day = BTreeMap::new();
+    for &finish_date in finish_dates.values() {
+        *beaten_by_year.entry(format_timestamp(finish_date, "%Y")).or_insert(0) += 1;
+        *beaten_by_month.entry(format_timestamp(finish_date, "%Y-%m")).or_insert(0) += 1;
+        *beaten_by_day.entry(format_timestamp(finish_date, "%Y-%m-%d")).or_insert(0) += 1;
+    }
+
+    // likewise a game was started on its start date, or failing that when it was first unfinished
+    let mut start_dates: HashMap<i64, i64> = HashMap::new();
+    for entry in &history {
+        if entry.play_state == "unfinished" {
+            start_dates.entry(entry.user_game_id).or_insert(entry.date);
+        }
+    }
+    for user_game in &user_games {
+        if let Some(start_date) = user_game.start_date {
+            start_dates.insert(user_game.id, start_date);
+        }
+    }
+    let days_to_beat: Vec<f64> = finish_dates.iter().filter_map(|(user_game_id, &finish_date)| {
+        start_dates.get(user_game_id).and_then(|&start_date| {
+            if finish_date >= start_date { Some((finish_date - start_date) as f64 / 86400.0) } else { None }
+        })
+    }).collect();
+    let average_days_to_beat = if days_to_beat.is_empty() {
+        None
+    } else {
+        Some(days_to_beat.iter().sum::<f64>() / days_to_beat.len() as f64)
+    };
+
+    let backlog_by_month = get_backlog_by_month(&user_games, &history, time::get_time().sec)?;
+
+    let user_game_ids: Vec<i64> = user_games.iter().map(|user_game| user_game.id).collect();
+    let total_playtime: Option<i64> = user_game_playtime::table.filter(
+        user_game_playtime::user_game_id.eq_any(user_game_ids),
+    ).select(
+        sql::<Nullable<BigInt>>("SUM(playtime_forever)"),
+    ).get_result(&conn).chain_err(|| "unable to total playtime")?;
+
+    let total: i64 = by_play_state.values().sum();
+    let completion_rate = {
+        let count_of = |play_states: &[&str]| -> i64 {
+            play_states.iter().filter_map(|play_state| by_play_state.get(*play_state)).sum()
+        };
+        let finishable = total - count_of(&["multiplayer", "null"]);
+        if finishable > 0 {
+            Some(count_of(&["beaten", "completed", "100_percent"]) as f64 / finishable as f64)
+        } else {
+            None
+        }
+    };
+
+    Ok(CollectionStats{
+        total: total,
+        by_play_state: by_play_state,
+        by_platform: by_platform,
+        by_platform_and_play_state: by_platform_and_play_state,
+        completion_rate: completion_rate,
+        beaten_by_year: beaten_by_year,
+        beaten_by_month: beaten_by_month,
+        beaten_by_day: beaten_by_day,
+        acquired_by_month: acquired_by_month,
+        backlog_by_month: backlog_by_month,
+        average_days_to_beat: average_days_to_beat,
+        total_playtime: total_playtime.unwrap_or(0),
+    })
+}
+
+pub fn get_goals(user_id: i64) -> Result<Vec<Goal>, Error> {
+    let conn = get_diesel_conn()?;
+    goal::table.filter(
+        goal::user_id.eq(user_id),
+    ).order(
+        goal::id.asc(),
+    ).load(&conn).chain_err(|| "unable to load goals")
+}
+
+pub fn add_goal(goal: NewGoal) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::insert(
+        &goal,
+    ).into(
+        goal::table,
+    ).execute(&conn).chain_err(|| "unable to save goal")?;
+
+    Ok(())
+}
+
+pub fn remove_goal(user_id: i64, id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::delete(
+        goal::table.filter(
+            goal::id.eq(id).and(
+                goal::user_id.eq(user_id)
+            )
+        )
+    ).execute(&conn).chain_err(|| "unable to remove goal")?;
+
+    Ok(())
+}
+
+pub fn mark_goal_met(id: i64, met_date: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        goal::table.filter(
+            goal::id.eq(id),
+        )
+    ).set(
+        goal::met_date.eq(Some(met_date)),
+    ).execute(&conn).chain_err(|| "unable to update goal")?;
+
+    Ok(())
+}
+
+pub fn get_optional_user_by_email(email: &str) -> Result<Option<User>, Error> {
+    let conn = get_diesel_conn()?;
+    user::table.filter(
+        user::email.eq(email),
+    ).get_result(&conn).optional().chain_err(|| "unable to load user")
+}
+
+fn hash_token(token: &str) -> String {
+    let mut token_hash = String::new();
+    for byte in Sha256::digest(token.as_bytes()).iter() {
+        write!(&mut token_hash, "{:02x}", byte).unwrap();
+    }
+    token_hash
+}
+
+/// Makes a new token for `purpose` that's good for `lifetime` seconds, returning the token to
+/// send to the user.
+pub fn create_user_token(user_id: i64, purpose: &str, new_email: Option<String>, lifetime: i64) -> Result<String, Error> {
+    let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
+    let mut token_bytes: Vec<u8> = vec![0; 32];
+    rng.fill_bytes(&mut token_bytes);
+    let mut token = String::new();
+    for byte in token_bytes.iter() {
+        write!(&mut token, "{:02x}", byte).unwrap();
+    }
+
+    let conn = get_diesel_conn()?;
+    diesel::insert(
+        &NewUserToken{
+            user_id: user_id,
+            purpose: purpose.to_string(),
+            token_hash: hash_token(&token),
+            new_email: new_email,
+            expires_date: time::get_time().sec + lifetime,
+        },
+    ).into(
+        user_token::table,
+    ).execute(&conn).chain_err(|| "unable to save token")?;
+
+    Ok(token)
+}
+
+/// Looks up a token for `purpose` that hasn't expired or been used yet, and uses it up.
+pub fn use_user_token(token: &str, purpose: &str) -> Result<Option<UserToken>, Error> {
+    let now = time::get_time().sec;
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let user_token: Option<UserToken> = user_token::table.filter(
+            user_token::token_hash.eq(hash_token(token)).and(
+                user_token::purpose.eq(purpose)
+            ).and(
+                user_token::used_date.is_null()
+            ).and(
+                user_token::expires_date.gt(now)
+            )
+        ).get_result(&conn).optional()?;
+
+        if let Some(ref user_token) = user_token {
+            diesel::update(
+                user_token::table.filter(
+                    user_token::id.eq(user_token.id),
+                )
+            ).set(
+                user_token::used_date.eq(Some(now)),
+            ).execute(&conn)?;
+        }
+        Ok(user_token)
+    }).chain_err(|| "unable to use token")
+}
+
+/// Uses up every outstanding token a user has for `purpose`, e.g. once their password is reset.
+pub fn expire_user_tokens(user_id: i64, purpose: &str) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user_token::table.filter(
+            user_token::user_id.eq(user_id).and(
+                user_token::purpose.eq(purpose)
+            ).and(
+                user_token::used_date.is_null()
+            )
+        )
+    ).set(
+        user_token::used_date.eq(Some(time::get_time().sec)),
+    ).execute(&conn).chain_err(|| "unable to expire tokens")?;
+
+    Ok(())
+}
+
+pub fn mark_email_verified(user_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user::table.filter(
+            user::id.eq(user_id),
+        )
+    ).set(
+        user::email_verified.eq(true),
+    ).execute(&conn).chain_err(|| "unable to verify email")?;
+
+    Ok(())
+}
+
+/// Changes a user's email to one they've confirmed they can read.
+pub fn update_email(user_id: i64, email: String) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user::table.filter(
+            user::id.eq(user_id),
+        )
+    ).set((
+        user::email.eq(email),
+        user::email_verified.eq(true),
+    )).execute(&conn).chain_err(|| "unable to update email")?;
+
+    Ok(())
+}
+
+pub fn update_password(user_id: i64, password: &str) -> Result<(), Error> {
+    let password_hash = password::hash(password)?;
+
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user_private::table.filter(
+            user_private::user_id.eq(user_id),
+        )
+    ).set((
+        user_private::password_hash.eq(password_hash.hash),
+        user_private::salt.eq(password_hash.salt),
+        user_private::hash_version.eq(password_hash.version),
+    )).execute(&conn).chain_err(|| "unable to update password")?;
+
+    Ok(())
+}
+
+/// Removes a user and everything they've added, all at once.
+pub fn delete_user(user_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        let user_game_ids: Vec<i64> = user_game::table.select(
+            user_game::id,
+        ).filter(
+            user_game::user_id.eq(user_id),
+        ).load(&conn)?;
+        let game_list_ids: Vec<i64> = game_list::table.select(
+            game_list::id,
+        ).filter(
+            game_list::user_id.eq(user_id),
+        ).load(&conn)?;
+
+        diesel::delete(
+            user_game_achievement::table.filter(user_game_achievement::user_game_id.eq_any(user_game_ids.clone()))
+        ).execute(&conn)?;
+        diesel::delete(
+            user_game_playtime::table.filter(user_game_playtime::user_game_id.eq_any(user_game_ids.clone()))
+        ).execute(&conn)?;
+        diesel::delete(
+            user_game_history::table.filter(user_game_history::user_game_id.eq_any(user_game_ids.clone()))
+        ).execute(&conn)?;
+        diesel::delete(
+            user_game_note::table.filter(user_game_note::user_game_id.eq_any(user_game_ids.clone()))
+        ).execute(&conn)?;
+        diesel::delete(
+            user_game_tag::table.filter(user_game_tag::user_game_id.eq_any(user_game_ids))
+        ).execute(&conn)?;
+        diesel::delete(
+            game_list_entry::table.filter(game_list_entry::game_list_id.eq_any(game_list_ids))
+        ).execute(&conn)?;
+
+        diesel::delete(game_list::table.filter(game_list::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(tag::table.filter(tag::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(up_next_entry::table.filter(up_next_entry::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(goal::table.filter(goal::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(user_token::table.filter(user_token::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(login_attempt::table.filter(login_attempt::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(user_two_factor::table.filter(user_two_factor::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(recovery_code::table.filter(recovery_code::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(wishlist_game::table.filter(wishlist_game::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(storefront_account::table.filter(storefront_account::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(user_game::table.filter(user_game::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(user_private::table.filter(user_private::user_id.eq(user_id))).execute(&conn)?;
+        diesel::delete(user::table.filter(user::id.eq(user_id))).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to delete user")
+}
+
+pub fn get_two_factor(user_id: i64) -> Result<Option<UserTwoFactor>, Error> {
+    let conn = get_diesel_conn()?;
+    user_two_factor::table.filter(
+        user_two_factor::user_id.eq(user_id),
+    ).get_result(&conn).optional().chain_err(|| "unable to load two-factor authentication")
+}
+
+/// Starts setting up two-factor authentication with a new secret, which doesn't take effect
+/// until `enable_two_factor`.
+pub fn start_two_factor(user_id: i64, secret: String) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::delete(
+            user_two_factor::table.filter(
+                user_two_factor::user_id.eq(user_id),
+            )
+        ).execute(&conn)?;
+        diesel::insert(
+            &NewUserTwoFactor{
+                user_id: user_id,
+                secret: secret,
+                enabled: false,
+            },
+        ).into(
+            user_two_factor::table,
+        ).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to start setting up two-factor authentication")
+}
+
+/// Turns on two-factor authentication once its first code has been given, replacing any old
+/// recovery codes with `recovery_codes`.
+pub fn enable_two_factor(user_id: i64, step: i64, recovery_codes: &[String]) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::update(
+            user_two_factor::table.filter(
+                user_two_factor::user_id.eq(user_id),
+            )
+        ).set((
+            user_two_factor::enabled.eq(true),
+            user_two_factor::last_used_step.eq(Some(step)),
+        )).execute(&conn)?;
+
+        diesel::delete(
+            recovery_code::table.filter(
+                recovery_code::user_id.eq(user_id),
+            )
+        ).execute(&conn)?;
+        let new_recovery_codes: Vec<NewRecoveryCode> = recovery_codes.iter().map(
+            |code| NewRecoveryCode{
+                user_id: user_id,
+                code_hash: hash_token(&two_factor::normalize_recovery_code(code)),
+            }
+        ).collect();
+        diesel::insert(
+            &new_recovery_codes,
+        ).into(
+            recovery_code::table,
+        ).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to turn on two-factor authentication")
+}
+
+/// Remembers the time step of the last code used, so it can't be used again.
+fn update_two_factor_step(user_id: i64, step: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    diesel::update(
+        user_two_factor::table.filter(
+            user_two_factor::user_id.eq(user_id),
+        )
+    ).set(
+        user_two_factor::last_used_step.eq(Some(step)),
+    ).execute(&conn).chain_err(|| "unable to update two-factor authentication")?;
+
+    Ok(())
+}
+
+/// Uses up a recovery code, if it's one of the user's and hasn't been used yet.
+fn use_recovery_code(user_id: i64, code: &str) -> Result<bool, Error> {
+    let conn = get_diesel_conn()?;
+    let used = diesel::update(
+        recovery_code::table.filter(
+            recovery_code::user_id.eq(user_id).and(
+                recovery_code::code_hash.eq(hash_token(&two_factor::normalize_recovery_code(code)))
+            ).and(
+                recovery_code::used_date.is_null()
+            )
+        )
+    ).set(
+        recovery_code::used_date.eq(Some(time::get_time().sec)),
+    ).execute(&conn).chain_err(|| "unable to use recovery code")?;
+
+    Ok(used > 0)
+}
+
+pub fn count_unused_recovery_codes(user_id: i64) -> Result<i64, Error> {
+    let conn = get_diesel_conn()?;
+    let codes: Vec<i64> = recovery_code::table.select(
+        recovery_code::id,
+    ).filter(
+        recovery_code::user_id.eq(user_id).and(
+            recovery_code::used_date.is_null()
+        )
+    ).load(&conn).chain_err(|| "unable to load recovery codes")?;
+
+    Ok(codes.len() as i64)
+}
+
+pub fn disable_two_factor(user_id: i64) -> Result<(), Error> {
+    let conn = get_diesel_conn()?;
+    conn.transaction::<_, diesel::result::Error, _>(|| {
+        diesel::delete(
+            user_two_factor::table.filter(
+                user_two_factor::user_id.eq(user_id),
+            )
+        ).execute(&conn)?;
+        diesel::delete(
+            recovery_code::table.filter(
+                recovery_code::user_id.eq(user_id),
+            )
+        ).execute(&conn)?;
+        Ok(())
+    }).chain_err(|| "unable to turn off two-factor authentication")
+}
@@ -0,0 +1,93 @@
+use argon2;
+use bcrypt;
+use rand::OsRng;
+use rand::Rng;
+
+use errors::Error;
+use errors::ResultExt;
+use secrets::PasswordHashingConfig;
+use secrets::get_secrets;
+
+/// The password with a hex salt appended, bcrypted. Every account made before Argon2id has one
+/// of these until its next login.
+pub const LEGACY_BCRYPT: i64 = 1;
+/// An Argon2id hash in its encoded form, which carries its own salt and parameters.
+pub const ARGON2ID: i64 = 2;
+
+const SALT_LENGTH: usize = 16;
+const HASH_LENGTH: u32 = 32;
+
+/// A stored password hash, with which scheme made it in `version`.
+pub struct PasswordHash {
+    pub version: i64,
+    pub hash: String,
+    /// Only used by legacy hashes; newer schemes keep their salt in `hash`.
+    pub salt: String,
+}
+
+fn get_config() -> PasswordHashingConfig {
+    get_secrets().ok().and_then(|secrets| secrets.password_hashing).unwrap_or_default()
+}
+
+fn get_argon2_config(config: &PasswordHashingConfig) -> argon2::Config<'static> {
+    argon2::Config{
+        variant: argon2::Variant::Argon2id,
+        version: argon2::Version::Version13,
+        mem_cost: config.memory_cost,
+        time_cost: config.time_cost,
+        lanes: config.lanes,
+        thread_mode: argon2::ThreadMode::Sequential,
+        secret: &[],
+        ad: &[],
+        hash_length: HASH_LENGTH,
+    }
+}
+
+/// What the start of an encoded hash made with `config` looks like.
+fn get_encoded_prefix(config: &PasswordHashingConfig) -> String {
+    format!("$argon2id$v=19$m={},t={},p={}$", config.memory_cost, config.time_cost, config.lanes)
+}
+
+/// Hashes a password with the current scheme and the configured parameters.
+pub fn hash(password: &str) -> Result<PasswordHash, Error> {
+    let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
+    let mut salt: Vec<u8> = vec![0; SALT_LENGTH];
+    rng.fill_bytes(&mut salt);
+
+    let config = get_config();
+    let hash = argon2::hash_encoded(
+        password.as_bytes(),
+        &salt,
+        &get_argon2_config(&config),
+    ).chain_err(|| "unable to hash password")?;
+
+    Ok(PasswordHash{
+        version: ARGON2ID,
+        hash: hash,
+        salt: "".to_string(),
+    })
+}
+
+pub fn verify(password: &str, stored: &PasswordHash) -> Result<bool, Error> {
+    match stored.version {
+        LEGACY_BCRYPT => {
+            let salted_password = format!("{}{}", password, stored.salt);
+            bcrypt::verify(
+                salted_password.as_str(),
+                stored.hash.as_str(),
+            ).chain_err(|| "error while verifying hashed password")
+        },
+        ARGON2ID => argon2::verify_encoded(
+            stored.hash.as_str(),
+            password.as_bytes(),
+        ).chain_err(|| "error while verifying hashed password"),
+        version => Err(format!("unknown password hash version {}", version).into()),
+    }
+}
+
+/// Whether a hash was made with an older scheme or different parameters than the configured
+/// ones, and so should be replaced the next time the password is known.
+pub fn needs_rehash(stored: &PasswordHash) -> bool {
+    stored.version != ARGON2ID || !stored.hash.starts_with(&get_encoded_prefix(&get_config()))
+}
+
@@ -6,7 +6,51 @@ use std::fs::File;
 
 #[derive(Serialize, Deserialize)]
 pub(crate) struct Secrets {
-    pub(crate) steam_api_key: String
+    #[serde(default)]
+    pub(crate) steam_api_key: String,
+    /// Where the webapp is reachable from, for links in emails.
+    #[serde(default)]
+    pub(crate) base_url: Option<String>,
+    #[serde(default)]
+    pub(crate) mail: Option<MailConfig>,
+    #[serde(default)]
+    pub(crate) password_hashing: Option<PasswordHashingConfig>,
+}
+
+/// How to send email, see `mail::get_transport`.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+pub(crate) enum MailConfig {
+    Smtp {
+        host: String,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+    },
+    File {
+        directory: String,
+    },
+    Stdout,
+}
+
+/// Argon2id parameters for new password hashes. Existing hashes are redone with these the next
+/// time their user logs in.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct PasswordHashingConfig {
+    /// In KiB.
+    pub(crate) memory_cost: u32,
+    pub(crate) time_cost: u32,
+    pub(crate) lanes: u32,
+}
+
+impl Default for PasswordHashingConfig {
+    fn default() -> PasswordHashingConfig {
+        PasswordHashingConfig{
+            memory_cost: 19456,
+            time_cost: 2,
+            lanes: 1,
+        }
+    }
 }
 
 pub(crate) fn get_secrets() -> Result<Secrets, errors::Error> {
@@ -1,5 +1,11 @@
+use iron::IronResult;
+use iron::Request;
+use iron::Response;
+use iron::middleware::AroundMiddleware;
+use iron::middleware::Handler;
 use typemap;
 
+/// Someone who's logged in.
 #[derive(Serialize, Deserialize)]
 pub struct Session {
     pub user_id: i64
@@ -10,3 +16,70 @@ pub struct SessionKey {}
 impl typemap::Key for SessionKey {
     type Value = Session;
 }
+
+/// Someone who's given the right password for an account with two-factor authentication, and
+/// still has to give a code before they're logged in.
+#[derive(Serialize, Deserialize)]
+pub struct PendingLogin {
+    pub user_id: i64,
+    pub expires: i64,
+}
+
+pub struct PendingLoginKey {}
+
+impl typemap::Key for PendingLoginKey {
+    type Value = PendingLogin;
+}
+
+/// Everything kept in the session cookie. Handlers don't see this, they see its parts under
+/// `SessionKey` and `PendingLoginKey`; see `SessionSplitter`.
+#[derive(Serialize, Deserialize)]
+pub struct StoredSession {
+    pub session: Option<Session>,
+    pub pending_login: Option<PendingLogin>,
+}
+
+pub struct StoredSessionKey {}
+
+impl typemap::Key for StoredSessionKey {
+    type Value = StoredSession;
+}
+
+/// Splits the stored session into its parts before handling a request and puts it back together
+/// afterwards, so that a pending login never counts as being logged in. Goes inside the session
+/// middleware.
+pub struct SessionSplitter;
+
+struct SessionSplitterHandler {
+    handler: Box<Handler>,
+}
+
+impl AroundMiddleware for SessionSplitter {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        Box::new(SessionSplitterHandler{handler: handler})
+    }
+}
+
+impl Handler for SessionSplitterHandler {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if let Some(stored_session) = req.extensions.remove::<StoredSessionKey>() {
+            if let Some(session) = stored_session.session {
+                req.extensions.insert::<SessionKey>(session);
+            }
+            if let Some(pending_login) = stored_session.pending_login {
+                req.extensions.insert::<PendingLoginKey>(pending_login);
+            }
+        }
+
+        let response = self.handler.handle(req);
+
+        let stored_session = StoredSession{
+            session: req.extensions.remove::<SessionKey>(),
+            pending_login: req.extensions.remove::<PendingLoginKey>(),
+        };
+        if stored_session.session.is_some() || stored_session.pending_login.is_some() {
+            req.extensions.insert::<StoredSessionKey>(stored_session);
+        }
+        response
+    }
+}
@@ -0,0 +1,255 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::env;
+use std::fs::File;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::Future;
+use futures::Stream;
+use hyper::Client;
+use hyper::StatusCode;
+use hyper::client::HttpConnector;
+use hyper;
+use serde;
+use serde_json;
+use tokio_core::reactor::Core;
+use tokio_core::reactor::Timeout;
+
+use errors::Error;
+use errors::ErrorKind;
+use errors::ResultExt;
+use secrets::get_secrets;
+
+pub const API_BASE_URL: &'static str = "http://api.steampowered.com";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_millis(1000);
+const MAX_ATTEMPTS: u32 = 4;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Anything that can answer Steam Web API calls.
+///
+/// `method` is the path of the API method, e.g. `IPlayerService/GetOwnedGames/v0001`. `params`
+/// shouldn't include the API key or format, implementations take care of those.
+pub trait SteamClient {
+    fn get(&self, method: &str, params: &[(&str, String)]) -> Result<Vec<u8>, Error>;
+}
+
+/// Calls a Steam Web API method and parses the JSON response.
+pub fn get_json<T, C>(client: &C, method: &str, params: &[(&str, String)]) -> Result<T, Error>
+    where C: SteamClient + ?Sized, for<'a> T: serde::Deserialize<'a>
+{
+    let body = client.get(method, params)?;
+    serde_json::from_slice(&body).chain_err(|| format!("unable to parse json from {}", method))
+}
+
+/// Picks a client based on the environment.
+///
+/// `GAMELOG_STEAM_FIXTURES` replays responses from a fixture directory instead of talking to
+/// Steam, and `GAMELOG_STEAM_RECORD` saves every real response into a fixture directory.
+pub fn from_env() -> Result<Box<SteamClient>, Error> {
+    if let Ok(fixture_dir) = env::var("GAMELOG_STEAM_FIXTURES") {
+        return Ok(Box::new(FixtureSteamClient::new(fixture_dir)));
+    }
+
+    let secrets = get_secrets()?;
+    let http_client = HttpSteamClient::new(secrets.steam_api_key)?;
+    match env::var("GAMELOG_STEAM_RECORD") {
+        Ok(record_dir) => Ok(Box::new(RecordingSteamClient::new(http_client, record_dir))),
+        Err(_) => Ok(Box::new(http_client)),
+    }
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Name of the fixture file for a call, e.g.
+/// `IPlayerService.GetOwnedGames/include_appinfo=1&steamid=76561197960287930.json`.
+fn fixture_path(dir: &PathBuf, method: &str, params: &[(&str, String)]) -> PathBuf {
+    let mut sorted_params: Vec<String> = params.iter().map(
+        |&(name, ref value)| format!("{}={}", name, percent_encode(value))
+    ).collect();
+    sorted_params.sort();
+    let query = if sorted_params.is_empty() { "index".to_string() } else { sorted_params.join("&") };
+
+    let method_without_version: Vec<&str> = method.split('/').take(2).collect();
+    dir.join(method_without_version.join(".")).join(format!("{}.json", query))
+}
+
+/// Talks to the real Steam Web API over HTTP.
+///
+/// Keeps one reactor for its whole lifetime, spaces requests out by `MIN_REQUEST_INTERVAL`,
+/// times out slow requests and retries timeouts, throttling and server errors with exponential
+/// backoff.
+pub struct HttpSteamClient {
+    api_key: String,
+    core: RefCell<Core>,
+    client: Client<HttpConnector>,
+    last_request: Cell<Option<Instant>>,
+}
+
+enum Attempt {
+    Done(Vec<u8>),
+    Retry(Error),
+}
+
+impl HttpSteamClient {
+    pub fn new(api_key: String) -> Result<HttpSteamClient, Error> {
+        let core = Core::new().chain_err(|| "unable to intialize tokio core")?;
+        let client = Client::new(&core.handle());
+        Ok(HttpSteamClient{
+            api_key: api_key,
+            core: RefCell::new(core),
+            client: client,
+            last_request: Cell::new(None),
+        })
+    }
+
+    fn wait_for_rate_limit(&self) {
+        if let Some(last_request) = self.last_request.get() {
+            let elapsed = last_request.elapsed();
+            if elapsed < MIN_REQUEST_INTERVAL {
+                thread::sleep(MIN_REQUEST_INTERVAL - elapsed);
+            }
+        }
+        self.last_request.set(Some(Instant::now()));
+    }
+
+    fn attempt(&self, url: &str, steam_id: Option<&str>) -> Result<Attempt, Error> {
+        self.wait_for_rate_limit();
+
+        let mut core = self.core.borrow_mut();
+        let uri: hyper::Uri = url.parse().chain_err(|| "unable to parse url")?;
+        let request = self.client.get(uri).and_then(|response| {
+            let status = response.status();
+            response.body().concat2().map(move |body| Some((status, body.to_vec())))
+        }).map_err(|error| error.to_string());
+        let timeout = Timeout::new(REQUEST_TIMEOUT, &core.handle())
+            .chain_err(|| "unable to create request timeout")?
+            .map(|_| None)
+            .map_err(|error| error.to_string());
+
+        let outcome = core.run(
+            request.select(timeout).map(|(outcome, _)| outcome).map_err(|(error, _)| error)
+        );
+
+        Ok(match outcome {
+            Err(error) => Attempt::Retry(format!("request to Steam failed: {}", error).into()),
+            Ok(None) => Attempt::Retry("request to Steam timed out".into()),
+            Ok(Some((status, body))) => match status {
+                StatusCode::Unauthorized => return Err(ErrorKind::SteamInvalidKey.into()),
+                StatusCode::Forbidden => match steam_id {
+                    Some(steam_id) => return Err(ErrorKind::SteamPrivateProfile(steam_id.to_string()).into()),
+                    None => return Err(format!("Steam returned {}", status).into()),
+                },
+                StatusCode::TooManyRequests => Attempt::Retry(ErrorKind::SteamThrottled.into()),
+                status if status.is_server_error() => Attempt::Retry(
+                    format!("Steam returned {}", status).into()
+                ),
+                status if status.is_success() => Attempt::Done(body),
+                status => return Err(format!("Steam returned {}", status).into()),
+            },
+        })
+    }
+}
+
+impl SteamClient for HttpSteamClient {
+    fn get(&self, method: &str, params: &[(&str, String)]) -> Result<Vec<u8>, Error> {
+        // Steam answers 403 when the profile being asked about is private.
+        let steam_id = params.iter().find(|&&(name, _)| name == "steamid").map(|&(_, ref value)| value.as_str());
+        let mut url = format!("{}/{}/?key={}&format=json", API_BASE_URL, method, self.api_key);
+        for &(name, ref value) in params {
+            url.push_str(&format!("&{}={}", name, percent_encode(value)));
+        }
+
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            match self.attempt(&url, steam_id)? {
+                Attempt::Done(body) => return Ok(body),
+                Attempt::Retry(error) => {
+                    if attempts >= MAX_ATTEMPTS {
+                        // Typed errors like `SteamThrottled` are returned as they are so callers
+                        // can still match on them.
+                        return match *error.kind() {
+                            ErrorKind::Msg(_) => Err(error).chain_err(
+                                || format!("giving up on {} after {} attempts", method, attempts)
+                            ),
+                            _ => Err(error),
+                        };
+                    }
+                    thread::sleep(backoff);
+                    backoff = backoff * 2;
+                },
+            }
+        }
+    }
+}
+
+/// Replays responses previously saved by `RecordingSteamClient` so syncing can run offline.
+pub struct FixtureSteamClient {
+    dir: PathBuf,
+}
+
+impl FixtureSteamClient {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FixtureSteamClient {
+        FixtureSteamClient{dir: dir.into()}
+    }
+}
+
+impl SteamClient for FixtureSteamClient {
+    fn get(&self, method: &str, params: &[(&str, String)]) -> Result<Vec<u8>, Error> {
+        let path = fixture_path(&self.dir, method, params);
+        let mut body = Vec::new();
+        File::open(&path).and_then(
+            |mut fd| fd.read_to_end(&mut body)
+        ).chain_err(|| format!("no fixture for {} at {}", method, path.display()))?;
+        Ok(body)
+    }
+}
+
+/// Passes calls through to another client, saving each response as a fixture.
+pub struct RecordingSteamClient<C: SteamClient> {
+    inner: C,
+    dir: PathBuf,
+}
+
+impl<C: SteamClient> RecordingSteamClient<C> {
+    pub fn new<P: Into<PathBuf>>(inner: C, dir: P) -> RecordingSteamClient<C> {
+        RecordingSteamClient{
+            inner: inner,
+            dir: dir.into(),
+        }
+    }
+}
+
+impl<C: SteamClient> SteamClient for RecordingSteamClient<C> {
+    fn get(&self, method: &str, params: &[(&str, String)]) -> Result<Vec<u8>, Error> {
+        let body = self.inner.get(method, params)?;
+
+        let path = fixture_path(&self.dir, method, params);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).chain_err(|| "unable to create fixture directory")?;
+        }
+        File::create(&path).and_then(
+            |mut fd| fd.write_all(&body)
+        ).chain_err(|| format!("unable to record fixture at {}", path.display()))?;
+
+        Ok(body)
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+
+use errors::Error;
+use errors::ErrorKind;
+use errors::ResultExt;
+use model;
+
+pub mod client;
+
+use self::client::SteamClient;
+use self::client::get_json;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Game {
+    pub(crate) appid: u64,
+    pub(crate) name: String,
+    img_icon_url: String,
+    img_logo_url: String,
+    pub(crate) has_community_visible_stats: Option<bool>,
+    pub(crate) playtime_forever: u64,
+    playtime_2weeks: Option<u64>,
+}
+
+// Steam leaves out everything but an empty object when the profile's game details are private
+#[derive(Serialize, Deserialize)]
+struct OwnedGames {
+    game_count: Option<u64>,
+    games: Option<Vec<Game>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OwnedGamesResponse {
+    response: OwnedGames
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RecentlyPlayedGame {
+    pub(crate) appid: u64,
+    pub(crate) name: String,
+    pub(crate) playtime_2weeks: u64,
+    pub(crate) playtime_forever: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecentlyPlayedGames {
+    total_count: Option<u64>,
+    games: Option<Vec<RecentlyPlayedGame>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecentlyPlayedGamesResponse {
+    response: RecentlyPlayedGames,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResolvedVanityUrl {
+    success: u64,
+    steamid: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResolveVanityUrlResponse {
+    response: ResolvedVanityUrl
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayerAchievement {
+    apiname: String,
+    achieved: u64,
+    unlocktime: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayerStats {
+    success: bool,
+    achievements: Option<Vec<PlayerAchievement>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayerAchievementsResponse {
+    playerstats: PlayerStats,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SchemaAchievement {
+    name: String,
+    #[serde(rename = "displayName")]
+    display_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AvailableGameStats {
+    achievements: Option<Vec<SchemaAchievement>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GameSchema {
+    #[serde(rename = "availableGameStats")]
+    available_game_stats: Option<AvailableGameStats>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GameSchemaResponse {
+    game: GameSchema,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WishlistItem {
+    appid: u64,
+    date_added: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Wishlist {
+    items: Option<Vec<WishlistItem>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WishlistResponse {
+    response: Wishlist,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoreItem {
+    appid: Option<u64>,
+    name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoreItems {
+    store_items: Option<Vec<StoreItem>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoreItemsResponse {
+    response: StoreItems,
+}
+
+// IStoreBrowseService/GetItems only takes so many ids per call
+const STORE_ITEMS_PER_REQUEST: usize = 100;
+
+// upper 32 bits of a SteamID64 for an individual account in the public universe
+const INDIVIDUAL_STEAM_ID64_PREFIX: u64 = 0x0110_0001;
+
+fn parse_steam_id64(candidate: &str) -> Option<u64> {
+    match candidate.parse::<u64>() {
+        Ok(steam_id) if steam_id >> 32 == INDIVIDUAL_STEAM_ID64_PREFIX => Some(steam_id),
+        _ => None,
+    }
+}
+
+fn is_valid_vanity_name(candidate: &str) -> bool {
+    candidate.len() >= 2 && candidate.len() <= 32 && candidate.chars().all(|c| {
+        c.is_ascii_alphanumeric() || c == '_' || c == '-'
+    })
+}
+
+fn resolve_vanity_name(client: &SteamClient, vanity_name: &str) -> Result<u64, Error> {
+    if !is_valid_vanity_name(vanity_name) {
+        return Err(format!("\"{}\" is not a valid Steam custom URL name", vanity_name).into());
+    }
+
+    let resolve_response: ResolveVanityUrlResponse = get_json(
+        client,
+        "ISteamUser/ResolveVanityURL/v0001",
+        &[("vanityurl", vanity_name.to_string())],
+    ).chain_err(|| "unable to reach Steam to look up your custom URL")?;
+
+    let resolved = resolve_response.response;
+    match (resolved.success, resolved.steamid) {
+        (1, Some(steam_id)) => parse_steam_id64(&steam_id).ok_or_else(
+            || format!("Steam returned an invalid SteamID64 for \"{}\"", vanity_name).into()
+        ),
+        _ => Err(format!("no Steam profile found with the custom URL \"{}\"", vanity_name).into()),
+    }
+}
+
+/// Turns whatever was typed into the Steam ID setting into a canonical SteamID64.
+///
+/// Accepts a SteamID64, a `steamcommunity.com/profiles/<id>` or `steamcommunity.com/id/<vanity>`
+/// URL, or a bare custom URL name, which gets resolved through ResolveVanityURL.
+pub fn resolve_steam_id(client: &SteamClient, input: &str) -> Result<u64, Error> {
+    let input = input.trim();
+
+    if let Some(steam_id) = parse_steam_id64(input) {
+        return Ok(steam_id);
+    }
+
+    let without_scheme = input.trim_left_matches("https://").trim_left_matches("http://");
+    let without_www = without_scheme.trim_left_matches("www.");
+    if without_www.starts_with("steamcommunity.com/") {
+        let mut path = without_www["steamcommunity.com/".len()..].split('/').filter(|segment| !segment.is_empty());
+        return match (path.next(), path.next()) {
+            (Some("profiles"), Some(steam_id)) => parse_steam_id64(steam_id).ok_or_else(
+                || format!("\"{}\" is not a valid SteamID64", steam_id).into()
+            ),
+            (Some("id"), Some(vanity_name)) => resolve_vanity_name(client, vanity_name),
+            _ => Err(format!("\"{}\" is not a Steam profile URL", input).into()),
+        };
+    }
+
+    if input.contains('/') {
+        return Err(format!("\"{}\" is not a Steam profile URL", input).into());
+    }
+
+    resolve_vanity_name(client, input)
+}
+
+pub(crate) fn get_owned_games(client: &SteamClient, steam_id: &str) -> Result<Vec<Game>, Error> {
+    let owned_games_response: OwnedGamesResponse = get_json(
+        client,
+        "IPlayerService/GetOwnedGames/v0001",
+        &[
+            ("steamid", steam_id.to_string()),
+            ("include_appinfo", "1".to_string()),
+        ],
+    )?;
+
+    match owned_games_response.response {
+        OwnedGames{games: Some(games), ..} => Ok(games),
+        OwnedGames{game_count: Some(_), games: None} => Ok(vec![]),
+        OwnedGames{game_count: None, games: None} => Err(
+            ErrorKind::SteamPrivateProfile(steam_id.to_string()).into()
+        ),
+    }
+}
+
+fn get_achievements(client: &SteamClient, steam_id: &str, appid: u64) -> Result<Vec<(SchemaAchievement, Option<i64>)>, Error> {
+    let schema_response: GameSchemaResponse = get_json(
+        client,
+        "ISteamUserStats/GetSchemaForGame/v2",
+        &[("appid", appid.to_string())],
+    )?;
+    let schema_achievements = match schema_response.game.available_game_stats {
+        Some(AvailableGameStats{achievements: Some(achievements)}) => achievements,
+        _ => return Ok(vec![]),
+    };
+
+    let player_response: PlayerAchievementsResponse = get_json(
+        client,
+        "ISteamUserStats/GetPlayerAchievements/v0001",
+        &[
+            ("steamid", steam_id.to_string()),
+            ("appid", appid.to_string()),
+        ],
+    )?;
+    let player_achievements = match player_response.playerstats {
+        PlayerStats{success: true, achievements: Some(achievements)} => achievements,
+        _ => vec![],
+    };
+
+    Ok(schema_achievements.into_iter().map(|schema_achievement| {
+        let unlock_date = player_achievements.iter().find(
+            |player_achievement| player_achievement.apiname == schema_achievement.name
+        ).and_then(|player_achievement| {
+            if player_achievement.achieved == 1 { Some(player_achievement.unlocktime as i64) } else { None }
+        });
+        (schema_achievement, unlock_date)
+    }).collect())
+}
+
+pub(crate) fn sync_achievements(client: &SteamClient, user_game_id: i64, steam_id: &str, appid: u64) -> Result<(), Error> {
+    let achievements = get_achievements(client, steam_id, appid)?;
+    if achievements.is_empty() {
+        return Ok(());
+    }
+
+    model::replace_user_game_achievements(
+        user_game_id,
+        achievements.into_iter().map(|(schema_achievement, unlock_date)| {
+            model::NewUserGameAchievement{
+                user_game_id: user_game_id,
+                api_name: schema_achievement.name,
+                name: schema_achievement.display_name,
+                unlock_date: unlock_date,
+            }
+        }).collect(),
+    )
+}
+
+fn get_wishlist(client: &SteamClient, steam_id: &str) -> Result<Vec<WishlistItem>, Error> {
+    let wishlist_response: WishlistResponse = get_json(
+        client,
+        "IWishlistService/GetWishlist/v1",
+        &[("steamid", steam_id.to_string())],
+    )?;
+
+    Ok(wishlist_response.response.items.unwrap_or_default())
+}
+
+fn get_app_names(client: &SteamClient, appids: &[u64]) -> Result<HashMap<u64, String>, Error> {
+    let mut names = HashMap::new();
+    for chunk in appids.chunks(STORE_ITEMS_PER_REQUEST) {
+        let ids: Vec<String> = chunk.iter().map(|appid| format!("{{\"appid\":{}}}", appid)).collect();
+        let input_json = format!(
+            "{{\"ids\":[{}],\"context\":{{\"language\":\"english\",\"country_code\":\"US\"}}}}",
+            ids.join(","),
+        );
+        let store_items_response: StoreItemsResponse = get_json(
+            client,
+            "IStoreBrowseService/GetItems/v1",
+            &[("input_json", input_json)],
+        )?;
+
+        for store_item in store_items_response.response.store_items.unwrap_or_default() {
+            if let (Some(appid), Some(name)) = (store_item.appid, store_item.name) {
+                names.insert(appid, name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+pub(crate) fn sync_wishlist(client: &SteamClient, user_id: i64, steam_id: &str) -> Result<(), Error> {
+    let wishlist = get_wishlist(client, steam_id)?;
+
+    let unknown_appids: Vec<u64> = wishlist.iter().map(
+        |item| item.appid
+    ).filter(
+        |appid| model::get_game_by_steam_id(*appid).is_err()
+    ).collect();
+    let names = get_app_names(client, &unknown_appids)?;
+
+    for item in wishlist {
+        let game_id = match model::get_game_by_steam_id(item.appid) {
+            Ok(game) => game.id,
+            Err(_) => match names.get(&item.appid) {
+                Some(name) => model::insert_game(
+                    model::NewGame{
+                        name: name.clone(),
+                        steam_id: Some(item.appid as i64),
+                    },
+                ).chain_err(|| "unable to insert game")?,
+                None => {
+                    eprintln!("skipping wishlisted app {}: Steam didn't return a name for it", item.appid);
+                    continue;
+                },
+            },
+        };
+
+        // games that are already in the collection don't belong on the wishlist
+        let user_game_id = model::get_user_game_by_user_id_and_game_id(user_id, game_id).ok().map(|user_game| user_game.id);
+        model::upsert_wishlist_game(
+            model::NewWishlistGame{
+                user_id: user_id,
+                game_id: game_id,
+                added_date: item.date_added,
+                user_game_id: user_game_id,
+            }
+        )?;
+    }
+    Ok(())
+}
+
+pub(crate) fn get_recently_played_games(client: &SteamClient, steam_id: &str) -> Result<Vec<RecentlyPlayedGame>, Error> {
+    let recently_played_response: RecentlyPlayedGamesResponse = get_json(
+        client,
+        "IPlayerService/GetRecentlyPlayedGames/v0001",
+        &[("steamid", steam_id.to_string())],
+    )?;
+
+    match recently_played_response.response {
+        RecentlyPlayedGames{games: Some(games), ..} => Ok(games),
+        RecentlyPlayedGames{total_count: Some(_), games: None} => Ok(vec![]),
+        RecentlyPlayedGames{total_count: None, games: None} => Err(
+            ErrorKind::SteamPrivateProfile(steam_id.to_string()).into()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::ErrorKind;
+    use model;
+
+    use super::client::FixtureSteamClient;
+    use super::*;
+
+    const STEAM_ID: &'static str = "76561197960287930";
+    const PRIVATE_STEAM_ID: &'static str = "76561197960265729";
+
+    fn fixture_client() -> FixtureSteamClient {
+        FixtureSteamClient::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/steam"))
+    }
+
+    fn add_user(username: &str) -> i64 {
+        model::signup(model::UserSignupInfo{
+            username: username.to_string(),
+            email: format!("{}@example.com", username),
+            password: "correct horse battery staple".to_string(),
+        }).unwrap()
+    }
+
+    fn add_user_game(user_id: i64, name: &str, appid: u64) -> i64 {
+        let game_id = model::insert_game(model::NewGame{
+            name: name.to_string(),
+            steam_id: Some(appid as i64),
+        }).unwrap();
+        model::add_user_game(model::NewUserGame{
+            game_id: game_id,
+            user_id: user_id,
+            play_state: "unplayed".to_string(),
+            platform: "win".to_string(),
+            acquisition_date: 1104537600,
+            start_date: None,
+            beat_date: None,
+        }).unwrap();
+        model::get_user_game_by_user_id_and_game_id(user_id, game_id).unwrap().id
+    }
+
+    #[test]
+    fn resolves_vanity_urls() {
+        let client = fixture_client();
+        assert_eq!(resolve_steam_id(&client, "gabelogannewell").unwrap(), 76561197960287930);
+        assert_eq!(
+            resolve_steam_id(&client, "https://steamcommunity.com/id/gabelogannewell/").unwrap(),
+            76561197960287930,
+        );
+        assert!(resolve_steam_id(&client, "nobody-by-this-name").is_err());
+    }
+
+    #[test]
+    fn private_profiles_have_no_owned_games() {
+        let client = fixture_client();
+        assert_eq!(get_owned_games(&client, STEAM_ID).unwrap().len(), 2);
+        match get_owned_games(&client, PRIVATE_STEAM_ID) {
+            Err(error) => match *error.kind() {
+                ErrorKind::SteamPrivateProfile(ref steam_id) => assert_eq!(steam_id, PRIVATE_STEAM_ID),
+                ref kind => panic!("expected a private profile, got {}", kind),
+            },
+            Ok(_) => panic!("expected a private profile"),
+        }
+    }
+
+    #[test]
+    fn sync_achievements_replaces_achievements() {
+        model::use_test_database("sync_achievements").unwrap();
+        let user_id = add_user("achiever");
+        let user_game_id = add_user_game(user_id, "Half-Life 2", 220);
+
+        // syncing twice shouldn't duplicate anything
+        sync_achievements(&fixture_client(), user_game_id, STEAM_ID, 220).unwrap();
+        sync_achievements(&fixture_client(), user_game_id, STEAM_ID, 220).unwrap();
+
+        let mut achievements = model::get_user_game_achievements(user_game_id).unwrap();
+        achievements.sort_by(|a, b| a.api_name.cmp(&b.api_name));
+        let rows: Vec<(&str, &str, Option<i64>)> = achievements.iter().map(
+            |achievement| (achievement.api_name.as_str(), achievement.name.as_str(), achievement.unlock_date)
+        ).collect();
+        assert_eq!(rows, vec![
+            ("HL2_HIT_CANCOP_WITHCAN", "Defiant", Some(1104537600)),
+            ("HL2_PUT_CANINTRASH", "Submissive", None),
+        ]);
+    }
+
+    #[test]
+    fn sync_wishlist_adds_unknown_games() {
+        model::use_test_database("sync_wishlist").unwrap();
+        let user_id = add_user("wisher");
+        let owned_user_game_id = add_user_game(user_id, "Half-Life 2", 220);
+
+        sync_wishlist(&fixture_client(), user_id, STEAM_ID).unwrap();
+
+        // Half-Life 2 is already owned and 999999 has no name, so only Half-Life is left
+        let wishlist: Vec<(String, i64)> = model::get_wishlist_games_with_names(user_id).unwrap().into_iter().map(
+            |(name, wishlist_game)| (name, wishlist_game.added_date)
+        ).collect();
+        assert_eq!(wishlist, vec![("Half-Life".to_string(), 1293840000)]);
+        assert_eq!(model::get_game_by_steam_id(70).unwrap().name, "Half-Life");
+        assert!(model::get_game_by_steam_id(999999).is_err());
+        assert_eq!(model::get_wishlist_date(owned_user_game_id).unwrap(), Some(1262304000));
+    }
+}
@@ -0,0 +1,221 @@
+use errors::Error;
+use errors::ErrorKind;
+use errors::ResultExt;
+use model;
+use time;
+
+pub mod steam;
+
+/// A game as a storefront reports it in a user's library.
+pub struct OwnedGame {
+    /// The storefront's own id for the game, e.g. a Steam appid.
+    pub external_id: String,
+    pub name: String,
+    /// Total playtime in minutes, for storefronts that track it.
+    pub playtime_forever: Option<i64>,
+}
+
+pub struct RecentlyPlayedGame {
+    pub game: OwnedGame,
+    /// Playtime in minutes over the last two weeks.
+    pub playtime_2weeks: i64,
+}
+
+/// Which optional stages to run on top of importing owned games.
+pub struct SyncOptions {
+    pub achievements: bool,
+    pub wishlist: bool,
+    pub recently_played: bool,
+}
+
+/// An external store that users can link an account from to import their library.
+pub trait StorefrontProvider {
+    /// What's stored in `storefront_account.provider` for this storefront.
+    fn slug(&self) -> &'static str;
+
+    /// Turns whatever the user typed in for their account into the canonical account id.
+    fn resolve_account_id(&self, input: &str) -> Result<String, Error>;
+
+    fn get_owned_games(&self, account_id: &str) -> Result<Vec<OwnedGame>, Error>;
+
+    fn get_recently_played_games(&self, _account_id: &str) -> Result<Vec<RecentlyPlayedGame>, Error> {
+        Ok(vec![])
+    }
+
+    /// Looks up the gamelog game for an owned game, by name unless the storefront knows better.
+    fn find_game(&self, game: &OwnedGame) -> Result<Option<model::Game>, Error> {
+        model::get_optional_game_by_name(&game.name)
+    }
+
+    /// Metadata for a game gamelog hasn't seen before.
+    fn new_game(&self, game: &OwnedGame) -> model::NewGame {
+        model::NewGame{
+            name: game.name.clone(),
+            steam_id: None,
+        }
+    }
+
+    /// Platform slug (see `config/platforms.json`) new collection entries get.
+    fn platform(&self, game: &OwnedGame) -> String;
+
+    /// Storefront specific stages, run after the owned games are in the collection.
+    fn sync_extras(
+        &self,
+        _user_id: i64,
+        _account_id: &str,
+        _user_games: &[(OwnedGame, i64)],
+        _options: &SyncOptions,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Every provider slug along with a name to show users.
+pub const PROVIDERS: &'static [(&'static str, &'static str)] = &[
+    ("steam", "Steam"),
+];
+
+pub fn get_provider(slug: &str) -> Result<Box<StorefrontProvider>, Error> {
+    match slug {
+        "steam" => Ok(Box::new(steam::SteamProvider::from_env()?)),
+        _ => Err(format!("unknown storefront \"{}\"", slug).into()),
+    }
+}
+
+fn find_or_insert_game(provider: &StorefrontProvider, game: &OwnedGame) -> Result<i64, Error> {
+    match provider.find_game(game)? {
+        Some(game_row) => Ok(game_row.id),
+        None => model::insert_game(provider.new_game(game)).chain_err(|| "unable to insert game"),
+    }
+}
+
+/// Makes sure an owned game is in the user's collection, and records its playtime.
+///
+/// Only brand new collection entries get a play state from here, after that the user is in charge.
+pub fn upsert_owned_game(
+    user_id: i64,
+    game_id: i64,
+    platform: String,
+    playtime_forever: Option<i64>,
+) -> Result<model::UserGame, Error> {
+    let user_game = match model::get_user_game_by_user_id_and_game_id(user_id, game_id) {
+        Ok(user_game) => user_game,
+        Err(_) => {
+            // games with playtime have been played, but only recent playtime says when, so they
+            // start out without a start date
+            let has_played = playtime_forever.map_or(false, |playtime_forever| playtime_forever > 0);
+            model::add_user_game(
+                model::NewUserGame{
+                    user_id: user_id,
+                    game_id: game_id,
+                    play_state: if has_played { "unfinished" } else { "unplayed" }.to_string(),
+                    platform: platform,
+                    acquisition_date: time::get_time().sec,
+                    start_date: None,
+                    beat_date: None,
+                }
+            ).chain_err(|| "unable to add game")?;
+            model::get_user_game_by_user_id_and_game_id(user_id, game_id)?
+        },
+    };
+
+    // total playtime doesn't say when the game was started, only recent playtime sets start dates
+    if let Some(playtime_forever) = playtime_forever {
+        model::update_total_playtime(user_game.id, playtime_forever)?;
+    }
+
+    Ok(user_game)
+}
+
+fn sync_recently_played(provider: &StorefrontProvider, account: &model::StorefrontAccount) -> Result<(), Error> {
+    let recently_played_games = provider.get_recently_played_games(&account.account_id)?;
+    let now = time::get_time().sec;
+
+    model::clear_recent_playtime(account.user_id)?;
+    for recently_played_game in recently_played_games {
+        let user_game = match provider.find_game(&recently_played_game.game)? {
+            Some(game) => match model::get_user_game_by_user_id_and_game_id(account.user_id, game.id) {
+                Ok(user_game) => user_game,
+                Err(_) => continue,
+            },
+            // played through family sharing or otherwise not in the collection
+            None => continue,
+        };
+
+        model::update_recent_playtime(
+            user_game.id,
+            recently_played_game.game.playtime_forever.unwrap_or(0),
+            recently_played_game.playtime_2weeks,
+            now,
+        )?;
+        model::mark_user_game_started(user_game.id, now)?;
+    }
+    Ok(())
+}
+
+fn sync_account(
+    provider: &StorefrontProvider,
+    options: &SyncOptions,
+    account: &model::StorefrontAccount,
+) -> Result<(), Error> {
+    let owned_games = provider.get_owned_games(&account.account_id)?;
+
+    let mut user_games = Vec::new();
+    for owned_game in owned_games {
+        let game_id = find_or_insert_game(provider, &owned_game)?;
+
+        let user_game = upsert_owned_game(
+            account.user_id,
+            game_id,
+            provider.platform(&owned_game),
+            owned_game.playtime_forever,
+        )?;
+        model::move_wishlist_game_to_collection(account.user_id, game_id, user_game.id)?;
+
+        user_games.push((owned_game, user_game.id));
+    }
+
+    if options.recently_played {
+        sync_recently_played(provider, account)?;
+    }
+
+    provider.sync_extras(account.user_id, &account.account_id, &user_games, options)
+}
+
+/// Imports every linked account's library, or only accounts from one storefront if `only` is set.
+///
+/// Storefronts that aren't configured (e.g. no Steam API key) are skipped.
+pub fn sync(options: &SyncOptions, only: Option<&str>) -> Result<(), Error> {
+    let accounts = model::get_all_storefront_accounts()?;
+
+    for &(slug, name) in PROVIDERS {
+        if only.map_or(false, |only_slug| only_slug != slug) {
+            continue;
+        }
+
+        let provider_accounts: Vec<&model::StorefrontAccount> = accounts.iter().filter(
+            |account| account.provider == slug
+        ).collect();
+        if provider_accounts.is_empty() {
+            continue;
+        }
+
+        let provider = match get_provider(slug) {
+            Ok(provider) => provider,
+            Err(error) => {
+                eprintln!("skipping {}, it isn't configured: {}", name, error);
+                continue;
+            },
+        };
+
+        for account in provider_accounts {
+            match sync_account(&*provider, options, account) {
+                Err(Error(ErrorKind::StorefrontAccountPrivate(_), _)) => {
+                    eprintln!("skipping user {}: {} account {} is private", account.user_id, name, account.account_id);
+                },
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
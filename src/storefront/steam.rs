@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use errors::Error;
+use errors::ErrorKind;
+use errors::ResultExt;
+use model;
+use steam::client::SteamClient;
+use steam::client;
+use steam;
+use super::OwnedGame;
+use super::RecentlyPlayedGame;
+use super::StorefrontProvider;
+use super::SyncOptions;
+
+// GetOwnedGames doesn't say which operating systems a game runs on, and almost every Steam game
+// runs on Windows, so that's where synced games go until the user says otherwise
+const DEFAULT_PLATFORM: &'static str = "win";
+
+pub struct SteamProvider {
+    client: Box<SteamClient>,
+    // filled in by get_owned_games so the achievements stage knows which games have any
+    appids_with_stats: RefCell<HashSet<u64>>,
+}
+
+impl SteamProvider {
+    pub fn from_env() -> Result<SteamProvider, Error> {
+        Ok(SteamProvider{
+            client: client::from_env()?,
+            appids_with_stats: RefCell::new(HashSet::new()),
+        })
+    }
+}
+
+// the generic sync only knows about storefront errors, so private profiles get translated
+fn map_private_profile(error: Error) -> Error {
+    let steam_id = match *error.kind() {
+        ErrorKind::SteamPrivateProfile(ref steam_id) => steam_id.clone(),
+        _ => return error,
+    };
+    Error::with_chain(error, ErrorKind::StorefrontAccountPrivate(steam_id))
+}
+
+fn parse_appid(external_id: &str) -> Result<u64, Error> {
+    external_id.parse().chain_err(|| format!("\"{}\" is not a Steam appid", external_id))
+}
+
+impl StorefrontProvider for SteamProvider {
+    fn slug(&self) -> &'static str {
+        "steam"
+    }
+
+    fn resolve_account_id(&self, input: &str) -> Result<String, Error> {
+        steam::resolve_steam_id(&*self.client, input).map(|steam_id| steam_id.to_string())
+    }
+
+    fn get_owned_games(&self, account_id: &str) -> Result<Vec<OwnedGame>, Error> {
+        let games = steam::get_owned_games(&*self.client, account_id).map_err(map_private_profile)?;
+
+        let mut appids_with_stats = self.appids_with_stats.borrow_mut();
+        Ok(games.into_iter().map(|game| {
+            if game.has_community_visible_stats == Some(true) {
+                appids_with_stats.insert(game.appid);
+            }
+            OwnedGame{
+                external_id: game.appid.to_string(),
+                name: game.name,
+                playtime_forever: Some(game.playtime_forever as i64),
+            }
+        }).collect())
+    }
+
+    fn get_recently_played_games(&self, account_id: &str) -> Result<Vec<RecentlyPlayedGame>, Error> {
+        let games = steam::get_recently_played_games(&*self.client, account_id).map_err(map_private_profile)?;
+        Ok(games.into_iter().map(|game| RecentlyPlayedGame{
+            game: OwnedGame{
+                external_id: game.appid.to_string(),
+                name: game.name,
+                playtime_forever: Some(game.playtime_forever as i64),
+            },
+            playtime_2weeks: game.playtime_2weeks as i64,
+        }).collect())
+    }
+
+    fn find_game(&self, game: &OwnedGame) -> Result<Option<model::Game>, Error> {
+        model::get_optional_game_by_steam_id(parse_appid(&game.external_id)?)
+    }
+
+    fn new_game(&self, game: &OwnedGame) -> model::NewGame {
+        model::NewGame{
+            name: game.name.clone(),
+            steam_id: game.external_id.parse::<i64>().ok(),
+        }
+    }
+
+    fn platform(&self, _game: &OwnedGame) -> String {
+        DEFAULT_PLATFORM.to_string()
+    }
+
+    fn sync_extras(
+        &self,
+        user_id: i64,
+        account_id: &str,
+        user_games: &[(OwnedGame, i64)],
+        options: &SyncOptions,
+    ) -> Result<(), Error> {
+        if options.achievements {
+            let appids_with_stats = self.appids_with_stats.borrow();
+            for &(ref game, user_game_id) in user_games {
+                let appid = parse_appid(&game.external_id)?;
+                if !appids_with_stats.contains(&appid) {
+                    continue;
+                }
+                if let Err(error) = steam::sync_achievements(&*self.client, user_game_id, account_id, appid) {
+                    match *error.kind() {
+                        ErrorKind::SteamInvalidKey | ErrorKind::SteamThrottled => return Err(error),
+                        _ => eprintln!("unable to sync achievements for {}: {}", game.name, error),
+                    }
+                }
+            }
+        }
+
+        if options.wishlist {
+            steam::sync_wishlist(&*self.client, user_id, account_id).map_err(map_private_profile)?;
+        }
+        Ok(())
+    }
+}
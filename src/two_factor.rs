@@ -0,0 +1,148 @@
+use std::fmt::Write;
+
+use base32;
+use hmac::Hmac;
+use hmac::Mac;
+use qrcode::Color;
+use qrcode::QrCode;
+use rand::OsRng;
+use rand::Rng;
+use sha1::Sha1;
+
+use errors::Error;
+use errors::ResultExt;
+
+const SECRET_LENGTH: usize = 20;
+// how many seconds each code lasts, and how many digits it has, as authenticator apps expect
+const STEP_SECONDS: i64 = 30;
+const DIGITS: u32 = 6;
+// codes from this many steps either side of now are accepted, for clocks that are a little off
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+const RECOVERY_CODE_CHARACTERS: &'static [u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+// size of each QR code module in pixels, and the blank border around the code in modules
+const QR_MODULE_SIZE: usize = 4;
+const QR_QUIET_ZONE: usize = 4;
+
+const BASE32: base32::Alphabet = base32::Alphabet::RFC4648{padding: false};
+
+/// A new random secret, base32 encoded the way authenticator apps take it.
+pub fn generate_secret() -> Result<String, Error> {
+    let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
+    let mut secret: Vec<u8> = vec![0; SECRET_LENGTH];
+    rng.fill_bytes(&mut secret);
+    Ok(base32::encode(BASE32, &secret))
+}
+
+/// The `otpauth://` URI that goes in the enrollment QR code.
+pub fn get_provisioning_uri(secret: &str, username: &str) -> String {
+    format!(
+        "otpauth://totp/gamelog:{}?secret={}&issuer=gamelog&digits={}&period={}",
+        percent_encode(username),
+        secret,
+        DIGITS,
+        STEP_SECONDS,
+    )
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::new();
+    for &byte in text.as_bytes() {
+        if (byte as char).is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'.' {
+            encoded.push(byte as char);
+        } else {
+            write!(encoded, "%{:02X}", byte).unwrap();
+        }
+    }
+    encoded
+}
+
+/// The code for one time step, per RFC 6238.
+fn get_code(secret: &[u8], step: i64) -> Result<u32, Error> {
+    let mut mac = Hmac::<Sha1>::new_varkey(secret).map_err(|_| Error::from("invalid secret"))?;
+    let mut counter = [0; 8];
+    for (index, byte) in counter.iter_mut().enumerate() {
+        *byte = (step >> (8 * (7 - index))) as u8;
+    }
+    mac.input(&counter);
+    let hash = mac.result().code();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let truncated = ((hash[offset] as u32 & 0x7f) << 24) |
+        ((hash[offset + 1] as u32) << 16) |
+        ((hash[offset + 2] as u32) << 8) |
+        (hash[offset + 3] as u32);
+    Ok(truncated % 10u32.pow(DIGITS))
+}
+
+/// Checks a code against a secret, returning the time step it's for if it's right. Codes for
+/// steps up to `last_used_step` are turned down, so each one only works once.
+pub fn verify_code(secret: &str, code: &str, now: i64, last_used_step: Option<i64>) -> Result<Option<i64>, Error> {
+    let secret = base32::decode(BASE32, secret).ok_or::<Error>("invalid secret".into())?;
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return Ok(None);
+    }
+    let code = match code.parse::<u32>() {
+        Ok(code) => code,
+        Err(_) => return Ok(None),
+    };
+
+    let current_step = now / STEP_SECONDS;
+    for step in (current_step - ALLOWED_DRIFT_STEPS)..(current_step + ALLOWED_DRIFT_STEPS + 1) {
+        if last_used_step.map(|last_used_step| step <= last_used_step).unwrap_or(false) {
+            continue;
+        }
+        if get_code(&secret, step)? == code {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// A fresh set of one-time recovery codes, for when the authenticator isn't around.
+pub fn generate_recovery_codes() -> Result<Vec<String>, Error> {
+    let mut rng = OsRng::new().chain_err(|| "unable to create rng")?;
+    Ok((0..RECOVERY_CODE_COUNT).map(|_| {
+        let characters: String = (0..RECOVERY_CODE_LENGTH).map(
+            |_| RECOVERY_CODE_CHARACTERS[rng.gen_range(0, RECOVERY_CODE_CHARACTERS.len())] as char
+        ).collect();
+        // split in half so they're easier to copy down
+        format!("{}-{}", &characters[..RECOVERY_CODE_LENGTH / 2], &characters[RECOVERY_CODE_LENGTH / 2..])
+    }).collect())
+}
+
+/// Puts recovery codes into the form they're stored in, so they match however they were typed.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+/// A QR code for `data` as an SVG, one square per dark module.
+pub fn qr_code_svg(data: &str) -> Result<String, Error> {
+    let code = QrCode::new(data.as_bytes()).map_err(|_| Error::from("unable to make QR code"))?;
+    let width = code.width();
+    let size = (width + 2 * QR_QUIET_ZONE) * QR_MODULE_SIZE;
+
+    let mut svg = String::new();
+    write!(
+        svg,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{0}" viewBox="0 0 {0} {0}"><rect width="{0}" height="{0}" fill="#fff"/>"##,
+        size,
+    ).unwrap();
+    for (index, &color) in code.to_colors().iter().enumerate() {
+        if color == Color::Dark {
+            write!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{2}" height="{2}"/>"#,
+                (index % width + QR_QUIET_ZONE) * QR_MODULE_SIZE,
+                (index / width + QR_QUIET_ZONE) * QR_MODULE_SIZE,
+                QR_MODULE_SIZE,
+            ).unwrap();
+        }
+    }
+    svg.push_str("</svg>");
+    Ok(svg)
+}
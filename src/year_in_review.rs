@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use askama::Template;
+use time;
+
+use errors::Error;
+use errors::ResultExt;
+use helpers::format_date;
+use helpers::get_platforms;
+use model;
+
+/// How many of the most played games a review lists.
+const MOST_PLAYED_COUNT: usize = 5;
+
+/// What a user did in one calendar year (UTC), from their collection's dates and play state
+/// history.
+pub struct YearInReview {
+    pub username: String,
+    pub user_id: i64,
+    pub year: i32,
+    pub started: Vec<ReviewedGame>,
+    pub beaten: Vec<ReviewedGame>,
+    pub abandoned: Vec<ReviewedGame>,
+    /// Games played during the year with the most playtime, which storefronts only report as a
+    /// lifetime total.
+    pub most_played: Vec<ReviewedGame>,
+    pub platforms: Vec<PlatformCount>,
+    /// The beaten game that had been waiting in the collection the longest, if any were beaten.
+    pub longest_held: Vec<ReviewedGame>,
+}
+
+pub struct ReviewedGame {
+    pub name: String,
+    pub platform: String,
+    pub date: String,
+    pub detail: String,
+}
+
+pub struct PlatformCount {
+    pub name: String,
+    pub count: usize,
+}
+
+/// A review as a single page that doesn't need the webapp to look at.
+#[derive(Template)]
+#[template(path = "year_in_review_export.html")]
+struct YearInReviewExportTemplate<'a> {
+    review: &'a YearInReview,
+}
+
+fn get_year_start(year: i32) -> Result<i64, Error> {
+    let tm = time::strptime(&format!("{}-01-01", year), "%Y-%m-%d").chain_err(|| "invalid year")?;
+    Ok(tm.to_timespec().sec)
+}
+
+/// The first date in the year a game's history reached one of `play_states`.
+fn find_history_date(history: &[&model::UserGameHistory], play_states: &[&str], year_start: i64, year_end: i64) -> Option<i64> {
+    history.iter().find(
+        |entry| play_states.contains(&entry.play_state.as_str()) && entry.date >= year_start && entry.date < year_end
+    ).map(|entry| entry.date)
+}
+
+pub fn year_in_review(user: &model::User, year: i32) -> Result<YearInReview, Error> {
+    let year_start = get_year_start(year)?;
+    let year_end = get_year_start(year + 1)?;
+    let in_year = |date: i64| date >= year_start && date < year_end;
+
+    let user_games = model::get_user_games_with_names(user.id)?;
+    let history = model::get_user_game_history(user.id)?;
+    let mut history_by_user_game: HashMap<i64, Vec<&model::UserGameHistory>> = HashMap::new();
+    for entry in &history {
+        history_by_user_game.entry(entry.user_game_id).or_insert_with(Vec::new).push(entry);
+    }
+    let playtimes: HashMap<i64, model::UserGamePlaytime> = model::get_user_game_playtimes(user.id)?.into_iter().map(
+        |playtime| (playtime.user_game_id, playtime)
+    ).collect();
+    let platform_names: HashMap<String, String> = get_platforms()?.into_iter().map(
+        |platform| (platform.slug, platform.name)
+    ).collect();
+
+    let mut started = vec![];
+    let mut beaten = vec![];
+    let mut abandoned = vec![];
+    let mut played = vec![];
+    let mut platform_counts: BTreeMap<String, usize> = BTreeMap::new();
+    let mut longest_held: Option<(i64, ReviewedGame)> = None;
+    let no_history = vec![];
+
+    for &(ref name, ref user_game) in &user_games {
+        let history = history_by_user_game.get(&user_game.id).unwrap_or(&no_history);
+        let platform = platform_names.get(&user_game.platform).cloned().unwrap_or(user_game.platform.clone());
+        let reviewed_game = |date: i64, detail: String| ReviewedGame{
+            name: name.clone(),
+            platform: platform.clone(),
+            date: format_date(date),
+            detail: detail,
+        };
+
+        let start_date = user_game.start_date.into_iter().find(|&date| in_year(date)).or_else(
+            || find_history_date(history, &["unfinished"], year_start, year_end)
+        );
+        let beat_date = user_game.beat_date.into_iter().find(|&date| in_year(date)).or_else(
+            || find_history_date(history, &["beaten", "completed", "100_percent"], year_start, year_end)
+        );
+        let abandon_date = find_history_date(history, &["wont_beat"], year_start, year_end);
+        let playtime = playtimes.get(&user_game.id);
+        let last_played_date = playtime.and_then(|playtime| playtime.last_played_date).into_iter().find(|&date| in_year(date));
+
+        if let Some(date) = start_date {
+            started.push((date, reviewed_game(date, "".to_string())));
+        }
+        if let Some(date) = beat_date {
+            let held_days = (date - user_game.acquisition_date) / 86400;
+            let is_longest = longest_held.as_ref().map(|&(longest, _)| held_days > longest).unwrap_or(true);
+            if is_longest {
+                longest_held = Some((held_days, reviewed_game(date, format!("after {} days in the collection", held_days))));
+            }
+            beaten.push((date, reviewed_game(date, "".to_string())));
+        }
+        if let Some(date) = abandon_date {
+            abandoned.push((date, reviewed_game(date, "".to_string())));
+        }
+
+        let activity_dates: Vec<i64> = start_date.into_iter().chain(beat_date).chain(abandon_date).chain(last_played_date).collect();
+        if let Some(&date) = activity_dates.iter().max() {
+            *platform_counts.entry(platform.clone()).or_insert(0) += 1;
+            if let Some(playtime) = playtime {
+                if playtime.playtime_forever > 0 {
+                    played.push((
+                        playtime.playtime_forever,
+                        reviewed_game(date, format!("{:.1} hours", playtime.playtime_forever as f64 / 60.0)),
+                    ));
+                }
+            }
+        }
+    }
+
+    started.sort_by_key(|&(date, _)| date);
+    beaten.sort_by_key(|&(date, _)| date);
+    abandoned.sort_by_key(|&(date, _)| date);
+    played.sort_by(|a, b| b.0.cmp(&a.0));
+    let mut platforms: Vec<PlatformCount> = platform_counts.into_iter().map(
+        |(name, count)| PlatformCount{name: name, count: count}
+    ).collect();
+    platforms.sort_by(|a, b| b.count.cmp(&a.count));
+
+    Ok(YearInReview{
+        username: user.username.clone(),
+        user_id: user.id,
+        year: year,
+        started: started.into_iter().map(|(_, game)| game).collect(),
+        beaten: beaten.into_iter().map(|(_, game)| game).collect(),
+        abandoned: abandoned.into_iter().map(|(_, game)| game).collect(),
+        most_played: played.into_iter().take(MOST_PLAYED_COUNT).map(|(_, game)| game).collect(),
+        platforms: platforms,
+        longest_held: longest_held.into_iter().map(|(_, game)| game).collect(),
+    })
+}
+
+pub fn render_export(review: &YearInReview) -> Result<String, Error> {
+    YearInReviewExportTemplate{review: review}.render().chain_err(|| "unable to render year in review")
+}